@@ -3,24 +3,34 @@
 // This program can be used to query the state of the terrarium, configure it's
 // schedule, and control its lights, mist, and fans.
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow};
 use clap::{Parser, Subcommand};
 use itertools::Itertools;
 use mdns_sd::{ServiceDaemon, ServiceEvent};
 use regex::Regex;
 use reqwest::StatusCode;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 use std::time::Duration;
-use terralib::config::TerrariumConfigUpdate;
+use terralib::config::diff::{ConfigChange, DryRunResult};
+use terralib::config::history::HistoryEntrySummary;
+use terralib::config::validation::ValidationReport;
+use terralib::config::{
+    ProfileRename, SchedulePreview, ScheduleUpdate, TerrariumConfig, TerrariumConfigUpdate, Update,
+};
+use terralib::events::Event;
 use terralib::terrarium::print_terrarium_state;
 use terralib::types::{
-    Actuator, ActuatorOverride, ActuatorOverrideSet, ActuatorValue, TerrariumState,
+    Actuator, ActuatorOverride, ActuatorOverrideSet, ActuatorValue, OverrideHold, OverrideSequence,
+    SequenceStep, TerrariumState,
 };
 
+mod simulate;
+
 #[derive(Parser, Debug)]
 #[command(about = "Oasis terrarium command-line client")]
 struct Args {
@@ -35,8 +45,22 @@ struct Args {
 enum Commands {
     /// Control the lights, fans, and mister.
     Ctl {
-        #[arg(help = "A list of actuator commands of the form <actuator>@<value>:<duration>")]
+        #[arg(
+            help = "A list of actuator commands of the form <actuator>@<value>:<duration>. The duration can also be 'next', to hold until the schedule next turns the actuator on or off, or 'manual', to hold until cleared. Separate steps with 'then' to run them one after another, and use 'wait:<secs>' to delay a step"
+        )]
         overrides: Vec<String>,
+        #[arg(
+            long,
+            conflicts_with = "overrides",
+            help = "Cancel the running sequence of steps"
+        )]
+        cancel: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["overrides", "cancel"],
+            help = "Clear the overrides of the given actuators (m, l, or f), or 'all' to clear every override"
+        )]
+        clear: Vec<String>,
     },
     /// Get the temperature and humidity of the terrarium, as well as the current state of the lights, fans, and mister.
     State {
@@ -47,8 +71,60 @@ enum Commands {
     Config {
         #[arg(long)]
         config_json: Option<String>,
-        #[arg(long)]
+        #[arg(
+            long,
+            help = "Config update to send. Files ending in .toml are read as toml, anything else as json"
+        )]
         config_file: Option<String>,
+        #[arg(
+            long,
+            value_enum,
+            default_value = "json",
+            help = "Format to print the current config in"
+        )]
+        format: ConfigFormat,
+        #[arg(
+            long,
+            help = "Show what the config update would change, without applying it"
+        )]
+        dry_run: bool,
+    },
+    /// Inspect the terrarium's schedule.
+    Schedule {
+        #[command(subcommand)]
+        command: ScheduleCommands,
+    },
+    /// List, switch between, and manage named schedule profiles.
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommands,
+    },
+    /// List recent configs and roll back to one of them.
+    History {
+        #[command(subcommand)]
+        command: HistoryCommands,
+    },
+    /// List recent things the terrarium did on its own, such as dimming the lights because it got too hot.
+    Events,
+    /// Simulate a day of a config's schedule without a terrarium. The config file has the same format as for `config --config-file`, and is applied on top of the defaults that a new terrarium starts with.
+    Simulate {
+        config_file: String,
+        #[arg(long, help = "Day to simulate (YYYY-MM-DD). Defaults to today")]
+        date: Option<jiff::civil::Date>,
+        #[arg(long, help = "If true, output is printed in csv format")]
+        csv: bool,
+        #[arg(long, help = "Seconds between csv rows", default_value = "60")]
+        csv_step: usize,
+    },
+    /// Print the JSON Schema for a type accepted or returned by the terrarium's api, for validating config files in an editor. Without a name, prints all of them keyed by name.
+    Schema {
+        #[arg(value_parser = terralib::schema::NAMES)]
+        name: Option<String>,
+        #[arg(
+            long,
+            help = "Instead of printing, write each schema to <dir>/<name>.schema.json"
+        )]
+        out_dir: Option<String>,
     },
     /// Scan the local network for online terrariums.
     Scan {
@@ -57,6 +133,51 @@ enum Commands {
     },
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+#[derive(Subcommand, Debug)]
+enum ScheduleCommands {
+    /// Print a timeline of when the lights, fans, and mister are scheduled to turn on and off.
+    Preview {
+        #[arg(long, help = "How many hours ahead to look", default_value = "24")]
+        hours: u32,
+        #[arg(long, help = "If true, output is printed in json format")]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCommands {
+    /// List profiles. The active one is marked with a '*'.
+    List,
+    /// Switch to running a different profile's schedule.
+    Activate { name: String },
+    /// Create a new profile with an empty schedule, or a copy of another profile's schedule.
+    Create {
+        name: String,
+        #[arg(long, help = "Profile to copy the schedule from")]
+        copy_from: Option<String>,
+        #[arg(long, help = "If true, also switch to the new profile")]
+        activate: bool,
+    },
+    /// Rename a profile.
+    Rename { from: String, to: String },
+    /// Delete a profile. The active profile can't be deleted.
+    Delete { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommands {
+    /// List the configs the terrarium has kept, oldest first. The current one is marked with a '*'.
+    List,
+    /// Go back to the config with the given id.
+    Rollback { id: u32 },
+}
+
 fn parse_duration(arg: &str) -> Result<Duration, std::num::ParseIntError> {
     let seconds = arg.parse()?;
     Ok(Duration::from_secs(seconds))
@@ -72,6 +193,22 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Args::parse();
 
+    // Simulation runs entirely locally, so doesn't need a terrarium address.
+    if let Commands::Simulate {
+        config_file,
+        date,
+        csv,
+        csv_step,
+    } = &args.command
+    {
+        return run_simulation(config_file, *date, *csv, *csv_step);
+    }
+    // The schemas are generated from the same types the terrarium uses, so
+    // there's no need to ask it for them.
+    if let Commands::Schema { name, out_dir } = &args.command {
+        return print_schemas(name.as_deref(), out_dir.as_deref());
+    }
+
     // The terrarium address can be passed by flag or environment variable. Flag
     // takes precedence.
     let addr = args
@@ -80,22 +217,63 @@ async fn main() -> anyhow::Result<()> {
         .expect("No address specified. Either pass --addr or set OASIS_ADDR env var.");
 
     match &args.command {
-        Commands::Ctl { overrides } => {
+        Commands::Ctl {
+            overrides,
+            cancel,
+            clear,
+        } => {
             log::info!("Connecting to terrarium at '{addr}'...");
             let client = reqwest::Client::new();
+            let sequence_uri = format!("http://{addr}/control/sequence");
 
-            let cmds: Result<Vec<ControlCommand>, CommandParseError> =
-                overrides.iter().map(|x| parse_cmd(x)).collect();
-            match cmds {
-                Ok(_) => {}
-                Err(ref err) => {
-                    panic!("Error parsing command(s): {err}");
+            if clear.iter().any(|abbrev| abbrev == "all") {
+                let resp = client
+                    .delete(format!("http://{addr}/overrides"))
+                    .send()
+                    .await?;
+                if resp.status() != StatusCode::OK {
+                    return Err(anyhow!("Clear failed: {}", resp.text().await?));
                 }
+                return Ok(());
+            }
+            if !clear.is_empty() {
+                for abbrev in clear {
+                    let actuator = match parse_actuator(abbrev) {
+                        Ok(actuator) => actuator,
+                        Err(err) => panic!("Error parsing actuator: {err}"),
+                    };
+                    let name = serde_json::to_value(actuator)?;
+                    let uri = format!("http://{addr}/overrides/{}", name.as_str().unwrap());
+                    let resp = client.delete(uri).send().await?;
+                    if resp.status() != StatusCode::OK {
+                        return Err(anyhow!("Clear failed: {}", resp.text().await?));
+                    }
+                }
+                return Ok(());
             }
 
-            let update_data = create_update_data(&cmds.unwrap());
-            let control_uri = format!("http://{addr}/control");
-            let resp = client.post(control_uri).json(&update_data).send().await?;
+            let resp = if *cancel {
+                client.delete(sequence_uri).send().await?
+            } else {
+                let steps = match parse_steps(overrides) {
+                    Ok(steps) => steps,
+                    Err(err) => {
+                        panic!("Error parsing command(s): {err}");
+                    }
+                };
+
+                // A single step without a delay is a plain control command.
+                if let [step] = steps.as_slice()
+                    && step.delay == 0
+                {
+                    let update_data = create_update_data(&step.cmds);
+                    let control_uri = format!("http://{addr}/control");
+                    client.post(control_uri).json(&update_data).send().await?
+                } else {
+                    let sequence = create_sequence(&steps);
+                    client.post(sequence_uri).json(&sequence).send().await?
+                }
+            };
             if resp.status() != StatusCode::OK {
                 return Err(anyhow!("Control failed: {}", resp.text().await?));
             }
@@ -126,30 +304,29 @@ async fn main() -> anyhow::Result<()> {
         Commands::Config {
             config_json,
             config_file,
+            format,
+            dry_run,
         } => {
             log::info!("Connecting to terrarium at '{addr}'...");
             let client = reqwest::Client::new();
 
             let config_uri = format!("http://{addr}/config");
-            if let Some(cfg) = config_json {
-                let config_data: TerrariumConfigUpdate = serde_json::from_str(cfg)?;
-                let resp = client.post(config_uri).json(&config_data).send().await?;
-                if resp.status() != StatusCode::OK {
-                    return Err(anyhow!(
-                        "Got bad response: {}",
-                        resp.text().await.expect("resp text")
-                    ));
-                }
-            } else if let Some(cfg_file) = config_file {
-                let file = File::open(cfg_file).expect("file read");
-                let reader = BufReader::new(file);
-                let config_data: TerrariumConfigUpdate = serde_json::from_reader(reader)?;
-                let resp = client.post(config_uri).json(&config_data).send().await?;
-                if resp.status() != StatusCode::OK {
-                    return Err(anyhow!(
-                        "Got bad response: {}",
-                        resp.text().await.expect("resp text")
-                    ));
+            let config_data = match (config_json, config_file) {
+                (Some(cfg), _) => Some(serde_json::from_str::<TerrariumConfigUpdate>(cfg)?),
+                (None, Some(cfg_file)) => Some(read_config_update_file(cfg_file)?),
+                (None, None) => None,
+            };
+            if let Some(config_data) = config_data {
+                if *dry_run {
+                    let dry_run_uri = format!("{config_uri}?dry_run=1");
+                    let result: DryRunResult =
+                        post_config_update(&client, &dry_run_uri, &config_data)
+                            .await?
+                            .json()
+                            .await?;
+                    print!("{}", format_config_changes(&result.changes));
+                } else {
+                    post_config_update(&client, &config_uri, &config_data).await?;
                 }
             } else {
                 let resp = client.get(config_uri).send().await?;
@@ -161,9 +338,109 @@ async fn main() -> anyhow::Result<()> {
                 }
 
                 let text = resp.text().await.unwrap();
+                match format {
+                    ConfigFormat::Json => println!("{text}"),
+                    ConfigFormat::Toml => {
+                        let (config, _) = TerrariumConfig::from_json(text.as_bytes())?;
+                        print!("{}", config.to_toml()?);
+                    }
+                }
+            }
+        }
+        Commands::Schedule {
+            command: ScheduleCommands::Preview { hours, json },
+        } => {
+            log::info!("Connecting to terrarium at '{addr}'...");
+            let client = reqwest::Client::new();
+
+            let preview_uri = format!("http://{addr}/schedule/preview?hours={hours}");
+            let resp = client.get(preview_uri).send().await?;
+            if resp.status() != StatusCode::OK {
+                return Err(anyhow!(
+                    "Got bad response: {}",
+                    resp.text().await.expect("resp text")
+                ));
+            }
+
+            let text = resp.text().await.unwrap();
+            if *json {
                 println!("{text}");
+            } else {
+                let preview: SchedulePreview = serde_json::from_str(&text)?;
+                print!("{}", format_schedule_preview(&preview));
+            }
+        }
+        Commands::Profile { command } => {
+            log::info!("Connecting to terrarium at '{addr}'...");
+            let client = reqwest::Client::new();
+
+            let config_uri = format!("http://{addr}/config");
+            let resp = client.get(&config_uri).send().await?;
+            if resp.status() != StatusCode::OK {
+                return Err(anyhow!(
+                    "Got bad response: {}",
+                    resp.text().await.expect("resp text")
+                ));
+            }
+            let config: TerrariumConfig = resp.json().await?;
+
+            if let ProfileCommands::List = command {
+                print!("{}", format_profiles(&config));
+                return Ok(());
+            }
+
+            let update = create_profile_update(command, &config)?;
+            post_config_update(&client, &config_uri, &update).await?;
+        }
+        Commands::History { command } => {
+            log::info!("Connecting to terrarium at '{addr}'...");
+            let client = reqwest::Client::new();
+
+            match command {
+                HistoryCommands::List => {
+                    let resp = client
+                        .get(format!("http://{addr}/config/history"))
+                        .send()
+                        .await?;
+                    if resp.status() != StatusCode::OK {
+                        return Err(anyhow!(
+                            "Got bad response: {}",
+                            resp.text().await.expect("resp text")
+                        ));
+                    }
+                    let history: Vec<HistoryEntrySummary> = resp.json().await?;
+                    print!("{}", format_history(&history));
+                }
+                HistoryCommands::Rollback { id } => {
+                    let resp = client
+                        .post(format!("http://{addr}/config/rollback/{id}"))
+                        .send()
+                        .await?;
+                    if resp.status() != StatusCode::OK {
+                        return Err(anyhow!(
+                            "Got bad response: {}",
+                            resp.text().await.expect("resp text")
+                        ));
+                    }
+                    println!("Rolled back to config #{id}");
+                }
+            }
+        }
+        Commands::Events => {
+            log::info!("Connecting to terrarium at '{addr}'...");
+            let client = reqwest::Client::new();
+            let resp = client.get(format!("http://{addr}/events")).send().await?;
+            if resp.status() != StatusCode::OK {
+                return Err(anyhow!(
+                    "Got bad response: {}",
+                    resp.text().await.expect("resp text")
+                ));
             }
+            let events: Vec<Event> = resp.json().await?;
+            print!("{}", format_events(&events));
         }
+        Commands::Simulate { .. } => unreachable!("simulate is handled above"),
+        Commands::Schema { .. } => unreachable!("schema is handled above"),
         Commands::Scan { timeout } => {
             // Create a daemon
             let mdns = ServiceDaemon::new().expect("Failed to create daemon");
@@ -226,6 +503,276 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// Sends @update to the terrarium's /config endpoint at @config_uri and returns
+// the response. If the terrarium rejects it, the error lists each problem
+// alongside the value that caused it.
+async fn post_config_update(
+    client: &reqwest::Client,
+    config_uri: &str,
+    update: &TerrariumConfigUpdate,
+) -> anyhow::Result<reqwest::Response> {
+    let resp = client.post(config_uri).json(update).send().await?;
+    match resp.status() {
+        StatusCode::OK => Ok(resp),
+        StatusCode::UNPROCESSABLE_ENTITY => {
+            let report: ValidationReport = resp.json().await?;
+            Err(anyhow!(
+                "Config update rejected:\n{}",
+                format_validation_report(&report, &serde_json::to_value(update)?)
+            ))
+        }
+        _ => Err(anyhow!(
+            "Got bad response: {}",
+            resp.text().await.expect("resp text")
+        )),
+    }
+}
+
+// Formats each problem in @report on its own lines, along with the value at
+// its path in @update, if there is one.
+fn format_validation_report(report: &ValidationReport, update: &serde_json::Value) -> String {
+    let mut out = String::new();
+    for err in &report.errors {
+        // Convert the path, like `schedule.mist[2].days`, to a json pointer,
+        // like `/schedule/mist/2/days`.
+        let pointer: String = err
+            .path
+            .replace('[', ".")
+            .replace(']', "")
+            .split('.')
+            .map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        match update.pointer(&pointer) {
+            Some(value) => out += &format!("  {} = {value}\n", err.path),
+            None => out += &format!("  {}\n", err.path),
+        }
+        let code = serde_json::to_value(err.code).unwrap();
+        out += &format!("      {} ({})\n", err.message, code.as_str().unwrap());
+    }
+    out
+}
+
+// Formats @changes one per line, like `timezone: null -> "Europe/Berlin"`.
+fn format_config_changes(changes: &[ConfigChange]) -> String {
+    if changes.is_empty() {
+        return "No changes\n".to_string();
+    }
+    changes.iter().map(|change| format!("{change}\n")).collect()
+}
+
+// Reads a config update from @path, as toml if it has a .toml extension and as
+// json otherwise.
+fn read_config_update_file(path: &str) -> anyhow::Result<TerrariumConfigUpdate> {
+    let is_toml = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        TerrariumConfigUpdate::from_toml(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("failed to parse toml config file '{path}'"))
+    } else {
+        let file = File::open(path)?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse json config file '{path}'"))
+    }
+}
+
+fn print_schemas(name: Option<&str>, out_dir: Option<&str>) -> anyhow::Result<()> {
+    if let Some(dir) = out_dir {
+        std::fs::create_dir_all(dir)?;
+        let names = name.map_or(terralib::schema::NAMES.to_vec(), |name| vec![name]);
+        for name in names {
+            let path = Path::new(dir).join(format!("{name}.schema.json"));
+            let schema = terralib::schema::schema(name).unwrap();
+            std::fs::write(&path, serde_json::to_string_pretty(&schema)? + "\n")?;
+            log::info!("Wrote {}", path.display());
+        }
+        return Ok(());
+    }
+
+    let js = match name {
+        Some(name) => terralib::schema::schema(name).unwrap().to_value(),
+        None => terralib::schema::all_schemas(),
+    };
+    println!("{}", serde_json::to_string_pretty(&js)?);
+    Ok(())
+}
+
+fn run_simulation(
+    config_file: &str,
+    date: Option<jiff::civil::Date>,
+    csv: bool,
+    csv_step: usize,
+) -> anyhow::Result<()> {
+    let update = read_config_update_file(config_file)?;
+    let mut config = TerrariumConfig::new_with_reasonable_defaults();
+    let result = update
+        .validate()
+        .map_err(anyhow::Error::from)
+        .and_then(|()| config.update(&update));
+    if let Err(err) = result {
+        return Err(match err.downcast::<ValidationReport>() {
+            Ok(report) => anyhow!(
+                "Invalid config:\n{}",
+                format_validation_report(&report, &serde_json::to_value(&update)?)
+            ),
+            Err(err) => err,
+        });
+    }
+
+    let tz = config.get_timezone();
+    let date = date.unwrap_or_else(|| jiff::Timestamp::now().to_zoned(tz.clone()).date());
+    let schedule = config.active_schedule().cloned().unwrap_or_default();
+    let sim = simulate::Simulation::run(&schedule, date, tz)?;
+
+    if csv {
+        print!("{}", sim.csv(csv_step));
+    } else {
+        println!("Simulated schedule for {date}:");
+        println!();
+        print!("{}", sim.timeline());
+        println!();
+        print!("{}", sim.summary());
+    }
+    Ok(())
+}
+
+fn format_profiles(config: &TerrariumConfig) -> String {
+    let mut out = String::new();
+    for name in config.profiles.keys() {
+        let marker = if config.active_profile.as_ref() == Some(name) {
+            '*'
+        } else {
+            ' '
+        };
+        out += &format!("{marker} {name}\n");
+    }
+    if config.profiles.is_empty() {
+        out += "No profiles\n";
+    }
+    out
+}
+
+fn format_history(history: &[HistoryEntrySummary]) -> String {
+    let mut out = String::new();
+    for (i, entry) in history.iter().enumerate() {
+        let marker = if i == history.len() - 1 { '*' } else { ' ' };
+        out += &format!(
+            "{marker} #{:<3} {:.0}  {}\n",
+            entry.id, entry.timestamp, entry.summary
+        );
+    }
+    if history.is_empty() {
+        out += "No config history\n";
+    }
+    out
+}
+
+fn format_events(events: &[Event]) -> String {
+    let mut out = String::new();
+    for event in events {
+        out += &format!("{:.0}  {}\n", event.timestamp, event.kind);
+    }
+    if events.is_empty() {
+        out += "No events\n";
+    }
+    out
+}
+
+// Builds the config update for a profile command. @config is the terrarium's
+// current config, which is used to check that the command makes sense before
+// sending it.
+fn create_profile_update(
+    cmd: &ProfileCommands,
+    config: &TerrariumConfig,
+) -> anyhow::Result<TerrariumConfigUpdate> {
+    let exists = |name: &String| config.profiles.contains_key(name);
+    let mut update = TerrariumConfigUpdate::default();
+    match cmd {
+        ProfileCommands::List => {}
+        ProfileCommands::Activate { name } => {
+            if !exists(name) {
+                return Err(anyhow!("No profile named '{name}'"));
+            }
+            update.active_profile = Update::Set(name.clone());
+        }
+        ProfileCommands::Create {
+            name,
+            copy_from,
+            activate,
+        } => {
+            if exists(name) {
+                return Err(anyhow!("Profile '{name}' already exists"));
+            }
+            let schedule_update = match copy_from {
+                Some(copy_from) => match config.profiles.get(copy_from) {
+                    Some(schedule) => ScheduleUpdate::from(schedule),
+                    None => return Err(anyhow!("No profile named '{copy_from}'")),
+                },
+                None => ScheduleUpdate::default(),
+            };
+            update.profiles = BTreeMap::from([(name.clone(), Update::Set(schedule_update))]);
+            if *activate {
+                update.active_profile = Update::Set(name.clone());
+            }
+        }
+        ProfileCommands::Rename { from, to } => {
+            if !exists(from) {
+                return Err(anyhow!("No profile named '{from}'"));
+            }
+            update.rename_profiles = vec![ProfileRename {
+                from: from.clone(),
+                to: to.clone(),
+            }];
+        }
+        ProfileCommands::Delete { name } => {
+            if !exists(name) {
+                return Err(anyhow!("No profile named '{name}'"));
+            }
+            if config.active_profile.as_ref() == Some(name) {
+                return Err(anyhow!(
+                    "Can't delete the active profile. Activate another profile first"
+                ));
+            }
+            update.profiles = BTreeMap::from([(name.clone(), Update::Clear)]);
+        }
+    }
+    update.validate()?;
+    Ok(update)
+}
+
+// Formats @preview as a timeline with one line per point in time that
+// something turns on or off.
+fn format_schedule_preview(preview: &SchedulePreview) -> String {
+    let on_off = |on| if on { "on" } else { "off" };
+    let time_fmt = "%a %H:%M:%S";
+
+    let mut out = format!(
+        "Schedule from {} to {}:\n",
+        preview.start.strftime("%a %Y-%m-%d %H:%M:%S %Z"),
+        preview.end.strftime("%a %Y-%m-%d %H:%M:%S %Z"),
+    );
+    out += &format!(
+        "  {}  lights {}, fans {}, mist {}\n",
+        preview.start.strftime(time_fmt),
+        on_off(preview.initial.lights > 0.0),
+        on_off(preview.initial.fans),
+        on_off(preview.initial.mist),
+    );
+    for (time, transitions) in &preview.transitions.iter().chunk_by(|t| &t.time) {
+        let changes = transitions
+            .map(|t| {
+                let name = serde_json::to_value(t.actuator).unwrap();
+                format!("{} {}", name.as_str().unwrap(), on_off(t.on))
+            })
+            .join(", ");
+        out += &format!("  {}  {changes}\n", time.strftime(time_fmt));
+    }
+    if preview.transitions.is_empty() {
+        out += "  No changes scheduled.\n";
+    }
+    out
+}
+
 #[derive(PartialEq, Eq, Hash, Clone)]
 struct MdnsInfo {
     ip: String,
@@ -246,6 +793,7 @@ fn create_update_data(cmds: &[ControlCommand]) -> ActuatorOverrideSet {
                 actuator: cmd.actuator,
                 value: val,
                 duration_secs: cmd.duration as u32,
+                hold: cmd.hold,
             }
         })
         .collect();
@@ -253,11 +801,32 @@ fn create_update_data(cmds: &[ControlCommand]) -> ActuatorOverrideSet {
     ActuatorOverrideSet { updates }
 }
 
+fn create_sequence(steps: &[ControlStep]) -> OverrideSequence {
+    let steps = steps
+        .iter()
+        .map(|step| SequenceStep {
+            delay_secs: step.delay,
+            updates: create_update_data(&step.cmds).updates,
+        })
+        .collect();
+    OverrideSequence { steps }
+}
+
+// One step of a `client ctl` sequence: the commands to run once @delay seconds
+// have passed after the previous step.
+#[derive(PartialEq, Debug)]
+struct ControlStep {
+    delay: u32,
+    cmds: Vec<ControlCommand>,
+}
+
 #[derive(PartialEq, Debug)]
 struct ControlCommand {
     actuator: Actuator,
     value: f32,    // TODO: or bool
     duration: f32, // in seconds
+    // If set, @duration is ignored.
+    hold: Option<OverrideHold>,
 }
 
 // TODO: consider using anyhow error instead since all we're doing is recording an error message
@@ -280,20 +849,40 @@ impl fmt::Display for CommandParseError {
 
 const DEFAULT_DURATION: f32 = 60.0;
 
+// Splits @args into steps at each 'then'. A 'wait:<secs>' in a step delays it.
+fn parse_steps(args: &[String]) -> Result<Vec<ControlStep>, CommandParseError> {
+    args.split(|arg| arg == "then")
+        .map(|step_args| {
+            let mut step = ControlStep {
+                delay: 0,
+                cmds: vec![],
+            };
+            for arg in step_args {
+                match arg.strip_prefix("wait:") {
+                    Some(secs) => {
+                        step.delay += secs.parse::<u32>().map_err(|_| {
+                            CommandParseError::new(&format!("Invalid wait: '{arg}'"))
+                        })?
+                    }
+                    None => step.cmds.push(parse_cmd(arg)?),
+                }
+            }
+            if step.cmds.is_empty() {
+                return Err(CommandParseError::new("Empty step"));
+            }
+            Ok(step)
+        })
+        .collect()
+}
+
 // TODO: mist and fan should be 0 or 1, lights should be in [0, 1]
 fn parse_cmd(cmd: &str) -> Result<ControlCommand, CommandParseError> {
     if cmd.is_empty() {
         return Err(CommandParseError::new("Empty command"));
     }
 
-    let abbrev_map = HashMap::from([
-        ("m", Actuator::Mist),
-        ("l", Actuator::Lights),
-        ("f", Actuator::Fans),
-    ]);
-
     let re = Regex::new(
-        r"^(?<abbrev>[a-zA-Z])(@(?<value>[0-9]*(\.[0-9]+)?))?(:(?<duration>[0-9]*(\.[0-9]+)?))?$",
+        r"^(?<abbrev>[a-zA-Z])(@(?<value>[0-9]*(\.[0-9]+)?))?(:(?<duration>[0-9]*(\.[0-9]+)?|next|manual))?$",
     )
     .unwrap();
     let caps = match re.captures(cmd) {
@@ -304,14 +893,7 @@ fn parse_cmd(cmd: &str) -> Result<ControlCommand, CommandParseError> {
     };
 
     let abbrev = caps.name("abbrev").unwrap().as_str();
-    let actuator = match abbrev_map.get(abbrev.to_lowercase().as_str()) {
-        Some(actuator) => *actuator,
-        None => {
-            return Err(CommandParseError::new(&format!(
-                "Invalid abbreviation: '{abbrev}'"
-            )));
-        }
-    };
+    let actuator = parse_actuator(abbrev)?;
 
     let value = match caps.name("value") {
         Some(mstr) => mstr.as_str().parse::<f32>().unwrap_or(0.0),
@@ -326,18 +908,36 @@ fn parse_cmd(cmd: &str) -> Result<ControlCommand, CommandParseError> {
         }
     };
 
-    let duration = match caps.name("duration") {
-        Some(mstr) => mstr.as_str().parse::<f32>().unwrap_or(0.0),
-        None => DEFAULT_DURATION,
+    let (duration, hold) = match caps.name("duration").map(|mstr| mstr.as_str()) {
+        Some("next") => (0.0, Some(OverrideHold::NextTransition)),
+        Some("manual") => (0.0, Some(OverrideHold::Manual)),
+        Some(duration) => (duration.parse::<f32>().unwrap_or(0.0), None),
+        None => (DEFAULT_DURATION, None),
     };
 
     Ok(ControlCommand {
         actuator,
         value,
         duration,
+        hold,
     })
 }
 
+// Parses an actuator abbreviation, in either case.
+fn parse_actuator(abbrev: &str) -> Result<Actuator, CommandParseError> {
+    let abbrev_map = HashMap::from([
+        ("m", Actuator::Mist),
+        ("l", Actuator::Lights),
+        ("f", Actuator::Fans),
+    ]);
+    match abbrev_map.get(abbrev.to_lowercase().as_str()) {
+        Some(actuator) => Ok(*actuator),
+        None => Err(CommandParseError::new(&format!(
+            "Invalid abbreviation: '{abbrev}'"
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod parse_cmd {
     use super::*;
@@ -350,6 +950,7 @@ mod parse_cmd {
                 actuator: Actuator::Mist,
                 value: 1.0,
                 duration: 2.0,
+                hold: None,
             })
         );
     }
@@ -362,6 +963,7 @@ mod parse_cmd {
                 actuator: Actuator::Mist,
                 value: 0.0,
                 duration: 2.0,
+                hold: None,
             })
         );
     }
@@ -374,6 +976,7 @@ mod parse_cmd {
                 actuator: Actuator::Mist,
                 value: 1.0,
                 duration: DEFAULT_DURATION,
+                hold: None,
             })
         );
     }
@@ -386,6 +989,7 @@ mod parse_cmd {
                 actuator: Actuator::Mist,
                 value: 1.0,
                 duration: 2.0,
+                hold: None,
             })
         );
     }
@@ -398,6 +1002,7 @@ mod parse_cmd {
                 actuator: Actuator::Lights,
                 value: 0.5,
                 duration: 0.5,
+                hold: None,
             })
         );
     }
@@ -410,6 +1015,27 @@ mod parse_cmd {
         );
     }
 
+    #[test]
+    fn hold() {
+        assert_eq!(
+            parse_cmd("l@0:next"),
+            Ok(ControlCommand {
+                actuator: Actuator::Lights,
+                value: 0.0,
+                duration: 0.0,
+                hold: Some(OverrideHold::NextTransition),
+            })
+        );
+        assert_eq!(
+            parse_cmd("F:manual").map(|cmd| cmd.hold),
+            Ok(Some(OverrideHold::Manual))
+        );
+        assert_eq!(
+            parse_cmd("F:forever"),
+            Err(CommandParseError::new("Invalid command: 'F:forever'"))
+        );
+    }
+
     #[test]
     fn invalid_command() {
         assert_eq!(
@@ -419,6 +1045,83 @@ mod parse_cmd {
     }
 }
 
+#[cfg(test)]
+mod parse_steps {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn cmd(actuator: Actuator, value: f32, duration: f32) -> ControlCommand {
+        ControlCommand {
+            actuator,
+            value,
+            duration,
+            hold: None,
+        }
+    }
+
+    #[test]
+    fn single_step() {
+        assert_eq!(
+            parse_steps(&args("M:30 f")),
+            Ok(vec![ControlStep {
+                delay: 0,
+                cmds: vec![
+                    cmd(Actuator::Mist, 1.0, 30.0),
+                    cmd(Actuator::Fans, 0.0, 60.0)
+                ],
+            }])
+        );
+    }
+
+    #[test]
+    fn chained() {
+        let steps = parse_steps(&args("M:30 then F:120 then wait:10 L@1:600")).unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                ControlStep {
+                    delay: 0,
+                    cmds: vec![cmd(Actuator::Mist, 1.0, 30.0)],
+                },
+                ControlStep {
+                    delay: 0,
+                    cmds: vec![cmd(Actuator::Fans, 1.0, 120.0)],
+                },
+                ControlStep {
+                    delay: 10,
+                    cmds: vec![cmd(Actuator::Lights, 1.0, 600.0)],
+                },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(create_sequence(&steps)).unwrap()["steps"][2],
+            serde_json::json!({
+                "delay_secs": 10,
+                "updates": [{"actuator": "lights", "value": 1.0, "duration_secs": 600}]
+            })
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse_steps(&args("M:30 then")),
+            Err(CommandParseError::new("Empty step"))
+        );
+        assert_eq!(
+            parse_steps(&args("wait:10")),
+            Err(CommandParseError::new("Empty step"))
+        );
+        assert_eq!(
+            parse_steps(&args("wait:x M")),
+            Err(CommandParseError::new("Invalid wait: 'wait:x'"))
+        );
+    }
+}
+
 #[cfg(test)]
 mod update_data {
     use super::*;
@@ -430,11 +1133,13 @@ mod update_data {
                 actuator: Actuator::Mist,
                 value: 1.0,
                 duration: 10.0,
+                hold: None,
             },
             ControlCommand {
                 actuator: Actuator::Lights,
                 value: 0.5,
                 duration: 10.0,
+                hold: None,
             },
         ];
         let update_data = create_update_data(&cmds);
@@ -446,11 +1151,13 @@ mod update_data {
                         actuator: Actuator::Mist,
                         value: ActuatorValue::Bool(true),
                         duration_secs: 10,
+                        hold: None,
                     },
                     ActuatorOverride {
                         actuator: Actuator::Lights,
                         value: ActuatorValue::Float(0.5),
                         duration_secs: 10,
+                        hold: None,
                     }
                 ],
             }
@@ -462,3 +1169,207 @@ mod update_data {
         );
     }
 }
+
+#[cfg(test)]
+mod schedule_preview {
+    use super::*;
+    use terralib::config::{Schedule, ScheduleTransition};
+    use terralib::types::ActuatorValues;
+
+    #[test]
+    fn format() {
+        let at = |time: &str| -> jiff::Zoned { format!("2025-04-07T{time}[UTC]").parse().unwrap() };
+        let preview = SchedulePreview {
+            start: at("09:00:00"),
+            end: at("12:00:00"),
+            initial: ActuatorValues::default(),
+            transitions: vec![
+                ScheduleTransition {
+                    time: at("10:00:01"),
+                    actuator: Actuator::Lights,
+                    on: true,
+                },
+                ScheduleTransition {
+                    time: at("11:00:00"),
+                    actuator: Actuator::Mist,
+                    on: true,
+                },
+                ScheduleTransition {
+                    time: at("11:00:00"),
+                    actuator: Actuator::Fans,
+                    on: false,
+                },
+            ],
+        };
+        assert_eq!(
+            format_schedule_preview(&preview),
+            "Schedule from Mon 2025-04-07 09:00:00 UTC to Mon 2025-04-07 12:00:00 UTC:
+  Mon 09:00:00  lights off, fans off, mist off
+  Mon 10:00:01  lights on
+  Mon 11:00:00  mist on, fans off
+"
+        );
+
+        let empty =
+            Schedule::default().preview(&at("09:00:00"), jiff::SignedDuration::from_hours(1));
+        assert!(format_schedule_preview(&empty).ends_with("No changes scheduled.\n"));
+    }
+}
+
+#[cfg(test)]
+mod profiles {
+    use super::*;
+
+    #[test]
+    fn list() {
+        let mut config = TerrariumConfig::new_with_reasonable_defaults();
+        config
+            .profiles
+            .insert("vacation".to_string(), Default::default());
+        assert_eq!(format_profiles(&config), "* default\n  vacation\n");
+    }
+
+    #[test]
+    fn create_and_activate() {
+        let config = TerrariumConfig::new_with_reasonable_defaults();
+        let update = create_profile_update(
+            &ProfileCommands::Create {
+                name: "vacation".to_string(),
+                copy_from: Some("default".to_string()),
+                activate: true,
+            },
+            &config,
+        )
+        .unwrap();
+
+        let mut updated = config.clone();
+        updated.update(&update).unwrap();
+        assert_eq!(updated.active_profile, Some("vacation".to_string()));
+        assert_eq!(updated.profiles["vacation"], config.profiles["default"]);
+    }
+
+    #[test]
+    fn errors() {
+        let config = TerrariumConfig::new_with_reasonable_defaults();
+        let err = |cmd| {
+            create_profile_update(&cmd, &config)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err(ProfileCommands::Activate {
+                name: "nope".to_string()
+            }),
+            "No profile named 'nope'"
+        );
+        assert_eq!(
+            err(ProfileCommands::Delete {
+                name: "default".to_string()
+            }),
+            "Can't delete the active profile. Activate another profile first"
+        );
+        assert!(
+            err(ProfileCommands::Rename {
+                from: "default".to_string(),
+                to: "bad name".to_string()
+            })
+            .starts_with("rename_profiles[0].to: Profile names can only contain")
+        );
+    }
+}
+
+#[cfg(test)]
+mod validation_report {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let update: TerrariumConfigUpdate = serde_json::from_str(
+            r#"{
+                "active_profile": "",
+                "schedule": {
+                    "mist": [{
+                        "start_time": "11:00:00",
+                        "duration_secs": 10,
+                        "repeat": {"n_hours": 1, "stop_time": "08:00:00"}
+                    }]
+                }
+            }"#,
+        )
+        .unwrap();
+        let report = update.validate().unwrap_err();
+        assert_eq!(
+            format_validation_report(&report, &serde_json::to_value(&update).unwrap()),
+            concat!(
+                "  schedule.mist[0].repeat.stop_time = \"08:00:00\"\n",
+                "      Stop time must be after start time (invalid_order)\n",
+                "  active_profile = \"\"\n",
+                "      Profile names must be 1-32 characters long, got '' (empty)\n",
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod dry_run {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_config_changes(&[]), "No changes\n");
+        let config = TerrariumConfig::new_with_reasonable_defaults();
+        let mut updated = config.clone();
+        updated.timezone = Some("Europe/Berlin".to_string());
+        let result = DryRunResult::new(&config, &updated).unwrap();
+        assert_eq!(
+            format_config_changes(&result.changes),
+            "timezone: null -> \"Europe/Berlin\"\n"
+        );
+    }
+}
+
+#[cfg(test)]
+mod history {
+    use super::*;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_history(&[]), "No config history\n");
+        let entry = |id, summary: &str| HistoryEntrySummary {
+            id,
+            timestamp: "2025-06-01T12:30:00.25Z".parse().unwrap(),
+            summary: summary.to_string(),
+        };
+        assert_eq!(
+            format_history(&[entry(1, "Changed timezone"), entry(2, "Rolled back to #1")]),
+            concat!(
+                "  #1   2025-06-01T12:30:00Z  Changed timezone\n",
+                "* #2   2025-06-01T12:30:00Z  Rolled back to #1\n",
+            )
+        );
+    }
+}
+
+#[cfg(test)]
+mod events {
+    use super::*;
+    use terralib::events::EventKind;
+    use terralib::thermal::ThermalLevel;
+
+    #[test]
+    fn format() {
+        assert_eq!(format_events(&[]), "No events\n");
+        let event = Event {
+            timestamp: "2025-06-01T12:30:00.25Z".parse().unwrap(),
+            kind: EventKind::Thermal {
+                level: ThermalLevel::Critical,
+                air_temp_c: Some(38.25),
+                cpu_temp_c: None,
+            },
+        };
+        assert_eq!(
+            format_events(&[event]),
+            "2025-06-01T12:30:00Z  Thermal protection Critical (air 38.2C, cpu unknown)\n"
+        );
+    }
+}
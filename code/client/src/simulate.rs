@@ -0,0 +1,288 @@
+// Offline simulation of a terrarium schedule over a full day. This is used by
+// the `simulate` subcommand to preview the effect of a config before sending it
+// to a terrarium.
+
+use jiff::civil::Date;
+use jiff::{SignedDuration, Zoned};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+use terralib::config::Schedule;
+use terralib::humidity::HumidityController;
+use terralib::types::ActuatorValues;
+
+// Each column of the ascii timeline covers this many seconds.
+const TIMELINE_BUCKET_SECS: usize = 15 * 60;
+
+// A very rough model of the terrarium's relative humidity. It isn't meant to
+// be accurate, just good enough to see how auto-mist interacts with the rest
+// of the schedule.
+struct HumidityModel {
+    humidity: f32,
+}
+
+impl HumidityModel {
+    // The humidity that the terrarium drifts towards when not misting. The
+    // lights warm things up and dry them out a bit.
+    fn ambient(lights: f32) -> f32 {
+        0.65 - 0.1 * lights
+    }
+
+    // Advances the model by one second.
+    fn step(&mut self, values: &ActuatorValues) {
+        if values.mist {
+            self.humidity += 0.004;
+        }
+        // The fans clear out the moist air much faster.
+        let decay_secs = if values.fans { 5.0 * 60.0 } else { 30.0 * 60.0 };
+        self.humidity += (Self::ambient(values.lights) - self.humidity) / decay_secs;
+        self.humidity = self.humidity.clamp(0.0, 1.0);
+    }
+}
+
+// The state of the terrarium at one second of the simulation.
+pub struct Sample {
+    pub time: Zoned,
+    pub values: ActuatorValues,
+    pub humidity: f32,
+}
+
+pub struct Simulation {
+    // One sample for every second of the day. That's 23 or 25 hours' worth on
+    // days when the clocks change.
+    pub samples: Vec<Sample>,
+}
+
+impl Simulation {
+    // Runs @schedule over all of @date in timezone @tz.
+    pub fn run(schedule: &Schedule, date: Date, tz: jiff::tz::TimeZone) -> anyhow::Result<Self> {
+        let start = date.to_zoned(tz)?;
+        let mut model = HumidityModel {
+            humidity: HumidityModel::ambient(0.0),
+        };
+        // The humidity controller keeps time with Instants, so give it
+        // simulated ones.
+        let mut humidity_controller = HumidityController::new();
+        let instant_start = Instant::now();
+        let humidity_control = schedule.humidity_control.clone().unwrap_or_default();
+
+        let secs = start.duration_until(&start.tomorrow()?).as_secs();
+        let mut samples = Vec::with_capacity(secs as usize);
+        for i in 0..secs {
+            let time = start.checked_add(SignedDuration::from_secs(i))?;
+            let mut values = schedule.evaluate(&time);
+            if let Some(setpoint) = schedule.humidity_setpoint
+                && schedule.auto_mist_enabled
+                && humidity_controller.update(
+                    &humidity_control,
+                    setpoint,
+                    Some(model.humidity),
+                    instant_start + Duration::from_secs(i as u64),
+                )
+            {
+                values.mist = true;
+            }
+            samples.push(Sample {
+                time,
+                values,
+                humidity: model.humidity,
+            });
+            model.step(&values);
+        }
+
+        Ok(Self { samples })
+    }
+
+    pub fn mist_secs(&self) -> usize {
+        self.samples.iter().filter(|s| s.values.mist).count()
+    }
+
+    pub fn fan_secs(&self) -> usize {
+        self.samples.iter().filter(|s| s.values.fans).count()
+    }
+
+    pub fn light_hours(&self) -> f32 {
+        self.samples
+            .iter()
+            .filter(|s| s.values.lights > 0.0)
+            .count() as f32
+            / 3600.0
+    }
+
+    // Renders an ascii timeline of the day with one column per 15 minutes.
+    // Lights are shaded by their average intensity, fans and mist are marked if
+    // they were on at all, and humidity is shown in tens of percent.
+    pub fn timeline(&self) -> String {
+        let buckets: Vec<&[Sample]> = self.samples.chunks(TIMELINE_BUCKET_SECS).collect();
+        let columns_per_hour = 3600 / TIMELINE_BUCKET_SECS;
+
+        let mut out = String::new();
+        let mut ruler = String::new();
+        // Labeled with the time of day, which doesn't go up by 3 every 3 hours
+        // when the clocks change.
+        for sample in self.samples.iter().step_by(3 * 3600) {
+            write!(
+                ruler,
+                "{:<width$}",
+                sample.time.hour(),
+                width = 3 * columns_per_hour
+            )
+            .unwrap();
+        }
+        writeln!(out, "         {}", ruler.trim_end()).unwrap();
+
+        let mut row = |name: &str, f: &dyn Fn(&[Sample]) -> char| {
+            let cells: String = buckets.iter().map(|b| f(b)).collect();
+            writeln!(out, "{name:<7} |{cells}|").unwrap();
+        };
+        row("lights", &|b| {
+            let avg = b.iter().map(|s| s.values.lights).sum::<f32>() / b.len() as f32;
+            match avg {
+                x if x <= 0.0 => ' ',
+                x if x < 0.25 => '.',
+                x if x < 0.5 => ':',
+                x if x < 0.75 => '=',
+                _ => '#',
+            }
+        });
+        row("fans", &|b| {
+            if b.iter().any(|s| s.values.fans) {
+                '#'
+            } else {
+                ' '
+            }
+        });
+        row("mist", &|b| {
+            if b.iter().any(|s| s.values.mist) {
+                '#'
+            } else {
+                ' '
+            }
+        });
+        row("humid", &|b| {
+            let avg = b.iter().map(|s| s.humidity).sum::<f32>() / b.len() as f32;
+            char::from_digit(((avg * 10.0) as u32).min(9), 10).unwrap()
+        });
+        out
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Mist: {}s\nFans: {}s\nLights: {:.2} hours\n",
+            self.mist_secs(),
+            self.fan_secs(),
+            self.light_hours()
+        )
+    }
+
+    // Formats the simulation as csv with one row every @step_secs seconds.
+    pub fn csv(&self, step_secs: usize) -> String {
+        let mut out = String::from("time,lights,fans,mist,humidity\n");
+        for s in self.samples.iter().step_by(step_secs.max(1)) {
+            writeln!(
+                out,
+                "{},{},{},{},{:.3}",
+                s.time.strftime("%Y-%m-%dT%H:%M:%S%:z"),
+                s.values.lights,
+                s.values.fans as u8,
+                s.values.mist as u8,
+                s.humidity
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod simulation {
+    use super::*;
+    use jiff::tz::TimeZone;
+    use terralib::types::RelativeHumidity;
+
+    fn run(schedule: &Schedule) -> Simulation {
+        Simulation::run(schedule, Date::constant(2025, 4, 7), TimeZone::UTC).unwrap()
+    }
+
+    #[test]
+    fn reasonable_defaults() {
+        let sim = run(&Schedule::new_with_reasonable_defaults());
+        assert_eq!(sim.samples.len(), 24 * 60 * 60);
+        // Two 60 second mist events. Event times are inclusive at both ends.
+        assert_eq!(sim.mist_secs(), 2 * 61);
+        // 2 minutes every hour from 10:30 to 21:30.
+        assert_eq!(sim.fan_secs(), 12 * 121);
+        // 10am to 10pm, exclusive at both ends.
+        assert_eq!(
+            sim.samples.iter().filter(|s| s.values.lights > 0.0).count(),
+            12 * 3600 - 1
+        );
+
+        let timeline = sim.timeline();
+        let lines: Vec<&str> = timeline.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(
+            lines[0]
+                .trim_start()
+                .starts_with("0           3           6")
+        );
+        assert_eq!(lines[1].len(), "lights  |".len() + 96 + 1);
+        // Lights come on at 70% in the bucket starting at 10:00.
+        assert_eq!(lines[1].find('='), Some("lights  |".len() + 40));
+    }
+
+    #[test]
+    fn dst() {
+        let tz = TimeZone::get("America/Los_Angeles").unwrap();
+        let schedule = Schedule::new_with_reasonable_defaults();
+        let sim = Simulation::run(&schedule, Date::constant(2025, 3, 9), tz.clone()).unwrap();
+        assert_eq!(sim.samples.len(), 23 * 60 * 60);
+        assert!(
+            sim.timeline()
+                .lines()
+                .next()
+                .unwrap()
+                .trim_start()
+                .starts_with("0           4")
+        );
+
+        let sim = Simulation::run(&schedule, Date::constant(2025, 11, 2), tz).unwrap();
+        assert_eq!(sim.samples.len(), 25 * 60 * 60);
+        assert_eq!(
+            sim.samples.last().unwrap().time.to_string(),
+            "2025-11-02T23:59:59-08:00[America/Los_Angeles]"
+        );
+        // The lights are on for the same 10am to 10pm.
+        assert_eq!(
+            sim.samples.iter().filter(|s| s.values.lights > 0.0).count(),
+            12 * 3600 - 1
+        );
+    }
+
+    #[test]
+    fn auto_mist() {
+        let schedule = Schedule {
+            auto_mist_enabled: true,
+            humidity_setpoint: Some(RelativeHumidity::new(0.8).unwrap()),
+            ..Schedule::default()
+        };
+        let sim = run(&schedule);
+        // The model can't stay above the setpoint without misting, so it mists
+        // regularly throughout the day and holds humidity between the bottom
+        // of the hysteresis band and the setpoint.
+        assert!(sim.mist_secs() > 0);
+        for s in &sim.samples[3600..] {
+            assert!(s.humidity > 0.74 && s.humidity < 0.81, "{}", s.humidity);
+        }
+    }
+
+    #[test]
+    fn csv() {
+        let sim = run(&Schedule::new_with_reasonable_defaults());
+        let csv = sim.csv(3600);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 25);
+        assert_eq!(lines[0], "time,lights,fans,mist,humidity");
+        assert_eq!(lines[1], "2025-04-07T00:00:00+00:00,0,0,0,0.650");
+        assert!(lines[12].starts_with("2025-04-07T11:00:00+00:00,0.7,0,1,"));
+    }
+}
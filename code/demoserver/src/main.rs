@@ -4,16 +4,23 @@
 
 use axum::{
     Json, Router,
-    extract::State,
+    body::Bytes,
+    extract::{Path, Query, State},
     http::StatusCode,
-    response::Html,
-    routing::{get, post},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
 };
+use serde::Deserialize;
 use std::sync::{Arc, Mutex};
-use terralib::config::{TerrariumConfig, TerrariumConfigUpdate, WifiDetails};
+use terralib::config::history::HistoryEntrySummary;
+use terralib::config::validation::ValidationReport;
+use terralib::config::{SchedulePreview, TerrariumConfig, TerrariumConfigUpdate, WifiDetails};
 use terralib::controller::TerrariumController;
-use terralib::terrarium::{FakeTerrarium, get_terrarium_state, print_terrarium_info};
-use terralib::types::{ActuatorOverrideSet, TerrariumState};
+use terralib::events::Event;
+use terralib::schema;
+use terralib::storage::{ConfigSource, StorageStatus};
+use terralib::terrarium::{FakeTerrarium, print_terrarium_info};
+use terralib::types::{Actuator, ActuatorOverrideSet, Hostname, OverrideSequence, TerrariumState};
 
 const INDEX_HTML: &str = include_str!("../../esp32/src/oasis/index.html");
 
@@ -32,7 +39,7 @@ async fn main() -> anyhow::Result<()> {
         ssid: "ssid".to_string(),
         password: "password".to_string(),
     });
-    cfg.name = Some("oasis".to_string());
+    cfg.name = Some(Hostname::try_from("oasis")?);
     let controller = Arc::new(Mutex::new(TerrariumController::new(terrarium, cfg)));
 
     print_terrarium_info(&mut *controller.lock().unwrap().terrarium().lock().unwrap());
@@ -41,8 +48,20 @@ async fn main() -> anyhow::Result<()> {
         .route("/", get(root))
         .route("/state", get(state))
         .route("/control", post(control))
+        .route(
+            "/control/sequence",
+            post(start_sequence).delete(cancel_sequence),
+        )
+        .route("/overrides", delete(clear_overrides))
+        .route("/overrides/{actuator}", delete(clear_override))
         .route("/config", post(update_config))
         .route("/config", get(get_config))
+        .route("/config/history", get(config_history))
+        .route("/config/rollback/{id}", post(rollback_config))
+        .route("/schedule/preview", get(schedule_preview))
+        .route("/schema", get(schema))
+        .route("/status", get(status))
+        .route("/events", get(events))
         .with_state(controller);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -58,9 +77,7 @@ async fn root() -> Html<&'static str> {
 async fn state(
     State(controller): State<Arc<Mutex<TerrariumController>>>,
 ) -> Result<Json<TerrariumState>, (StatusCode, String)> {
-    Ok(Json(get_terrarium_state(
-        &mut *controller.lock().unwrap().terrarium().lock().unwrap(),
-    )))
+    Ok(Json(controller.lock().unwrap().state()))
 }
 
 async fn control(
@@ -80,26 +97,184 @@ async fn control(
     StatusCode::OK
 }
 
+async fn start_sequence(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+    Json(sequence): Json<OverrideSequence>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    log::info!("POST /control/sequence called with {sequence:?}");
+    controller
+        .lock()
+        .unwrap()
+        .start_sequence(&sequence)
+        .map(|()| StatusCode::OK)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+async fn cancel_sequence(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if controller.lock().unwrap().cancel_sequence() {
+        Ok(StatusCode::OK)
+    } else {
+        Err((StatusCode::NOT_FOUND, "No sequence is running".to_string()))
+    }
+}
+
+async fn clear_overrides(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    log::info!("DELETE /overrides called");
+    if controller.lock().unwrap().clear_overrides() {
+        Ok(StatusCode::OK)
+    } else {
+        Err((StatusCode::NOT_FOUND, "Nothing is overridden".to_string()))
+    }
+}
+
+async fn clear_override(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+    Path(actuator): Path<Actuator>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    log::info!("DELETE /overrides/{actuator:?} called");
+    if controller.lock().unwrap().clear_override(actuator) {
+        Ok(StatusCode::OK)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            format!("{actuator:?} isn't overridden"),
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct UpdateConfigParams {
+    dry_run: Option<String>,
+}
+
 async fn update_config(
     State(controller): State<Arc<Mutex<TerrariumController>>>,
-    Json(cfg_update): Json<TerrariumConfigUpdate>,
-) -> StatusCode {
-    // TODO: different error codes depending on what happened
+    Query(params): Query<UpdateConfigParams>,
+    body: Bytes,
+) -> Response {
+    // Parsed by hand rather than with the Json extractor so that values that
+    // don't fit their field's type get a validation report like the esp32
+    // sends.
+    let cfg_update = match TerrariumConfigUpdate::from_json(&body) {
+        Ok(cfg_update) => cfg_update,
+        Err(err) => {
+            return match err.downcast::<ValidationReport>() {
+                Ok(report) => (StatusCode::UNPROCESSABLE_ENTITY, Json(report)).into_response(),
+                Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            };
+        }
+    };
     log::info!("POST /config called with {cfg_update:?}");
-    match controller.lock().unwrap().update_config(&cfg_update) {
-        Ok(()) => {}
+    // A dry run reports what the update would change without applying it.
+    let result = if params.dry_run.is_some_and(|v| v != "0") {
+        controller
+            .lock()
+            .unwrap()
+            .dry_run_config_update(&cfg_update)
+            .map(|result| Json(result).into_response())
+    } else {
+        controller
+            .lock()
+            .unwrap()
+            .update_config(&cfg_update)
+            .map(|()| StatusCode::OK.into_response())
+    };
+    match result {
+        Ok(response) => response,
         Err(err) => {
-            // TODO:  include error message in response body
             log::error!("Error handling /config: {err:?}");
-            return StatusCode::BAD_REQUEST;
+            match err.downcast::<ValidationReport>() {
+                Ok(report) => (StatusCode::UNPROCESSABLE_ENTITY, Json(report)).into_response(),
+                Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+            }
         }
     }
-    StatusCode::OK
 }
 
 async fn get_config(
     State(controller): State<Arc<Mutex<TerrariumController>>>,
 ) -> Result<Json<TerrariumConfig>, (StatusCode, String)> {
     log::info!("GET /config called");
-    Ok(Json(controller.lock().unwrap().config().clone()))
+    Ok(Json(controller.lock().unwrap().config().redacted()))
+}
+
+async fn config_history(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+) -> Json<Vec<HistoryEntrySummary>> {
+    log::info!("GET /config/history called");
+    Json(controller.lock().unwrap().history().summaries())
+}
+
+async fn rollback_config(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    log::info!("POST /config/rollback/{id} called");
+    let mut controller = controller.lock().unwrap();
+    if controller.history().get(id).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("no config history entry #{id}"),
+        ));
+    }
+    controller
+        .rollback_config(id)
+        .map(|()| StatusCode::OK)
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+#[derive(Deserialize)]
+struct SchedulePreviewParams {
+    hours: Option<u32>,
+}
+
+async fn schedule_preview(
+    State(controller): State<Arc<Mutex<TerrariumController>>>,
+    Query(params): Query<SchedulePreviewParams>,
+) -> Result<Json<SchedulePreview>, (StatusCode, String)> {
+    log::info!("GET /schedule/preview called");
+    // The lock is released before computing the preview.
+    let request = controller
+        .lock()
+        .unwrap()
+        .schedule_preview(params.hours.unwrap_or(24));
+    request
+        .map(|request| Json(request.compute()))
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+#[derive(Deserialize)]
+struct SchemaParams {
+    name: Option<String>,
+}
+
+async fn schema(
+    Query(params): Query<SchemaParams>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    log::info!("GET /schema called");
+    match params.name {
+        None => Ok(Json(schema::all_schemas())),
+        Some(name) => schema::schema(&name)
+            .map(|schema| Json(schema.to_value()))
+            .ok_or((StatusCode::NOT_FOUND, format!("unknown schema '{name}'"))),
+    }
+}
+
+async fn events(State(controller): State<Arc<Mutex<TerrariumController>>>) -> Json<Vec<Event>> {
+    log::info!("GET /events called");
+    Json(controller.lock().unwrap().events())
+}
+
+// The demoserver doesn't store its config, so it always starts from the
+// defaults.
+async fn status() -> Json<StorageStatus> {
+    log::info!("GET /status called");
+    Json(StorageStatus {
+        config_source: ConfigSource::Defaults,
+        config_error: None,
+    })
 }
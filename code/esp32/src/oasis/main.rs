@@ -19,21 +19,34 @@ use esp_idf_svc::wifi;
 use esp_idf_svc::wifi::{AsyncWifi, EspWifi};
 use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs::EspDefaultNvsPartition};
 use rand::Rng;
-use std::fs::File;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use terralib::cancel_context::CancelContext;
-use terralib::config::{TerrariumConfig, TerrariumConfigUpdate, Update, WifiDetails};
+use terralib::config::history::ConfigHistory;
+use terralib::config::validation::ValidationReport;
+use terralib::config::{TerrariumConfig, TerrariumConfigUpdate, WifiDetails};
 use terralib::controller::{TerrariumController, spin_lock_mutex, terrarium_controller_main_loop};
 use terralib::influxdb;
+use terralib::schema;
+use terralib::storage::{self, ConfigSource, StorageStatus};
 use terralib::terrarium::{get_terrarium_state, print_terrarium_info};
-use terralib::types::{ActuatorOverrideSet, SensorValues, TerrariumState};
+use terralib::types::{
+    Actuator, ActuatorOverrideSet, OverrideSequence, SensorValues, TerrariumState,
+};
 use terrarium::effects;
 use terrarium::real_terrarium::RealTerrarium;
 
 const INDEX_HTML: &str = include_str!("index.html");
 
+// Written with terralib::storage, which keeps the previous good copy as
+// config.json.bak.
 const CONFIG_FILE_PATH: &str = "/oasisdata/config.json";
+// Where a config file is moved when neither it nor its backup can be read, so
+// that it can still be recovered by hand.
+const CONFIG_UNREADABLE_FILE_PATH: &str = "/oasisdata/config.json.unreadable";
+// The last few configs, for rolling back config updates.
+const CONFIG_HISTORY_FILE_PATH: &str = "/oasisdata/config_history.json";
 
 // Need lots of stack to parse JSON
 const HTTP_SERVER_STACK_SIZE: usize = 12240;
@@ -69,19 +82,51 @@ async fn main(spawner: Spawner) {
     // Mount /oasisdata partition and read config file.
     log::info!("Mounting littlefs on /oasisdata partition");
     let _mounted_littlefs = mount_data_partition().expect("Mounting data partition");
-    let cfg = match read_config_file() {
-        Ok(cfg) => {
-            log::info!("Successfully read config file from nvs: {cfg:?}");
-            cfg
+    let (cfg, storage_status) = match read_config_file() {
+        Ok(Some(loaded)) => {
+            log::info!("Successfully read config file from nvs: {:?}", loaded.value);
+            let config_source = if loaded.from_backup {
+                log::warn!(
+                    "Using the backup config file, since the config file couldn't be used: {}",
+                    loaded.error.as_deref().unwrap_or_default()
+                );
+                ConfigSource::Backup
+            } else {
+                ConfigSource::File
+            };
+            let status = StorageStatus {
+                config_source,
+                config_error: loaded.error,
+            };
+            (loaded.value, status)
+        }
+        Ok(None) => {
+            log::info!("No config file found, using default config");
+            let default = TerrariumConfig::new_with_reasonable_defaults();
+            log::info!("Writing default config file to flash memory...");
+            write_config_file(&default).expect("Saving config file should succeed");
+            let status = StorageStatus {
+                config_source: ConfigSource::Defaults,
+                config_error: None,
+            };
+            (default, status)
         }
         Err(err) => {
-            // TODO: differentiate between "not found" and other errors
-            log::warn!("Unable to read config file from nvs: {err}");
-            log::warn!("Using default config");
+            // Move the unreadable file out of the way rather than overwriting
+            // it, so that the old config can still be recovered.
+            log::error!("Unable to read config file from nvs: {err:?}");
+            log::warn!("Moving it to {CONFIG_UNREADABLE_FILE_PATH} and using default config");
+            if let Err(err) = std::fs::rename(CONFIG_FILE_PATH, CONFIG_UNREADABLE_FILE_PATH) {
+                log::error!("Error moving config file: {err}");
+            }
             let default = TerrariumConfig::new_with_reasonable_defaults();
             log::info!("Writing default config file to flash memory...");
             write_config_file(&default).expect("Saving config file should succeed");
-            default
+            let status = StorageStatus {
+                config_source: ConfigSource::Defaults,
+                config_error: Some(format!("{err:#}")),
+            };
+            (default, status)
         }
     };
 
@@ -115,13 +160,17 @@ async fn main(spawner: Spawner) {
         .expect("AsyncWifi should initialize"),
     ));
 
-    let hostname = cfg.name.clone().unwrap_or("oasis".to_string());
+    let hostname = cfg.name.clone().map_or("oasis".to_string(), String::from);
     let cfg_wifi_details = cfg.wifi.clone();
 
     let controller = Arc::new(Mutex::new(TerrariumController::new(
         Arc::new(Mutex::new(terrarium)),
         cfg,
     )));
+    controller
+        .lock()
+        .unwrap()
+        .set_history(read_config_history());
 
     // Initialize mdns service to broadcast the terrarium's hostname on the network.
     let mdns = Arc::new(Mutex::new(
@@ -156,6 +205,8 @@ async fn main(spawner: Spawner) {
     // Setup http server
     let server_configuration = esp_idf_svc::http::server::Configuration {
         stack_size: HTTP_SERVER_STACK_SIZE,
+        // Needed for routes with ids in them, like /config/rollback/{id}
+        uri_match_wildcard: true,
         ..Default::default()
     };
     let mut http_server =
@@ -188,7 +239,7 @@ async fn main(spawner: Spawner) {
 
             let update_data = match serde_json::from_slice::<ActuatorOverrideSet>(&buf) {
                 Err(e) => {
-                    req.into_status_response(413)?
+                    req.into_status_response(400)?
                         .write_all(format!("json parse error: '{e}'").as_bytes())?;
                     return Ok(());
                 }
@@ -208,6 +259,91 @@ async fn main(spawner: Spawner) {
         })
         .expect("Http handler registration should succeed");
 
+    // POST "/control/sequence" starts a sequence of overrides, one step after
+    // another, replacing any sequence that's already running.
+    let ctlref8 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/control/sequence", Method::Post, move |mut req| {
+            let len = req.content_len().unwrap_or(0) as usize;
+
+            if len > MAX_REQUEST_LEN {
+                req.into_status_response(413)?
+                    .write_all("Request too big".as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            let sequence = match serde_json::from_slice::<OverrideSequence>(&buf) {
+                Err(e) => {
+                    req.into_status_response(400)?
+                        .write_all(format!("json parse error: '{e}'").as_bytes())?;
+                    return Ok(());
+                }
+                Ok(s) => s,
+            };
+
+            if let Err(err) = ctlref8.lock().unwrap().start_sequence(&sequence) {
+                req.into_status_response(400)?
+                    .write_all(err.to_string().as_bytes())?;
+            }
+
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // DELETE "/control/sequence" cancels the running sequence, along with the
+    // overrides it applied.
+    let ctlref9 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/control/sequence", Method::Delete, move |req| {
+            log::info!("got DELETE /control/sequence");
+            if !ctlref9.lock().unwrap().cancel_sequence() {
+                req.into_status_response(404)?
+                    .write_all("No sequence is running".as_bytes())?;
+            }
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // DELETE "/overrides" clears every override, and cancels the running
+    // sequence. The overrides themselves are reported by "/state".
+    let ctlref11 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/overrides", Method::Delete, move |req| {
+            log::info!("got DELETE /overrides");
+            if !ctlref11.lock().unwrap().clear_overrides() {
+                req.into_status_response(404)?
+                    .write_all("Nothing is overridden".as_bytes())?;
+            }
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // DELETE "/overrides/{actuator}" clears the override of an actuator, such
+    // as one that's held until cleared.
+    let ctlref10 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/overrides/*", Method::Delete, move |req| {
+            log::info!("got DELETE {}", req.uri());
+            let name = req.uri().trim_start_matches("/overrides/").to_string();
+            let actuator = match serde_json::from_value::<Actuator>(name.clone().into()) {
+                Ok(actuator) => actuator,
+                Err(_) => {
+                    req.into_status_response(400)?
+                        .write_all(format!("invalid actuator: '{name}'").as_bytes())?;
+                    return Ok(());
+                }
+            };
+            if !ctlref10.lock().unwrap().clear_override(actuator) {
+                req.into_status_response(404)?
+                    .write_all(format!("{name} isn't overridden").as_bytes())?;
+            }
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
     // The "/state" route returns the current actuator settings and sensor
     // readings.
     let ctlref2 = controller.clone();
@@ -217,9 +353,7 @@ async fn main(spawner: Spawner) {
 
             // TODO: this can be done better. write bytes directly to resp
             // rather than creating a vec then converting.
-            let js = serde_json::json!(get_terrarium_state(
-                &mut *ctlref2.lock().unwrap().terrarium().lock().unwrap()
-            ));
+            let js = serde_json::json!(ctlref2.lock().unwrap().state());
             let mut bytes: Vec<u8> = Vec::new();
             serde_json::to_writer(&mut bytes, &js).unwrap();
             resp.write(bytes.as_slice())?;
@@ -228,13 +362,70 @@ async fn main(spawner: Spawner) {
         })
         .expect("Http handler registration should succeed");
 
-    // GET "/config" returns the terrarium configuration
+    // GET "/schedule/preview?hours=N" returns the scheduled actuator
+    // transitions over the next N hours (default 24).
+    let ctlref5 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/schedule/preview", Method::Get, move |req| {
+            let hours = match query_param(req.uri(), "hours").map(str::parse::<u32>) {
+                None => 24,
+                Some(Ok(hours)) => hours,
+                Some(Err(e)) => {
+                    req.into_status_response(400)?
+                        .write_all(format!("invalid hours: '{e}'").as_bytes())?;
+                    return Ok(());
+                }
+            };
+
+            // The lock is released before computing the preview.
+            let request = ctlref5.lock().unwrap().schedule_preview(hours);
+            match request.map(|request| request.compute()) {
+                Ok(preview) => {
+                    let mut bytes: Vec<u8> = Vec::new();
+                    serde_json::to_writer(&mut bytes, &preview).unwrap();
+                    req.into_ok_response()?.write_all(bytes.as_slice())?;
+                }
+                Err(err) => {
+                    req.into_status_response(400)?
+                        .write_all(err.to_string().as_bytes())?;
+                }
+            }
+
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // GET "/schema" returns the JSON Schemas for the json accepted and returned
+    // by the api, as an object keyed by type name. GET "/schema?name=<type>"
+    // returns just one of them.
+    http_server
+        .fn_handler::<anyhow::Error, _>("/schema", Method::Get, move |req| {
+            let js = match query_param(req.uri(), "name").map(str::to_string) {
+                None => schema::all_schemas(),
+                Some(name) => match schema::schema(&name) {
+                    Some(schema) => schema.to_value(),
+                    None => {
+                        req.into_status_response(404)?
+                            .write_all(format!("unknown schema '{name}'").as_bytes())?;
+                        return Ok(());
+                    }
+                },
+            };
+            let mut bytes: Vec<u8> = Vec::new();
+            serde_json::to_writer(&mut bytes, &js).unwrap();
+            req.into_ok_response()?.write_all(bytes.as_slice())?;
+
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // GET "/config" returns the terrarium configuration, with secrets redacted
     let ctlref3 = controller.clone();
     http_server
         .fn_handler::<anyhow::Error, _>("/config", Method::Get, move |req| {
             let mut resp = req.into_ok_response()?;
 
-            let js = serde_json::json!(*ctlref3.lock().unwrap().config());
+            let js = serde_json::json!(ctlref3.lock().unwrap().config().redacted());
             let mut bytes: Vec<u8> = Vec::new();
             serde_json::to_writer(&mut bytes, &js).unwrap();
             resp.write(bytes.as_slice())?;
@@ -243,10 +434,87 @@ async fn main(spawner: Spawner) {
         })
         .expect("Http handler registration should succeed");
 
+    // GET "/status" reports whether the config had to be recovered at boot
+    http_server
+        .fn_handler::<anyhow::Error, _>("/status", Method::Get, move |req| {
+            log::info!("got GET /status");
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(&serde_json::to_vec(&storage_status)?)?;
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // GET "/events" lists recent things the controller did on its own, such as
+    // dimming the lights because it got too hot
+    let ctlref7 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/events", Method::Get, move |req| {
+            log::info!("got GET /events");
+            let events = ctlref7.lock().unwrap().events();
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(&serde_json::to_vec(&events)?)?;
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // GET "/config/history" lists the recent configs that can be rolled back to
+    let ctlref5 = controller.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/config/history", Method::Get, move |req| {
+            log::info!("got GET /config/history");
+            let summaries = ctlref5.lock().unwrap().history().summaries();
+            req.into_response(200, None, &[("Content-Type", "application/json")])?
+                .write_all(&serde_json::to_vec(&summaries)?)?;
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
+    // POST "/config/rollback/{id}" goes back to the config in history entry {id}
+    let ctlref6 = controller.clone();
+    let mdnsref = mdns.clone();
+    http_server
+        .fn_handler::<anyhow::Error, _>("/config/rollback/*", Method::Post, move |req| {
+            log::info!("got POST {}", req.uri());
+            let id = match req
+                .uri()
+                .trim_start_matches("/config/rollback/")
+                .parse::<u32>()
+            {
+                Ok(id) => id,
+                Err(err) => {
+                    req.into_status_response(400)?
+                        .write_all(format!("invalid history id: {err}").as_bytes())?;
+                    return Ok(());
+                }
+            };
+
+            let old_config = ctlref6.lock().unwrap().config().clone();
+            let new_config = {
+                let mut ctl = ctlref6.lock().unwrap();
+                if ctl.history().get(id).is_none() {
+                    req.into_status_response(404)?
+                        .write_all(format!("no config history entry #{id}").as_bytes())?;
+                    return Ok(());
+                }
+                ctl.rollback_config(id)?;
+                save_config(&ctl)?
+            };
+            log::info!("Rolled back config to history entry #{id}");
+
+            apply_network_changes(&old_config, &new_config, &mdnsref);
+
+            req.into_ok_response()?;
+            Ok(())
+        })
+        .expect("Http handler registration should succeed");
+
     let ctlref4 = controller.clone();
     http_server
         .fn_handler::<anyhow::Error, _>("/config", Method::Post, move |mut req| {
             log::info!("got POST /config");
+            // A dry run reports what the update would change without applying
+            // it.
+            let dry_run = query_param(req.uri(), "dry_run").is_some_and(|v| v != "0");
             let len = req.content_len().unwrap_or(0) as usize;
 
             if len > MAX_REQUEST_LEN {
@@ -259,51 +527,72 @@ async fn main(spawner: Spawner) {
             let mut buf = vec![0; len];
             req.read_exact(&mut buf)?;
 
-            let cfg_update = match serde_json::from_slice::<TerrariumConfigUpdate>(&buf) {
+            let cfg_update = match TerrariumConfigUpdate::from_json(&buf) {
                 Ok(d) => d,
-                Err(e) => {
-                    req.into_status_response(413)?
-                        .write_all(format!("json parse error: '{e}'").as_bytes())?;
+                // Values that don't fit their field's type are reported the
+                // same way as any other validation problem.
+                Err(e) => match e.downcast::<ValidationReport>() {
+                    Ok(report) => {
+                        req.into_response(422, None, &[("Content-Type", "application/json")])?
+                            .write_all(&serde_json::to_vec(&report)?)?;
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        req.into_status_response(400)?
+                            .write_all(format!("json parse error: '{e}'").as_bytes())?;
+                        return Ok(());
+                    }
+                },
+            };
+
+            let old_config = ctlref4.lock().unwrap().config().clone();
+            let result = if dry_run {
+                ctlref4
+                    .lock()
+                    .unwrap()
+                    .dry_run_config_update(&cfg_update)
+                    .map(Some)
+            } else {
+                ctlref4
+                    .lock()
+                    .unwrap()
+                    .update_config(&cfg_update)
+                    .map(|()| None)
+            };
+            let dry_run_result = match result {
+                Ok(dry_run_result) => dry_run_result,
+                Err(err) => {
+                    log::error!("Error updating config: {}", err);
+                    // Problems with the update itself are reported as json so
+                    // the client can show them next to the offending fields.
+                    match err.downcast::<ValidationReport>() {
+                        Ok(report) => {
+                            let mut bytes: Vec<u8> = Vec::new();
+                            serde_json::to_writer(&mut bytes, &report).unwrap();
+                            req.into_response(422, None, &[("Content-Type", "application/json")])?
+                                .write_all(bytes.as_slice())?;
+                        }
+                        Err(err) => {
+                            req.into_status_response(500)?
+                                .write_all(format!("config update failed: {err}").as_bytes())?;
+                        }
+                    }
                     return Ok(());
                 }
             };
 
-            // See if hostname or wifi creds changed
-            // TODO: compare values of update.wifi and old config.wifi. It might
-            // be a Set, but with the same value as before.
-            let needs_wifi_reset = cfg_update.wifi != Update::NoChange;
-            let needs_hostname_change = cfg_update.name != Update::NoChange;
-
-            if let Err(err) = ctlref4.lock().unwrap().update_config(&cfg_update) {
-                log::error!("Error updating config: {}", err);
-                req.into_status_response(413)?
-                    .write_all(format!("config update rejected: {err}").as_bytes())?;
+            // Nothing was changed, so there's nothing to save or reconfigure.
+            if let Some(dry_run_result) = dry_run_result {
+                req.into_response(200, None, &[("Content-Type", "application/json")])?
+                    .write_all(&serde_json::to_vec(&dry_run_result)?)?;
                 return Ok(());
             }
-            write_config_file(ctlref4.lock().unwrap().config())?;
+
+            let new_config = save_config(&ctlref4.lock().unwrap())?;
 
             log::info!("Successfully updated config via /config http");
 
-            if needs_wifi_reset {
-                // send new wifi details to wifi management task
-                block_on(send_wifi_details(
-                    ctlref4.lock().unwrap().config().wifi.clone(),
-                ));
-            }
-            if needs_hostname_change {
-                let hostname = ctlref4
-                    .lock()
-                    .unwrap()
-                    .config()
-                    .name
-                    .clone()
-                    .unwrap_or("oasis".to_string());
-                mdns.lock()
-                    .unwrap()
-                    .set_hostname(&hostname)
-                    .expect("Setting mdns hostname should succeed");
-                log::info!("Successfully updated mdns hostname to '{}'", hostname)
-            }
+            apply_network_changes(&old_config, &new_config, &mdns);
 
             Ok(())
         })
@@ -366,7 +655,9 @@ async fn reset_button_watcher(
         println!("Reset button pressed!");
 
         // Start breathing leds to indicate that the reset button press was/is registered.
-        spin_lock_mutex(&*controller).await.takeover_lights();
+        spin_lock_mutex(&*controller)
+            .await
+            .takeover_lights_for_effect("breathe");
         let breathe_ctx = Arc::new(CancelContext::new());
         spawner.must_spawn(effects::breathe(
             spin_lock_mutex(&*controller).await.terrarium(),
@@ -626,20 +917,110 @@ fn mount_data_partition() -> anyhow::Result<MountedLittlefs<Littlefs<u8>>> {
     Ok(mounted_littlefs)
 }
 
-fn read_config_file() -> anyhow::Result<TerrariumConfig> {
-    let file = File::open(CONFIG_FILE_PATH)?;
-    let state: TerrariumConfig = serde_json::from_reader(file)?;
-    Ok(state)
+// Returns None if there is no config file yet. If the config file is corrupt,
+// the backup of the previous one is used instead. Configs stored by older
+// firmware are upgraded to the current format, which keeps the original as the
+// backup.
+fn read_config_file() -> anyhow::Result<Option<storage::Loaded<TerrariumConfig>>> {
+    let path = Path::new(CONFIG_FILE_PATH);
+    let Some(loaded) = storage::read(path, TerrariumConfig::from_json)? else {
+        return Ok(None);
+    };
+    let (cfg, version) = loaded.value;
+    if loaded.from_backup {
+        // Replace the unusable config file with the backup right away, so
+        // that the next write backs up a good copy rather than moving the
+        // unusable file over the backup.
+        log::info!("Restoring config file from its backup");
+        storage::write_keep_backup(path, &serde_json::to_vec(&cfg)?)?;
+    } else if version != cfg.version {
+        log::info!(
+            "Upgrading config file from version {version} to {}",
+            cfg.version
+        );
+        write_config_file(&cfg)?;
+    }
+    Ok(Some(storage::Loaded {
+        value: cfg,
+        from_backup: loaded.from_backup,
+        error: loaded.error,
+    }))
 }
 
+// Deletes the config file and config history, along with their backups.
 fn delete_config_file() -> anyhow::Result<()> {
-    Ok(std::fs::remove_file(CONFIG_FILE_PATH)?)
+    storage::remove(Path::new(CONFIG_FILE_PATH))?;
+    storage::remove(Path::new(CONFIG_HISTORY_FILE_PATH))
 }
 
 fn write_config_file(cfg: &TerrariumConfig) -> anyhow::Result<()> {
-    let file = File::create(CONFIG_FILE_PATH)?;
-    serde_json::to_writer(file, cfg)?;
-    Ok(())
+    storage::write(Path::new(CONFIG_FILE_PATH), &serde_json::to_vec(cfg)?)
+}
+
+// Returns an empty history if there isn't a history file or it can't be read,
+// since the history is only a convenience. Configs in the history aren't
+// upgraded like the config file is, so a firmware update that changes the
+// config format also starts a new history.
+fn read_config_history() -> ConfigHistory {
+    match storage::read(Path::new(CONFIG_HISTORY_FILE_PATH), |json| {
+        Ok(serde_json::from_slice(json)?)
+    }) {
+        Ok(Some(loaded)) => loaded.value,
+        Ok(None) => ConfigHistory::default(),
+        Err(err) => {
+            log::warn!("Ignoring unreadable config history file: {err}");
+            ConfigHistory::default()
+        }
+    }
+}
+
+fn write_config_history(history: &ConfigHistory) -> anyhow::Result<()> {
+    storage::write(
+        Path::new(CONFIG_HISTORY_FILE_PATH),
+        &serde_json::to_vec(history)?,
+    )
+}
+
+// Saves the controller's config and config history after the config changed,
+// and returns the new config. Failing to save the history isn't treated as an
+// error, since the config itself was saved.
+fn save_config(controller: &TerrariumController) -> anyhow::Result<TerrariumConfig> {
+    write_config_file(controller.config())?;
+    if let Err(err) = write_config_history(controller.history()) {
+        log::error!("Error saving config history: {err}");
+    }
+    Ok(controller.config().clone())
+}
+
+// Applies changes to the wifi details and hostname when the config changes from
+// @old to @new.
+fn apply_network_changes(
+    old: &TerrariumConfig,
+    new: &TerrariumConfig,
+    mdns: &Mutex<mdns::EspMdns>,
+) {
+    if new.wifi != old.wifi {
+        // send new wifi details to wifi management task
+        block_on(send_wifi_details(new.wifi.clone()));
+    }
+    if new.name != old.name {
+        let hostname = new.name.clone().map_or("oasis".to_string(), String::from);
+        mdns.lock()
+            .unwrap()
+            .set_hostname(&hostname)
+            .expect("Setting mdns hostname should succeed");
+        log::info!("Successfully updated mdns hostname to '{}'", hostname)
+    }
+}
+
+// Returns the value of the query parameter @name in @uri, if present.
+fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+    let (_path, query) = uri.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _value)| *key == name)
+        .map(|(_key, value)| value)
 }
 
 // Temperature conversion.
@@ -1,68 +1,576 @@
+use crate::cron::CronExpr;
+use crate::humidity::HumidityControl;
 use crate::influxdb;
-use crate::types::ActuatorValues;
+use crate::reasons::{ActuatorReason, ActuatorReasons};
+use crate::sun;
+use crate::thermal::ThermalLimits;
+use crate::types::{
+    Actuator, ActuatorValues, BoundedSecs, Hostname, Intensity, RelativeHumidity, RepeatHours,
+};
 use anyhow::anyhow;
-use jiff::civil::Time;
+use jiff::civil::{Date, Time};
+use jiff::tz::TimeZone;
+use jiff::{SignedDuration, Timestamp, Zoned};
+use schemars::JsonSchema;
 use serde;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use validation::{ErrorCode, ValidationReport, field, index};
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub mod diff;
+pub mod history;
+pub mod migration;
+mod toml_format;
+pub mod validation;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct TerrariumConfig {
-    pub name: Option<String>,
+    // Version of the config format. See migration.rs.
+    pub version: u32,
+    pub name: Option<Hostname>,
     pub wifi: Option<WifiDetails>,
-    pub schedule: Option<Schedule>,
+    // Named schedules, such as "normal" or "vacation". Only the schedule of
+    // @active_profile is run. If there is no active profile, nothing is
+    // scheduled.
+    pub profiles: BTreeMap<String, Schedule>,
+    pub active_profile: Option<String>,
     pub timezone: Option<String>,
     pub influxdb: Option<influxdb::Config>,
+    // When to dim the lights to keep the terrarium from overheating. If not
+    // set, the defaults are used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalLimits>,
+}
+
+impl Default for TerrariumConfig {
+    fn default() -> Self {
+        Self {
+            version: migration::CURRENT_VERSION,
+            name: None,
+            wifi: None,
+            profiles: BTreeMap::new(),
+            active_profile: None,
+            timezone: None,
+            influxdb: None,
+            thermal: None,
+        }
+    }
 }
 
+const DEFAULT_TIMEZONE: &str = "America/Los_Angeles";
+
+// The profile that a new terrarium starts with.
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Profiles are stored on the esp32, so keep the number of them reasonable.
+const MAX_PROFILES: usize = 8;
+const MAX_PROFILE_NAME_LEN: usize = 32;
+
+// Stands in for secrets, such as the wifi password, in configs that are sent to
+// clients. Secrets are write-only: sending the placeholder back in an update
+// keeps the stored secret.
+pub const REDACTED: &str = "<redacted>";
+
 impl TerrariumConfig {
     pub fn new_with_reasonable_defaults() -> Self {
         Self {
-            name: Some("oasis".into()),
+            version: migration::CURRENT_VERSION,
+            name: Some(Hostname::try_from("oasis").unwrap()),
             wifi: None,
-            schedule: Some(Schedule::new_with_reasonable_defaults()),
+            profiles: BTreeMap::from([(
+                DEFAULT_PROFILE.to_string(),
+                Schedule::new_with_reasonable_defaults(),
+            )]),
+            active_profile: Some(DEFAULT_PROFILE.to_string()),
             timezone: None,
             influxdb: None,
+            thermal: None,
+        }
+    }
+
+    // Parses a stored config, upgrading it from an older version of the format
+    // if needed. Also returns the version it was stored as.
+    pub fn from_json(json: &[u8]) -> anyhow::Result<(Self, u32)> {
+        Self::from_json_value(serde_json::from_slice(json)?)
+    }
+
+    // Same as from_json(), but for a config written as toml.
+    pub fn from_toml(s: &str) -> anyhow::Result<(Self, u32)> {
+        Self::from_json_value(toml_format::toml_to_json(s)?)
+    }
+
+    fn from_json_value(doc: serde_json::Value) -> anyhow::Result<(Self, u32)> {
+        let (doc, version) = migration::migrate(doc)?;
+        let cfg: Self = serde_json::from_value(doc)?;
+        cfg.validate()?;
+        Ok((cfg, version))
+    }
+
+    // Checks the rules that the config's types can't check on their own, the
+    // same way that updates are checked, so that a stored config that breaks
+    // them isn't loaded.
+    fn validate(&self) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
+        for (name, schedule) in &self.profiles {
+            let path = field("profiles", name);
+            validate_profile_name(name, &path, &mut report);
+            schedule.validate_at(&path, &mut report);
+        }
+        if let Some(thermal) = &self.thermal {
+            thermal.validate_at("thermal", &mut report);
+        }
+        report.into_result()
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        toml_format::json_to_toml(
+            &to_json_value(self)?,
+            "Oasis terrarium config.\n\nTimes are local to the terrarium's timezone. Fields that aren't set are\nleft out.",
+        )
+    }
+
+    // Returns a copy of the config with its secrets replaced by REDACTED. Secrets
+    // that aren't set are left empty, so it's still possible to tell whether
+    // they are.
+    pub fn redacted(&self) -> Self {
+        let redact = |secret: &mut String| {
+            if !secret.is_empty() {
+                *secret = REDACTED.to_string();
+            }
+        };
+        let mut cfg = self.clone();
+        if let Some(wifi) = &mut cfg.wifi {
+            redact(&mut wifi.password);
+        }
+        if let Some(influxdb) = &mut cfg.influxdb {
+            redact(&mut influxdb.token);
+        }
+        cfg
+    }
+
+    // Returns the schedule of the active profile, if any.
+    pub fn active_schedule(&self) -> Option<&Schedule> {
+        self.profiles.get(self.active_profile.as_ref()?)
+    }
+
+    // Applies @update to the config. The update should already have been
+    // validated with TerrariumConfigUpdate::validate(). This returns an error
+    // (a ValidationReport) if the update doesn't make sense for this config,
+    // such as activating a profile that doesn't exist, in which case the config
+    // may be partially updated.
+    pub fn update(&mut self, update: &TerrariumConfigUpdate) -> anyhow::Result<()> {
+        match &update.name {
+            Update::Set(name) => self.name = Some(name.clone()),
+            Update::Clear => self.name = None,
+            Update::NoChange => {}
+        };
+
+        match &update.wifi {
+            Update::Set(wifi) => {
+                let password = updated_secret(
+                    &wifi.password,
+                    self.wifi.as_ref().map(|wifi| &wifi.password),
+                    "wifi.password",
+                )?;
+                self.wifi = Some(WifiDetails {
+                    ssid: wifi.ssid.clone(),
+                    password,
+                });
+            }
+            Update::Clear => self.wifi = None,
+            Update::NoChange => {}
+        };
+
+        // Profiles are renamed first, so that the rest of the update can refer
+        // to them by their new names.
+        for (i, rename) in update.rename_profiles.iter().enumerate() {
+            let path = index("rename_profiles", i);
+            if self.profiles.contains_key(&rename.to) {
+                return Err(ValidationReport::single(
+                    &field(&path, "to"),
+                    ErrorCode::AlreadyExists,
+                    format!(
+                        "Can't rename profile '{}' to '{}', that name is already taken",
+                        rename.from, rename.to
+                    ),
+                )
+                .into());
+            }
+            let schedule = self.profiles.remove(&rename.from).ok_or_else(|| {
+                ValidationReport::single(
+                    &field(&path, "from"),
+                    ErrorCode::NotFound,
+                    format!("Can't rename profile '{}', it doesn't exist", rename.from),
+                )
+            })?;
+            self.profiles.insert(rename.to.clone(), schedule);
+            if self.active_profile.as_ref() == Some(&rename.from) {
+                self.active_profile = Some(rename.to.clone());
+            }
+        }
+
+        for (name, profile_update) in &update.profiles {
+            match profile_update {
+                // Creates the profile if it doesn't exist yet.
+                Update::Set(schedule_update) => {
+                    let schedule = self.profiles.entry(name.clone()).or_default();
+                    schedule.update(schedule_update);
+                    let mut report = ValidationReport::default();
+                    schedule.validate_ramps_at(&field("profiles", name), &mut report);
+                    report.into_result()?;
+                }
+                Update::Clear => {
+                    if self.profiles.remove(name).is_none() {
+                        return Err(ValidationReport::single(
+                            &field("profiles", name),
+                            ErrorCode::NotFound,
+                            format!("Can't delete profile '{name}', it doesn't exist"),
+                        )
+                        .into());
+                    }
+                }
+                Update::NoChange => {}
+            }
+        }
+
+        match &update.active_profile {
+            Update::Set(name) => self.active_profile = Some(name.clone()),
+            Update::Clear => self.active_profile = None,
+            Update::NoChange => {}
+        };
+
+        // @schedule updates the active profile. If there isn't one, it creates
+        // and activates the default profile, like setting the schedule did
+        // before there were profiles.
+        match &update.schedule {
+            Update::Set(schedule_update) => {
+                let name = self
+                    .active_profile
+                    .get_or_insert_with(|| DEFAULT_PROFILE.to_string());
+                let schedule = self.profiles.entry(name.clone()).or_default();
+                schedule.update(schedule_update);
+                let mut report = ValidationReport::default();
+                schedule.validate_ramps_at("schedule", &mut report);
+                report.into_result()?;
+            }
+            Update::Clear => self.active_profile = None,
+            Update::NoChange => {}
+        };
+
+        if let Some(active_profile) = &self.active_profile
+            && !self.profiles.contains_key(active_profile)
+        {
+            return Err(ValidationReport::single(
+                "active_profile",
+                ErrorCode::NotFound,
+                format!("Active profile '{active_profile}' doesn't exist"),
+            )
+            .into());
+        }
+        if self.profiles.len() > MAX_PROFILES {
+            return Err(ValidationReport::single(
+                "profiles",
+                ErrorCode::TooMany,
+                format!("There can be at most {MAX_PROFILES} profiles"),
+            )
+            .into());
+        }
+
+        match &update.timezone {
+            Update::Set(timezone) => self.timezone = Some(timezone.clone()),
+            Update::Clear => self.timezone = None,
+            Update::NoChange => {}
+        }
+
+        match &update.thermal {
+            Update::Set(thermal) => self.thermal = Some(thermal.clone()),
+            Update::Clear => self.thermal = None,
+            Update::NoChange => {}
         }
+
+        match &update.influxdb {
+            Update::Set(influxdb) => {
+                let token = updated_secret(
+                    &influxdb.token,
+                    self.influxdb.as_ref().map(|influxdb| &influxdb.token),
+                    "influxdb.token",
+                )?;
+                self.influxdb = Some(influxdb::Config {
+                    token,
+                    ..influxdb.clone()
+                });
+            }
+            Update::Clear => self.influxdb = None,
+            Update::NoChange => {}
+        };
+
+        Ok(())
+    }
+
+    // Uses the configured timezone if possible, otherwise defaults to US West
+    // Coast time.
+    pub fn get_timezone(&self) -> TimeZone {
+        self.timezone
+            .as_ref()
+            .and_then(|tz_name| {
+                TimeZone::get(tz_name)
+                    .map_err(|err| {
+                        log::warn!("Invalid timezone: '{}', {}", tz_name, err);
+                        err
+                    })
+                    .ok()
+            })
+            .unwrap_or_else(|| {
+                TimeZone::get(DEFAULT_TIMEZONE).expect("Default timezone should always be valid")
+            })
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+// Returns the secret to store when an update sets the one at @path to @new.
+// REDACTED means to keep the @current secret, which only works if there is one.
+fn updated_secret(new: &str, current: Option<&String>, path: &str) -> anyhow::Result<String> {
+    if new != REDACTED {
+        return Ok(new.to_string());
+    }
+    match current {
+        Some(current) if !current.is_empty() => Ok(current.clone()),
+        _ => Err(ValidationReport::single(
+            path,
+            ErrorCode::NotFound,
+            "There's no stored secret to keep, so it has to be sent in full",
+        )
+        .into()),
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct WifiDetails {
     pub ssid: String,
     pub password: String,
 }
 
 // TODO: unify this with ScheduledEvent?
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct TimeRange {
     pub start: Time,
     pub stop: Time,
+    // Days of the week this range applies to. If not specified, it applies
+    // every day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<Vec<Weekday>>,
+}
+
+// Day of the week. Serialized as a three-letter lowercase abbreviation, for
+// example "mon".
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl From<jiff::civil::Weekday> for Weekday {
+    fn from(weekday: jiff::civil::Weekday) -> Self {
+        match weekday {
+            jiff::civil::Weekday::Monday => Weekday::Mon,
+            jiff::civil::Weekday::Tuesday => Weekday::Tue,
+            jiff::civil::Weekday::Wednesday => Weekday::Wed,
+            jiff::civil::Weekday::Thursday => Weekday::Thu,
+            jiff::civil::Weekday::Friday => Weekday::Fri,
+            jiff::civil::Weekday::Saturday => Weekday::Sat,
+            jiff::civil::Weekday::Sunday => Weekday::Sun,
+        }
+    }
+}
+
+// Returns true if @days includes the given weekday. No day restriction (None)
+// means every day.
+fn runs_on(days: &Option<Vec<Weekday>>, weekday: Weekday) -> bool {
+    match days {
+        Some(days) => days.contains(&weekday),
+        None => true,
+    }
 }
 
 // Schedule describes when the lights, fans, and mister should turn on/off
 // throughout the day.
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
 pub struct Schedule {
     pub lights: Option<TimeRange>,
-    pub light_intensity: Option<f32>,
+    pub light_intensity: Option<Intensity>,
+    // Optional ramps at the start and end of the light period. Without them,
+    // the lights go straight from off to @light_intensity and back.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunrise: Option<LightRamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sunset: Option<LightRamp>,
+    // Light intensity keyframes throughout the day. If any are given, they are
+    // used instead of @lights/@light_intensity/@sunrise/@sunset. Intensity is
+    // interpolated linearly between keyframes and wraps around midnight from
+    // the last keyframe back to the first, so this can describe light periods
+    // that cross midnight.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub light_profile: Vec<LightKeyframe>,
+    // If set, the light period follows sunrise and sunset at a real-world
+    // location instead of using @lights. @light_intensity, @sunrise and
+    // @sunset still apply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solar_lights: Option<SolarLights>,
     pub fans: Vec<ScheduledEvent>,
     pub mist: Vec<ScheduledEvent>,
     // If true, the mister should automatically turn on to keep the terrarium's
     // humidity above @humidity_setpoint.
     pub auto_mist_enabled: bool,
-    pub humidity_setpoint: Option<f32>,
+    pub humidity_setpoint: Option<RelativeHumidity>,
+    // How auto-mist holds the humidity at @humidity_setpoint. If not set, the
+    // defaults are used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub humidity_control: Option<HumidityControl>,
+}
+
+// Gradual change of light intensity over @duration_secs. A sunrise ramp starts
+// at the beginning of the light period and a sunset ramp ends at the end of it.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct LightRamp {
+    pub duration_secs: RampSecs,
+    #[serde(default)]
+    pub curve: RampCurve,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RampCurve {
+    #[default]
+    Linear,
+    Sine,
+    Smoothstep,
+}
+
+impl RampCurve {
+    // Maps ramp progress in [0, 1] to a brightness fraction in [0, 1].
+    pub fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            RampCurve::Linear => x,
+            RampCurve::Sine => (1.0 - (x * std::f32::consts::PI).cos()) / 2.0,
+            RampCurve::Smoothstep => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct LightKeyframe {
+    pub time: Time,
+    pub intensity: Intensity,
+}
+
+// A light period that follows the sunrise and sunset times at a real-world
+// location, so that the day length changes with the seasons.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct SolarLights {
+    // Degrees north
+    pub latitude: f64,
+    // Degrees east
+    pub longitude: f64,
+    // Timezone of the location. Sunrise and sunset are converted to wall-clock
+    // times in this timezone and then used as wall-clock times in the
+    // terrarium's timezone. This way, a far-away location's day happens at the
+    // same time of day as it does there. Defaults to the terrarium's timezone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    // Shifts the start/end of the light period relative to the actual
+    // sunrise/sunset. Positive values are later.
+    #[serde(default)]
+    pub sunrise_offset_mins: i32,
+    #[serde(default)]
+    pub sunset_offset_mins: i32,
+    // Limits on the length of the light period. If it falls outside of these,
+    // it is lengthened or shortened equally at both ends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_day_length_mins: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_day_length_mins: Option<u32>,
+}
+
+impl SolarLights {
+    // Returns the start and end of the light period for @date in @tz, or None
+    // if the lights shouldn't come on at all that day.
+    pub fn light_period(&self, date: Date, tz: &TimeZone) -> Option<(Zoned, Zoned)> {
+        let day = sun::solar_day(date, self.latitude, self.longitude);
+        let mut sunrise =
+            day.sunrise() + SignedDuration::from_mins(self.sunrise_offset_mins.into());
+        let mut sunset = day.sunset() + SignedDuration::from_mins(self.sunset_offset_mins.into());
+
+        let day_length = sunrise.duration_until(sunset);
+        let mut clamped = day_length;
+        if let Some(min) = self.min_day_length_mins {
+            clamped = clamped.max(SignedDuration::from_mins(min.into()));
+        }
+        if let Some(max) = self.max_day_length_mins {
+            clamped = clamped.min(SignedDuration::from_mins(max.into()));
+        }
+        if clamped <= SignedDuration::ZERO {
+            return None;
+        }
+        if clamped != day_length {
+            let middle = sunrise + day_length / 2;
+            sunrise = middle - clamped / 2;
+            sunset = middle + clamped / 2;
+        }
+
+        let location_tz = match &self.timezone {
+            Some(name) => TimeZone::get(name)
+                .map_err(|err| log::warn!("Invalid solar_lights timezone: '{name}', {err}"))
+                .unwrap_or_else(|_| tz.clone()),
+            None => tz.clone(),
+        };
+        let to_wall_clock = |ts: Timestamp| {
+            ts.to_zoned(location_tz.clone())
+                .datetime()
+                .to_zoned(tz.clone())
+                .ok()
+        };
+        Some((to_wall_clock(sunrise)?, to_wall_clock(sunset)?))
+    }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+// Light ramps can be at most 12 hours long.
+pub type RampSecs = BoundedSecs<{ 12 * 60 * 60 }>;
+
+// Scheduled events can run for at most a day at a time.
+pub type EventSecs = BoundedSecs<{ 24 * 60 * 60 }>;
+
+const MAX_HUMIDITY_SETPOINT: f32 = 0.95;
+
+// Max number of keyframes allowed in Schedule.light_profile.
+const MAX_LIGHT_KEYFRAMES: usize = 48;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
 pub struct ScheduledEvent {
+    // Ignored if @cron is set.
+    #[serde(default)]
     pub start_time: Time,
-    pub duration_secs: u32,
+    pub duration_secs: EventSecs,
     pub repeat: Option<RepeatInfo>,
+    // Days of the week this event runs on. If not specified, it runs every
+    // day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub days: Option<Vec<Weekday>>,
+    // If set, the event runs for @duration_secs each time this cron
+    // expression fires, instead of at @start_time. Can't be combined with
+    // @repeat or @days. See cron.rs for the supported syntax.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cron: Option<CronExpr>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct RepeatInfo {
-    pub n_hours: u32,
+    pub n_hours: RepeatHours,
     pub stop_time: Time,
 }
 
@@ -75,33 +583,45 @@ impl Schedule {
             lights: Some(TimeRange {
                 start: "10:00".parse().unwrap(),
                 stop: "22:00".parse().unwrap(),
+                days: None,
             }),
-            light_intensity: Some(0.7),
+            light_intensity: Some(Intensity::new(0.7).unwrap()),
+            sunrise: None,
+            sunset: None,
+            light_profile: vec![],
+            solar_lights: None,
             // Mist on for 60 seconds at 11am and again at 4pm.
             mist: vec![
                 ScheduledEvent {
                     start_time: "11:00".parse().unwrap(),
-                    duration_secs: 60,
+                    duration_secs: BoundedSecs::new(60).unwrap(),
                     repeat: None,
+                    days: None,
+                    cron: None,
                 },
                 ScheduledEvent {
                     start_time: "16:00".parse().unwrap(),
-                    duration_secs: 60,
+                    duration_secs: BoundedSecs::new(60).unwrap(),
                     repeat: None,
+                    days: None,
+                    cron: None,
                 },
             ],
             // Fans on for 2 minutes every hour during daylight hours. The fans
             // are intentionally set to come on while mist is *not* on.
             fans: vec![ScheduledEvent {
                 start_time: "10:30".parse().unwrap(),
-                duration_secs: 2 * 60,
+                duration_secs: BoundedSecs::new(2 * 60).unwrap(),
                 repeat: Some(RepeatInfo {
-                    n_hours: 1,
+                    n_hours: RepeatHours::new(1).unwrap(),
                     stop_time: "22:00".parse().unwrap(),
                 }),
+                days: None,
+                cron: None,
             }],
             auto_mist_enabled: false,
             humidity_setpoint: None,
+            humidity_control: None,
         }
     }
 
@@ -118,6 +638,30 @@ impl Schedule {
             Update::NoChange => {}
         }
 
+        match &update.sunrise {
+            Update::Set(sunrise) => self.sunrise = Some(sunrise.clone()),
+            Update::Clear => self.sunrise = None,
+            Update::NoChange => {}
+        }
+
+        match &update.sunset {
+            Update::Set(sunset) => self.sunset = Some(sunset.clone()),
+            Update::Clear => self.sunset = None,
+            Update::NoChange => {}
+        }
+
+        match &update.light_profile {
+            Update::Set(light_profile) => self.light_profile = light_profile.clone(),
+            Update::Clear => self.light_profile = vec![],
+            Update::NoChange => {}
+        }
+
+        match &update.solar_lights {
+            Update::Set(solar_lights) => self.solar_lights = Some(solar_lights.clone()),
+            Update::Clear => self.solar_lights = None,
+            Update::NoChange => {}
+        }
+
         match &update.fans {
             Update::Set(fans) => self.fans = fans.clone(),
             Update::Clear => self.fans = vec![],
@@ -141,53 +685,374 @@ impl Schedule {
             Update::Clear => self.humidity_setpoint = None,
             Update::NoChange => {}
         }
+
+        match &update.humidity_control {
+            Update::Set(humidity_control) => self.humidity_control = Some(humidity_control.clone()),
+            Update::Clear => self.humidity_control = None,
+            Update::NoChange => {}
+        }
+    }
+
+    pub fn evaluate(&self, now: &Zoned) -> ActuatorValues {
+        self.evaluate_with_reasons(now).0
     }
 
-    pub fn evaluate(&self, t: Time) -> ActuatorValues {
+    // Like evaluate(), but also returns which part of the schedule set each
+    // actuator.
+    pub fn evaluate_with_reasons(&self, now: &Zoned) -> (ActuatorValues, ActuatorReasons) {
         let mut v = ActuatorValues::default();
+        let mut reasons = ActuatorReasons::default();
+        let t = now.time();
 
-        if let Some(lights) = &self.lights {
+        if !self.light_profile.is_empty() {
+            v.lights = evaluate_light_profile(&self.light_profile, t);
+            reasons.lights = ActuatorReason::LightProfile;
+        } else if let Some(solar_lights) = &self.solar_lights {
+            if let Some(intensity) = self.light_intensity {
+                v.lights = intensity.get() * self.solar_light_factor(solar_lights, now);
+                if v.lights > 0.0 {
+                    reasons.lights = ActuatorReason::SolarLights;
+                }
+            }
+        } else if let Some(lights) = &self.lights {
             if let Some(intensity) = self.light_intensity {
-                if lights.start < t && t < lights.stop {
-                    v.lights = intensity;
+                if runs_on(&lights.days, now.weekday().into())
+                    && lights.start < t
+                    && t < lights.stop
+                {
+                    v.lights = intensity.get()
+                        * self.ramp_factor(
+                            lights.start.duration_until(t),
+                            t.duration_until(lights.stop),
+                        );
+                    reasons.lights = ActuatorReason::LightTimeRange {
+                        start: lights.start,
+                        stop: lights.stop,
+                    };
                 }
             }
         }
 
         // note: the auto mist feature is handled by the controller, not the
         // schedule.
-        v.mist = evaluate_scheduled_events(&self.mist, t);
-        v.fans = evaluate_scheduled_events(&self.fans, t);
+        for (on, reason, events) in [
+            (&mut v.mist, &mut reasons.mist, &self.mist),
+            (&mut v.fans, &mut reasons.fans, &self.fans),
+        ] {
+            if let Some((index, start_time)) = active_scheduled_event(events, now) {
+                *on = true;
+                *reason = ActuatorReason::ScheduleEvent { index, start_time };
+            }
+        }
+
+        (v, reasons)
+    }
+
+    // Returns the fraction of full intensity the lights should be at during
+    // the sunrise and sunset ramps, or 1.0 outside of them. @elapsed is the time
+    // since the start of the light period and @remaining is the time until it
+    // ends.
+    fn ramp_factor(&self, elapsed: SignedDuration, remaining: SignedDuration) -> f32 {
+        let elapsed = elapsed.as_secs_f32();
+        let remaining = remaining.as_secs_f32();
+        let mut factor: f32 = 1.0;
+        if let Some(sunrise) = &self.sunrise {
+            let duration = sunrise.duration_secs.get() as f32;
+            if duration > 0.0 && elapsed < duration {
+                factor = factor.min(sunrise.curve.apply(elapsed / duration));
+            }
+        }
+        if let Some(sunset) = &self.sunset {
+            let duration = sunset.duration_secs.get() as f32;
+            if duration > 0.0 && remaining < duration {
+                factor = factor.min(sunset.curve.apply(remaining / duration));
+            }
+        }
+
+        // Round to 0.1% steps. The controller re-applies the lights whenever
+        // the value changes, so this keeps it from re-fading the leds on every
+        // iteration of its loop during a ramp.
+        (factor * 1000.0).round() / 1000.0
+    }
+
+    fn solar_light_factor(&self, solar_lights: &SolarLights, now: &Zoned) -> f32 {
+        // The light period can cross midnight in the terrarium's timezone, so
+        // yesterday's period might still be going.
+        let today = now.date();
+        for date in [today.yesterday().ok(), Some(today)].into_iter().flatten() {
+            if let Some((start, stop)) = solar_lights.light_period(date, now.time_zone())
+                && start < *now
+                && *now < stop
+            {
+                return self.ramp_factor(start.duration_until(now), now.duration_until(&stop));
+            }
+        }
+        0.0
+    }
+}
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
 
-        v
+// Linearly interpolates between the keyframes surrounding @t. @keyframes must
+// be non-empty and sorted by time.
+fn evaluate_light_profile(keyframes: &[LightKeyframe], t: Time) -> f32 {
+    // The keyframe at or before @t, wrapping around to the last keyframe of the
+    // previous day if needed. Same for the next keyframe.
+    let prev = keyframes
+        .iter()
+        .rev()
+        .find(|k| k.time <= t)
+        .unwrap_or(&keyframes[keyframes.len() - 1]);
+    let next = keyframes
+        .iter()
+        .find(|k| k.time > t)
+        .unwrap_or(&keyframes[0]);
+
+    let span = prev
+        .time
+        .duration_until(next.time)
+        .as_secs()
+        .rem_euclid(SECS_PER_DAY);
+    if span == 0 {
+        // There is only one keyframe.
+        return prev.intensity.get();
     }
+    let elapsed = prev
+        .time
+        .duration_until(t)
+        .as_secs()
+        .rem_euclid(SECS_PER_DAY);
+    let frac = elapsed as f32 / span as f32;
+    let intensity = prev.intensity.get() + (next.intensity.get() - prev.intensity.get()) * frac;
+
+    // See the comment in Schedule::ramp_factor() about rounding.
+    (intensity * 1000.0).round() / 1000.0
 }
 
-fn evaluate_scheduled_events(events: &Vec<ScheduledEvent>, t: Time) -> bool {
-    for event in events {
+// Returns the index of the first of @events that's running at @now, along with
+// when the occurrence of it that's running started.
+fn active_scheduled_event(events: &[ScheduledEvent], now: &Zoned) -> Option<(usize, Time)> {
+    let t = now.time();
+    let weekday = now.weekday().into();
+    for (index, event) in events.iter().enumerate() {
+        if let Some(cron) = &event.cron {
+            if let Some(fired) = cron.active_since(now, event.duration_secs.get()) {
+                return Some((index, fired.time()));
+            }
+            continue;
+        }
+
+        if !runs_on(&event.days, weekday) {
+            continue;
+        }
+
         let mut start_time = event.start_time;
-        let event_duration = std::time::Duration::from_secs(event.duration_secs.into());
+        let event_duration = std::time::Duration::from_secs(event.duration_secs.get().into());
         let mut end_time = start_time + event_duration;
         if start_time <= t && t <= end_time {
-            return true;
+            return Some((index, start_time));
         }
 
         if let Some(repeat) = &event.repeat {
             loop {
-                start_time += std::time::Duration::from_hours(repeat.n_hours.into());
-                if start_time > repeat.stop_time {
-                    break;
-                }
+                // Repeats don't wrap around past midnight.
+                start_time = match start_time
+                    .checked_add(SignedDuration::from_hours(repeat.n_hours.get().into()))
+                {
+                    Ok(next) if next <= repeat.stop_time => next,
+                    _ => break,
+                };
                 end_time = start_time + event_duration;
 
                 if start_time <= t && t <= end_time {
-                    return true;
+                    return Some((index, start_time));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+// The schedule's actuator values over a window of time, for showing what's
+// coming up.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct SchedulePreview {
+    pub start: Zoned,
+    pub end: Zoned,
+    // Actuator values at @start.
+    pub initial: ActuatorValues,
+    pub transitions: Vec<ScheduleTransition>,
+}
+
+// Everything needed to compute a `SchedulePreview`. This is a copy of the
+// schedule so that the preview can be computed without holding the
+// controller's lock.
+#[derive(Clone, Debug)]
+pub struct SchedulePreviewRequest {
+    pub schedule: Schedule,
+    pub start: Zoned,
+    pub duration: SignedDuration,
+}
+
+impl SchedulePreviewRequest {
+    pub fn compute(&self) -> SchedulePreview {
+        self.schedule.preview(&self.start, self.duration)
+    }
+}
+
+// An actuator turning on or off. For the lights, only turning on or off counts,
+// not changes in intensity such as during a sunrise ramp.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ScheduleTransition {
+    pub time: Zoned,
+    pub actuator: Actuator,
+    pub on: bool,
+}
+
+impl Schedule {
+    // Lists every actuator on/off transition from @start until @duration
+    // later, to the second, which is the resolution of all of the schedule's
+    // times. Rather than evaluating every second, the schedule is evaluated at
+    // the checkpoints from preview_checkpoints(), and where an actuator
+    // differs between two checkpoints, the second it changed is found by
+    // bisection.
+    pub fn preview(&self, start: &Zoned, duration: SignedDuration) -> SchedulePreview {
+        let start = start
+            .with()
+            .subsec_nanosecond(0)
+            .build()
+            .unwrap_or_else(|_| start.clone());
+        let end = start.saturating_add(duration);
+        let initial = self.evaluate(&start);
+
+        let mut transitions = vec![];
+        let mut prev = (start.clone(), initial);
+        for t in self.preview_checkpoints(&start, &end) {
+            let cur = self.evaluate(&t);
+            for actuator in [Actuator::Lights, Actuator::Fans, Actuator::Mist] {
+                let is_on = cur.is_on(actuator);
+                if prev.1.is_on(actuator) != is_on {
+                    transitions.push(ScheduleTransition {
+                        time: self.find_transition(actuator, is_on, &prev.0, &t),
+                        actuator,
+                        on: is_on,
+                    });
+                }
+            }
+            prev = (t, cur);
+        }
+        // Transitions of different actuators between the same two checkpoints
+        // aren't necessarily found in order. The sort is stable so that
+        // transitions at the same time stay in actuator order.
+        transitions.sort_by(|a, b| a.time.cmp(&b.time));
+
+        SchedulePreview {
+            start,
+            end,
+            initial,
+            transitions,
+        }
+    }
+
+    // Returns the times in (@start, @end] where the schedule is evaluated for
+    // a preview, in order. These are the start of every minute, which is
+    // where cron events start, plus the times of day where events, light
+    // periods and light keyframes start and end. Between two checkpoints, each
+    // actuator changes at most once, which preview() relies on. Solar light
+    // periods are hours long, so the minute checkpoints cover them.
+    fn preview_checkpoints(&self, start: &Zoned, end: &Zoned) -> Vec<Zoned> {
+        let mut times_of_day = vec![];
+        if self.light_profile.is_empty() {
+            if let Some(lights) = &self.lights {
+                times_of_day.extend([lights.start, lights.stop]);
+            }
+        } else {
+            times_of_day.extend(self.light_profile.iter().map(|k| k.time));
+        }
+        for event in self.fans.iter().chain(self.mist.iter()) {
+            if event.cron.is_some() {
+                continue;
+            }
+            // Mirrors the repeats in active_scheduled_event(). The 0..=24 bound
+            // is what limits the loop: it's enough for an event repeating
+            // every hour from midnight, and ends the loop even if the repeats
+            // never pass their stop time.
+            let duration = std::time::Duration::from_secs(event.duration_secs.get().into());
+            let mut event_start = event.start_time;
+            for _ in 0..=24 {
+                times_of_day.extend([event_start, event_start + duration]);
+                let Some(repeat) = &event.repeat else {
+                    break;
+                };
+                event_start = match event_start
+                    .checked_add(SignedDuration::from_hours(repeat.n_hours.get().into()))
+                {
+                    Ok(next) if next <= repeat.stop_time => next,
+                    _ => break,
+                };
+            }
+        }
+
+        // Events are on for their last second and lights are off for their
+        // first, so the second after each time of day matters too.
+        let mut secs = std::collections::BTreeSet::new();
+        let start_secs = start.timestamp().as_second();
+        let end_secs = end.timestamp().as_second();
+        let mut date = start.date().yesterday().unwrap_or(start.date());
+        while date <= end.date() {
+            for time in &times_of_day {
+                // A time of day happens twice when the clocks go back.
+                let t = start
+                    .time_zone()
+                    .to_ambiguous_timestamp(date.to_datetime(*time));
+                for t in [t.earlier(), t.later()].into_iter().flatten() {
+                    secs.extend([t.as_second(), t.as_second() + 1]);
                 }
             }
+            match date.tomorrow() {
+                Ok(next) => date = next,
+                Err(_) => break,
+            }
         }
+        let first_minute = start_secs - start_secs.rem_euclid(60) + 60;
+        secs.extend((first_minute..end_secs).step_by(60));
+        secs.insert(end_secs);
+
+        secs.range(start_secs + 1..=end_secs)
+            .filter_map(|&s| Timestamp::from_second(s).ok())
+            .map(|t| t.to_zoned(start.time_zone().clone()))
+            .collect()
+    }
+
+    // Returns the first second in (@before, @after] at which @actuator is
+    // @on, given that it isn't at @before and is at @after.
+    fn find_transition(
+        &self,
+        actuator: Actuator,
+        on: bool,
+        before: &Zoned,
+        after: &Zoned,
+    ) -> Zoned {
+        let (mut lo, mut hi) = (before.clone(), after.clone());
+        while hi.timestamp().as_second() - lo.timestamp().as_second() > 1 {
+            let half = (hi.timestamp().as_second() - lo.timestamp().as_second()) / 2;
+            let mid = lo.saturating_add(SignedDuration::from_secs(half));
+            if self.evaluate(&mid).is_on(actuator) == on {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        hi
     }
+}
 
-    false
+// Converts @v to a json value. This goes through a string rather than using
+// serde_json::to_value() so that f32s don't pick up extra digits when widened
+// to f64 (0.7 rather than 0.699999988079071).
+fn to_json_value<T: Serialize>(v: &T) -> anyhow::Result<serde_json::Value> {
+    Ok(serde_json::from_str(&serde_json::to_string(v)?)?)
 }
 
 // This type is very similar to `TerrariumConfig`, but is used for specifying
@@ -205,21 +1070,39 @@ fn evaluate_scheduled_events(events: &Vec<ScheduledEvent>, t: Time) -> bool {
 // - json field has a value -> Update::Set
 // - json field is null -> Update::Clear
 // - json field is not present -> Update::NoChange
-#[derive(Default, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Default, Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct TerrariumConfigUpdate {
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
-    pub name: Update<String>,
+    pub name: Update<Hostname>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub wifi: Update<WifiDetails>,
+    // Updates the schedule of the active profile.
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub schedule: Update<ScheduleUpdate>,
+    // Creates, updates, or deletes (with null) profiles by name. Creating a
+    // profile starts from an empty schedule.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Update<ScheduleUpdate>>,
+    // Applied in order, before any other profile changes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rename_profiles: Vec<ProfileRename>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub active_profile: Update<String>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub timezone: Update<String>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub influxdb: Update<influxdb::Config>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub thermal: Update<ThermalLimits>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct ProfileRename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(untagged)]
 #[derive(Default)]
 pub enum Update<T> {
@@ -238,13 +1121,49 @@ impl<T> Update<T> {
     }
 }
 
+// Deserializing through Option rather than deriving an untagged Deserialize
+// keeps the error from T, which says what's wrong with the value, instead of
+// replacing it with "data did not match any variant".
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Update<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<T>::deserialize(deserializer).map(Update::from)
+    }
+}
+
+// The derived schema would just be "T or null", so this is written by hand to
+// spell out what leaving the field out and setting it to null each mean.
+impl<T: JsonSchema> JsonSchema for Update<T> {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        format!("Update_{}", T::schema_name()).into()
+    }
+
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn json_schema(generator: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "Leave out to keep the current value, set to null to clear it, or set to a new value.",
+            "anyOf": [generator.subschema_for::<T>(), {"type": "null"}],
+        })
+    }
+}
+
 // See comment for TerrariumConfigUpdate.
-#[derive(Default, Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Default, Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub struct ScheduleUpdate {
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub lights: Update<TimeRange>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
-    pub light_intensity: Update<f32>,
+    pub light_intensity: Update<Intensity>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub sunrise: Update<LightRamp>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub sunset: Update<LightRamp>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub light_profile: Update<Vec<LightKeyframe>>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub solar_lights: Update<SolarLights>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub fans: Update<Vec<ScheduledEvent>>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
@@ -252,127 +1171,428 @@ pub struct ScheduleUpdate {
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
     pub auto_mist_enabled: Update<bool>,
     #[serde(default, skip_serializing_if = "Update::is_no_change")]
-    pub humidity_setpoint: Update<f32>,
+    pub humidity_setpoint: Update<RelativeHumidity>,
+    #[serde(default, skip_serializing_if = "Update::is_no_change")]
+    pub humidity_control: Update<HumidityControl>,
 }
 
 impl TerrariumConfigUpdate {
-    pub fn validate(&self) -> anyhow::Result<()> {
+    // Parses an update received as json. Values that are the wrong type or out
+    // of range are returned as a ValidationReport pointing at the offending
+    // field, while json that doesn't parse at all is a plain error.
+    pub fn from_json(data: &[u8]) -> anyhow::Result<Self> {
+        let de = &mut serde_json::Deserializer::from_slice(data);
+        serde_path_to_error::deserialize(de).map_err(|err| {
+            let inner = err.inner();
+            if inner.classify() != serde_json::error::Category::Data {
+                return anyhow!("{inner}");
+            }
+            // The path of the root object is ".".
+            let path = match err.path().to_string().as_str() {
+                "." => String::new(),
+                path => path.to_string(),
+            };
+            // Drop the line/column suffix, since the path already says where
+            // the problem is.
+            let message = inner.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            };
+            ValidationReport::single(&path, ErrorCode::InvalidValue, message).into()
+        })
+    }
+
+    // Parses an update written as toml. Since toml has no null, fields can't be
+    // cleared this way.
+    pub fn from_toml(s: &str) -> anyhow::Result<Self> {
+        Ok(serde_json::from_value(toml_format::toml_to_json(s)?)?)
+    }
+
+    // Fails if the update clears any fields, since that can't be expressed in
+    // toml.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let toml = toml_format::json_to_toml(
+            &to_json_value(self)?,
+            "Oasis terrarium config update.\n\nFields that are left out aren't changed, and fields that are given replace\nthe current value, including whole lists such as fans.",
+        )?;
+        // Clears get dropped along with all of the other nulls, so the only way
+        // to tell them apart from fields that are None is to read it back.
+        if Self::from_toml(&toml)? != *self {
+            return Err(anyhow!(
+                "update clears fields, which can't be represented in toml"
+            ));
+        }
+        Ok(toml)
+    }
+
+    // Checks for problems with the update that don't depend on the current
+    // config. The report includes every problem found, not just the first.
+    pub fn validate(&self) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
         if let Update::Set(schedule_update) = &self.schedule {
-            schedule_update.validate()?;
+            schedule_update.validate_at("schedule", &mut report);
         }
-        if let Update::Set(name) = &self.name {
-            if name.len() > 30 {
-                return Err(anyhow!("Name too long"));
-            }
-            if name.len() == 0 {
-                return Err(anyhow!("Name can not be empty string"));
+        for (name, profile_update) in &self.profiles {
+            let path = field("profiles", name);
+            validate_profile_name(name, &path, &mut report);
+            if let Update::Set(schedule_update) = profile_update {
+                schedule_update.validate_at(&path, &mut report);
             }
-            // TODO: name should be a valid domain name identifier
+        }
+        for (i, rename) in self.rename_profiles.iter().enumerate() {
+            let path = field(&index("rename_profiles", i), "to");
+            validate_profile_name(&rename.to, &path, &mut report);
+        }
+        if let Update::Set(active_profile) = &self.active_profile {
+            validate_profile_name(active_profile, "active_profile", &mut report);
         }
         if let Update::Set(timezone) = &self.timezone {
-            let _tz = jiff::tz::TimeZone::get(timezone)?;
+            validate_timezone(timezone, "timezone", &mut report);
+        }
+        if let Update::Set(thermal) = &self.thermal {
+            thermal.validate_at("thermal", &mut report);
+        }
+        report.into_result()
+    }
+}
+
+impl<T> From<Option<T>> for Update<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => Update::Set(value),
+            None => Update::Clear,
+        }
+    }
+}
+
+// An update that replaces every field with the corresponding one of @schedule.
+impl From<&Schedule> for ScheduleUpdate {
+    fn from(schedule: &Schedule) -> Self {
+        let schedule = schedule.clone();
+        Self {
+            lights: schedule.lights.into(),
+            light_intensity: schedule.light_intensity.into(),
+            sunrise: schedule.sunrise.into(),
+            sunset: schedule.sunset.into(),
+            light_profile: Update::Set(schedule.light_profile),
+            solar_lights: schedule.solar_lights.into(),
+            fans: Update::Set(schedule.fans),
+            mist: Update::Set(schedule.mist),
+            auto_mist_enabled: Update::Set(schedule.auto_mist_enabled),
+            humidity_setpoint: schedule.humidity_setpoint.into(),
+            humidity_control: schedule.humidity_control.into(),
         }
-        Ok(())
     }
 }
 
 impl ScheduleUpdate {
-    // TODO: can we do validation at deserialization time instead? It'd be nice
-    // if it was impossible to build an invalid ScheduleUpdate in the first
-    // place.
-    pub fn validate(&self) -> anyhow::Result<()> {
+    // Checks the rules that involve more than one value, such as times that
+    // have to be in order. Single values, like intensities, are checked by
+    // their types when they're deserialized. Stored schedules are checked the
+    // same way when they're loaded, see Schedule::validate_at().
+    pub fn validate(&self) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::default();
+        self.validate_at("", &mut report);
+        report.into_result()
+    }
+
+    // Adds any problems with the update to @report. @path is the path of the
+    // update within the config update.
+    fn validate_at(&self, path: &str, report: &mut ValidationReport) {
         if let Update::Set(mist) = &self.mist {
-            validate_scheduled_events(mist)?;
+            validate_scheduled_events(mist, &field(path, "mist"), report);
         }
         if let Update::Set(fans) = &self.fans {
-            validate_scheduled_events(fans)?;
+            validate_scheduled_events(fans, &field(path, "fans"), report);
         }
 
         if let Update::Set(lights) = &self.lights {
+            let lights_path = field(path, "lights");
             if lights.stop <= lights.start {
-                return Err(anyhow!("light start time should be before stop time"));
+                report.add(
+                    &field(&lights_path, "stop"),
+                    ErrorCode::InvalidOrder,
+                    "light start time should be before stop time",
+                );
             }
+            validate_days(&lights.days, &field(&lights_path, "days"), report);
         }
 
-        if let Update::Set(light_intensity) = self.light_intensity {
-            if !(0.0..=1.0).contains(&light_intensity) {
-                return Err(anyhow!(
-                    "light_intensity must be between 0.0 and 1.0, got {}",
-                    light_intensity
-                ));
-            }
+        if let Update::Set(light_profile) = &self.light_profile {
+            validate_light_profile(light_profile, &field(path, "light_profile"), report);
         }
 
-        if let Update::Set(humidity_setpoint) = self.humidity_setpoint {
-            if !(0.0..0.95).contains(&humidity_setpoint) {
-                return Err(anyhow!(
-                    "humidity_setpoint must be between 0.0 and 0.95, got {}",
-                    humidity_setpoint
-                ));
-            }
+        if let Update::Set(solar_lights) = &self.solar_lights {
+            solar_lights.validate_at(&field(path, "solar_lights"), report);
         }
 
-        Ok(())
+        // Any relative humidity can be represented, but the terrarium can't
+        // get much above this, so auto-mist would never turn off.
+        if let Update::Set(humidity_setpoint) = self.humidity_setpoint
+            && humidity_setpoint.get() >= MAX_HUMIDITY_SETPOINT
+        {
+            report.add(
+                &field(path, "humidity_setpoint"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "humidity_setpoint must be less than {MAX_HUMIDITY_SETPOINT}, got {humidity_setpoint}"
+                ),
+            );
+        }
+
+        if let Update::Set(humidity_control) = &self.humidity_control {
+            humidity_control.validate_at(&field(path, "humidity_control"), report);
+        }
+    }
+}
+
+impl Schedule {
+    // Adds any problems with the schedule to @report, the same as for an
+    // update that sets all of its fields. @path is the path of the schedule
+    // within the config.
+    fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        ScheduleUpdate::from(self).validate_at(path, report);
+        self.validate_ramps_at(path, report);
+    }
+
+    // Makes sure that the sunrise and sunset ramps fit within the light
+    // period. The ramps and the lights can be updated separately, so this is
+    // checked once they've been merged.
+    fn validate_ramps_at(&self, path: &str, report: &mut ValidationReport) {
+        if let (Some(lights), Some(sunrise), Some(sunset)) =
+            (&self.lights, &self.sunrise, &self.sunset)
+        {
+            let period_secs = lights.start.duration_until(lights.stop).as_secs();
+            if i64::from(sunrise.duration_secs.get()) + i64::from(sunset.duration_secs.get())
+                > period_secs
+            {
+                report.add(
+                    &field(path, "lights"),
+                    ErrorCode::Conflict,
+                    "sunrise and sunset ramps are longer than the light period",
+                );
+            }
+        }
     }
 }
 
 impl ScheduledEvent {
-    fn validate(&self) -> anyhow::Result<()> {
-        if let Some(repeat) = &self.repeat {
-            if self.start_time >= repeat.stop_time {
-                return Err(anyhow!("Stop time must be after start time"));
+    fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        if let Some(repeat) = &self.repeat
+            && self.start_time >= repeat.stop_time
+        {
+            report.add(
+                &field(&field(path, "repeat"), "stop_time"),
+                ErrorCode::InvalidOrder,
+                "Stop time must be after start time",
+            );
+        }
+        validate_days(&self.days, &field(path, "days"), report);
+        if self.cron.is_some() && (self.repeat.is_some() || self.days.is_some()) {
+            report.add(
+                &field(path, "cron"),
+                ErrorCode::Conflict,
+                "cron events can't also have repeat or days. Use the cron expression instead",
+            );
+        }
+    }
+}
+
+impl SolarLights {
+    fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            report.add(
+                &field(path, "latitude"),
+                ErrorCode::OutOfRange,
+                format!("latitude must be between -90 and 90, got {}", self.latitude),
+            );
+        }
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            report.add(
+                &field(path, "longitude"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "longitude must be between -180 and 180, got {}",
+                    self.longitude
+                ),
+            );
+        }
+        if let Some(timezone) = &self.timezone {
+            validate_timezone(timezone, &field(path, "timezone"), report);
+        }
+        for (name, offset) in [
+            ("sunrise_offset_mins", self.sunrise_offset_mins),
+            ("sunset_offset_mins", self.sunset_offset_mins),
+        ] {
+            if offset.abs() > 12 * 60 {
+                report.add(
+                    &field(path, name),
+                    ErrorCode::OutOfRange,
+                    format!("sunrise/sunset offsets can be at most 12 hours, got {offset} minutes"),
+                );
             }
         }
-        Ok(())
+        for (name, length) in [
+            ("min_day_length_mins", self.min_day_length_mins),
+            ("max_day_length_mins", self.max_day_length_mins),
+        ] {
+            if let Some(length) = length
+                && length > 24 * 60
+            {
+                report.add(
+                    &field(path, name),
+                    ErrorCode::OutOfRange,
+                    format!("day length limits can be at most 24 hours, got {length} minutes"),
+                );
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_day_length_mins, self.max_day_length_mins)
+            && min > max
+        {
+            report.add(
+                &field(path, "min_day_length_mins"),
+                ErrorCode::Conflict,
+                "min_day_length_mins must not be greater than max_day_length_mins",
+            );
+        }
+    }
+}
+
+fn validate_profile_name(name: &str, path: &str, report: &mut ValidationReport) {
+    if name.is_empty() || name.len() > MAX_PROFILE_NAME_LEN {
+        report.add(
+            path,
+            if name.is_empty() {
+                ErrorCode::Empty
+            } else {
+                ErrorCode::TooLong
+            },
+            format!("Profile names must be 1-{MAX_PROFILE_NAME_LEN} characters long, got '{name}'"),
+        );
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        report.add(
+            path,
+            ErrorCode::InvalidCharacters,
+            format!("Profile names can only contain letters, numbers, '-', and '_', got '{name}'"),
+        );
     }
 }
 
-fn validate_scheduled_events(events: &Vec<ScheduledEvent>) -> anyhow::Result<()> {
-    for event in events {
-        event.validate()?;
+fn validate_timezone(timezone: &str, path: &str, report: &mut ValidationReport) {
+    if let Err(err) = TimeZone::get(timezone) {
+        report.add(path, ErrorCode::InvalidTimezone, err.to_string());
+    }
+}
+
+fn validate_light_profile(keyframes: &[LightKeyframe], path: &str, report: &mut ValidationReport) {
+    if keyframes.len() > MAX_LIGHT_KEYFRAMES {
+        report.add(
+            path,
+            ErrorCode::TooMany,
+            format!(
+                "light_profile can have at most {MAX_LIGHT_KEYFRAMES} keyframes, got {}",
+                keyframes.len()
+            ),
+        );
+    }
+    for (i, keyframe) in keyframes.iter().enumerate() {
+        if i > 0 && keyframe.time <= keyframes[i - 1].time {
+            report.add(
+                &field(&index(path, i), "time"),
+                ErrorCode::InvalidOrder,
+                "light_profile keyframes must be in increasing order of time",
+            );
+        }
+    }
+}
+
+fn validate_days(days: &Option<Vec<Weekday>>, path: &str, report: &mut ValidationReport) {
+    if let Some(days) = days {
+        if days.is_empty() {
+            report.add(
+                path,
+                ErrorCode::Empty,
+                "days can not be empty. Leave it out to run every day",
+            );
+        }
+        for (i, day) in days.iter().enumerate() {
+            if days[..i].contains(day) {
+                report.add(
+                    &index(path, i),
+                    ErrorCode::Duplicate,
+                    format!("days contains {day:?} more than once"),
+                );
+            }
+        }
+    }
+}
+
+fn validate_scheduled_events(events: &[ScheduledEvent], path: &str, report: &mut ValidationReport) {
+    for (i, event) in events.iter().enumerate() {
+        event.validate_at(&index(path, i), report);
     }
-    Ok(())
 }
 
 #[cfg(test)]
 mod schedule {
     use super::*;
 
+    // Returns the given time of day on Monday April 7th, 2025.
+    fn at(time: &str) -> Zoned {
+        on("2025-04-07", time)
+    }
+
+    fn on(date: &str, time: &str) -> Zoned {
+        format!("{date}T{time}[UTC]").parse().unwrap()
+    }
+
     #[test]
     fn evaluate() {
         let sch = Schedule {
             lights: Some(TimeRange {
                 start: "08:30".parse().unwrap(),
                 stop: "22:00".parse().unwrap(),
+                days: None,
             }),
-            light_intensity: Some(0.5),
+            light_intensity: Some(Intensity::new(0.5).unwrap()),
+            sunrise: None,
+            sunset: None,
+            light_profile: vec![],
+            solar_lights: None,
             fans: vec![ScheduledEvent {
                 start_time: "09:00".parse().unwrap(),
-                duration_secs: 100,
+                duration_secs: BoundedSecs::new(100).unwrap(),
                 repeat: Some(RepeatInfo {
-                    n_hours: 1,
+                    n_hours: RepeatHours::new(1).unwrap(),
                     stop_time: "22:00".parse().unwrap(),
                 }),
+                days: None,
+                cron: None,
             }],
             mist: vec![ScheduledEvent {
                 start_time: "09:00".parse().unwrap(),
-                duration_secs: 100,
+                duration_secs: BoundedSecs::new(100).unwrap(),
                 repeat: Some(RepeatInfo {
-                    n_hours: 1,
+                    n_hours: RepeatHours::new(1).unwrap(),
                     stop_time: "22:00".parse().unwrap(),
                 }),
+                days: None,
+                cron: None,
             }],
             auto_mist_enabled: false,
             humidity_setpoint: None,
+            humidity_control: None,
         };
 
-        assert_eq!(
-            sch.evaluate("06:00".parse().unwrap()),
-            ActuatorValues::default()
-        );
+        assert_eq!(sch.evaluate(&at("06:00")), ActuatorValues::default());
 
         assert_eq!(
-            sch.evaluate("09:30".parse().unwrap()),
+            sch.evaluate(&at("09:30")),
             ActuatorValues {
                 lights: 0.5,
                 fans: false,
@@ -381,7 +1601,7 @@ mod schedule {
         );
 
         assert_eq!(
-            sch.evaluate("22:01".parse().unwrap()),
+            sch.evaluate(&at("22:01")),
             ActuatorValues {
                 lights: 0.0,
                 fans: true,
@@ -395,13 +1615,17 @@ mod schedule {
         let events = vec![
             ScheduledEvent {
                 start_time: "09:00".parse().unwrap(),
-                duration_secs: 30,
+                duration_secs: BoundedSecs::new(30).unwrap(),
                 repeat: None,
+                days: None,
+                cron: None,
             },
             ScheduledEvent {
                 start_time: "10:00".parse().unwrap(),
-                duration_secs: 30,
+                duration_secs: BoundedSecs::new(30).unwrap(),
                 repeat: None,
+                days: None,
+                cron: None,
             },
         ];
 
@@ -436,7 +1660,7 @@ mod schedule {
             },
         ] {
             assert_eq!(
-                evaluate_scheduled_events(&events, test.time.parse().unwrap()),
+                active_scheduled_event(&events, &at(test.time)).is_some(),
                 test.on
             );
         }
@@ -446,11 +1670,13 @@ mod schedule {
     fn test_evaluate_scheduled_events_repeating() {
         let events = vec![ScheduledEvent {
             start_time: "09:00".parse().unwrap(),
-            duration_secs: 30,
+            duration_secs: BoundedSecs::new(30).unwrap(),
             repeat: Some(RepeatInfo {
-                n_hours: 1,
+                n_hours: RepeatHours::new(1).unwrap(),
                 stop_time: "22:00".parse().unwrap(),
             }),
+            days: None,
+            cron: None,
         }];
 
         struct Entry<'a> {
@@ -488,11 +1714,662 @@ mod schedule {
             },
         ] {
             assert_eq!(
-                evaluate_scheduled_events(&events, test.time.parse().unwrap()),
+                active_scheduled_event(&events, &at(test.time)).is_some(),
                 test.on
             );
         }
     }
+
+    #[test]
+    fn evaluate_with_reasons() {
+        let event = |start_time: &str, repeat, cron: Option<&str>| ScheduledEvent {
+            start_time: start_time.parse().unwrap(),
+            duration_secs: BoundedSecs::new(60).unwrap(),
+            repeat,
+            days: None,
+            cron: cron.map(|cron| cron.parse().unwrap()),
+        };
+        let sch = Schedule {
+            lights: Some(TimeRange {
+                start: "08:00".parse().unwrap(),
+                stop: "20:00".parse().unwrap(),
+                days: None,
+            }),
+            light_intensity: Some(Intensity::new(0.5).unwrap()),
+            mist: vec![
+                event("07:00", None, None),
+                event(
+                    "09:00",
+                    Some(RepeatInfo {
+                        n_hours: RepeatHours::new(2).unwrap(),
+                        stop_time: "15:00".parse().unwrap(),
+                    }),
+                    None,
+                ),
+            ],
+            fans: vec![event("00:00", None, Some("30 * * * *"))],
+            ..Schedule::default()
+        };
+
+        let (values, reasons) = sch.evaluate_with_reasons(&at("11:00:30"));
+        assert!(values.mist);
+        assert_eq!(
+            reasons,
+            ActuatorReasons {
+                lights: ActuatorReason::LightTimeRange {
+                    start: "08:00".parse().unwrap(),
+                    stop: "20:00".parse().unwrap(),
+                },
+                fans: ActuatorReason::Unscheduled,
+                mist: ActuatorReason::ScheduleEvent {
+                    index: 1,
+                    start_time: "11:00".parse().unwrap(),
+                },
+            }
+        );
+
+        let (_, reasons) = sch.evaluate_with_reasons(&at("21:30:10"));
+        assert_eq!(reasons.lights, ActuatorReason::Unscheduled);
+        assert_eq!(
+            reasons.fans,
+            ActuatorReason::ScheduleEvent {
+                index: 0,
+                start_time: "21:30".parse().unwrap(),
+            }
+        );
+
+        // Solar lights are only a reason while they're on. Sunrise in
+        // Singapore is around 07:13 on the terrarium's clock.
+        let sch = Schedule {
+            solar_lights: Some(singapore()),
+            ..sch
+        };
+        let reasons_at = |time: &str| {
+            let now: Zoned = format!("2025-01-15T{time}[America/Los_Angeles]")
+                .parse()
+                .unwrap();
+            sch.evaluate_with_reasons(&now).1.lights
+        };
+        assert_eq!(reasons_at("12:00"), ActuatorReason::SolarLights);
+        assert_eq!(reasons_at("03:00"), ActuatorReason::Unscheduled);
+    }
+
+    #[test]
+    fn evaluate_weekdays() {
+        let sch = Schedule {
+            lights: Some(TimeRange {
+                start: "08:00".parse().unwrap(),
+                stop: "20:00".parse().unwrap(),
+                days: Some(vec![Weekday::Sat, Weekday::Sun]),
+            }),
+            light_intensity: Some(Intensity::new(0.5).unwrap()),
+            mist: vec![ScheduledEvent {
+                start_time: "11:00".parse().unwrap(),
+                duration_secs: BoundedSecs::new(60).unwrap(),
+                repeat: None,
+                days: Some(vec![Weekday::Mon]),
+                cron: None,
+            }],
+            ..Schedule::default()
+        };
+
+        // Monday
+        assert_eq!(
+            sch.evaluate(&on("2025-04-07", "11:00:30")),
+            ActuatorValues {
+                lights: 0.0,
+                fans: false,
+                mist: true,
+            }
+        );
+        // Tuesday
+        assert_eq!(
+            sch.evaluate(&on("2025-04-08", "11:00:30")),
+            ActuatorValues::default()
+        );
+        // Sunday
+        assert_eq!(
+            sch.evaluate(&on("2025-04-13", "11:00:30")),
+            ActuatorValues {
+                lights: 0.5,
+                fans: false,
+                mist: false,
+            }
+        );
+    }
+
+    #[test]
+    fn evaluate_cron_events() {
+        let sch: Schedule = serde_json::from_str(
+            r#"{
+                "lights": null,
+                "light_intensity": null,
+                "fans": [{"cron": "*/20 9-16 * * *", "duration_secs": 60, "repeat": null}],
+                "mist": [{"cron": "15,45 11 * * mon,wed,fri", "duration_secs": 30, "repeat": null}],
+                "auto_mist_enabled": false,
+                "humidity_setpoint": null
+            }"#,
+        )
+        .unwrap();
+
+        let fans = |date, time| sch.evaluate(&on(date, time)).fans;
+        assert!(fans("2025-04-07", "09:00:30"));
+        assert!(fans("2025-04-07", "16:41:00"));
+        assert!(!fans("2025-04-07", "09:01:01"));
+        assert!(!fans("2025-04-07", "17:00:30"));
+
+        let mist = |date, time| sch.evaluate(&on(date, time)).mist;
+        // Monday, Tuesday, and Wednesday
+        assert!(mist("2025-04-07", "11:15:10"));
+        assert!(!mist("2025-04-08", "11:15:10"));
+        assert!(mist("2025-04-09", "11:45:30"));
+        assert!(!mist("2025-04-09", "11:45:31"));
+    }
+
+    #[test]
+    fn preview() {
+        let sch = Schedule::new_with_reasonable_defaults();
+        let preview = sch.preview(&at("09:00:00.25"), SignedDuration::from_hours(3));
+        assert_eq!(preview.start, at("09:00:00"));
+        assert_eq!(preview.end, at("12:00:00"));
+        assert_eq!(preview.initial, ActuatorValues::default());
+
+        let transition = |time, actuator, on| ScheduleTransition {
+            time: at(time),
+            actuator,
+            on,
+        };
+        // The event times are inclusive at both ends, so an event turns off the
+        // second after its last second.
+        assert_eq!(
+            preview.transitions,
+            vec![
+                transition("10:00:01", Actuator::Lights, true),
+                transition("10:30:00", Actuator::Fans, true),
+                transition("10:32:01", Actuator::Fans, false),
+                transition("11:00:00", Actuator::Mist, true),
+                transition("11:01:01", Actuator::Mist, false),
+                transition("11:30:00", Actuator::Fans, true),
+                transition("11:32:01", Actuator::Fans, false),
+            ]
+        );
+    }
+
+    // The brute-force version of Schedule::preview(), which evaluates the
+    // schedule every second.
+    fn preview_every_second(
+        sch: &Schedule,
+        start: &Zoned,
+        duration: SignedDuration,
+    ) -> SchedulePreview {
+        let end = start.saturating_add(duration);
+        let initial = sch.evaluate(start);
+        let mut transitions = vec![];
+        let mut prev = initial;
+        let mut t = start.clone();
+        while t < end {
+            t = t.saturating_add(SignedDuration::from_secs(1));
+            let cur = sch.evaluate(&t);
+            for actuator in [Actuator::Lights, Actuator::Fans, Actuator::Mist] {
+                if prev.is_on(actuator) != cur.is_on(actuator) {
+                    transitions.push(ScheduleTransition {
+                        time: t.clone(),
+                        actuator,
+                        on: cur.is_on(actuator),
+                    });
+                }
+            }
+            prev = cur;
+        }
+        SchedulePreview {
+            start: start.clone(),
+            end,
+            initial,
+            transitions,
+        }
+    }
+
+    #[test]
+    fn preview_matches_every_second() {
+        let event = |start_time: &str, duration_secs, repeat: Option<(u32, &str)>| ScheduledEvent {
+            start_time: start_time.parse().unwrap(),
+            duration_secs: BoundedSecs::new(duration_secs).unwrap(),
+            repeat: repeat.map(|(n_hours, stop_time)| RepeatInfo {
+                n_hours: RepeatHours::new(n_hours).unwrap(),
+                stop_time: stop_time.parse().unwrap(),
+            }),
+            days: None,
+            cron: None,
+        };
+        let schedules = [
+            Schedule::new_with_reasonable_defaults(),
+            Schedule {
+                lights: Some(TimeRange {
+                    start: "22:00:30".parse().unwrap(),
+                    stop: "01:30:15".parse().unwrap(),
+                    days: Some(vec![Weekday::Sat, Weekday::Sun]),
+                }),
+                light_intensity: Some(Intensity::new(0.5).unwrap()),
+                sunrise: Some(LightRamp {
+                    duration_secs: BoundedSecs::new(30 * 60).unwrap(),
+                    curve: RampCurve::Sine,
+                }),
+                sunset: Some(LightRamp {
+                    duration_secs: BoundedSecs::new(10 * 60).unwrap(),
+                    curve: RampCurve::Linear,
+                }),
+                fans: vec![
+                    event("01:15:17", 5, Some((3, "20:00"))),
+                    event("01:15:20", 30, None),
+                ],
+                mist: vec![
+                    ScheduledEvent {
+                        days: Some(vec![Weekday::Sun]),
+                        ..event("23:59:50", 20, None)
+                    },
+                    event("08:00", 90, Some((5, "20:00"))),
+                ],
+                ..Schedule::default()
+            },
+            Schedule {
+                light_profile: vec![
+                    keyframe("01:30", 0.6),
+                    keyframe("02:10", 0.0),
+                    keyframe("19:50", 0.0),
+                    keyframe("20:00:03", 0.001),
+                    keyframe("21:00", 0.0),
+                ],
+                ..Schedule::default()
+            },
+        ];
+        // Around the clocks going forward and back in Los Angeles.
+        for start in ["2025-03-08T12:00:00", "2025-11-01T12:00:00"] {
+            let start: Zoned = format!("{start}[America/Los_Angeles]").parse().unwrap();
+            for sch in &schedules {
+                let duration = SignedDuration::from_hours(72);
+                assert_eq!(
+                    sch.preview(&start, duration),
+                    preview_every_second(sch, &start, duration)
+                );
+            }
+        }
+
+        // Solar lights and cron events are only covered by the checkpoints at
+        // the start of every minute.
+        let sch = Schedule {
+            light_intensity: Some(Intensity::new(0.5).unwrap()),
+            sunrise: Some(LightRamp {
+                duration_secs: BoundedSecs::new(60 * 60).unwrap(),
+                curve: RampCurve::Smoothstep,
+            }),
+            solar_lights: Some(singapore()),
+            fans: vec![ScheduledEvent {
+                cron: Some("*/7 * * * *".parse().unwrap()),
+                ..event("00:00", 20, None)
+            }],
+            ..Schedule::default()
+        };
+        let start: Zoned = "2025-01-15T00:00:00[America/Los_Angeles]".parse().unwrap();
+        let duration = SignedDuration::from_hours(24);
+        assert_eq!(
+            sch.preview(&start, duration),
+            preview_every_second(&sch, &start, duration)
+        );
+    }
+
+    #[test]
+    fn evaluate_ramps() {
+        let sch = Schedule {
+            lights: Some(TimeRange {
+                start: "08:00".parse().unwrap(),
+                stop: "20:00".parse().unwrap(),
+                days: None,
+            }),
+            light_intensity: Some(Intensity::new(0.8).unwrap()),
+            sunrise: Some(LightRamp {
+                duration_secs: BoundedSecs::new(60 * 60).unwrap(),
+                curve: RampCurve::Linear,
+            }),
+            sunset: Some(LightRamp {
+                duration_secs: BoundedSecs::new(30 * 60).unwrap(),
+                curve: RampCurve::Smoothstep,
+            }),
+            ..Schedule::default()
+        };
+
+        let lights_at = |time| sch.evaluate(&at(time)).lights;
+        assert_eq!(lights_at("07:59"), 0.0);
+        assert_eq!(lights_at("08:15"), 0.2);
+        assert_eq!(lights_at("08:30"), 0.4);
+        assert_eq!(lights_at("09:00"), 0.8);
+        assert_eq!(lights_at("12:00"), 0.8);
+        // Halfway through the sunset, smoothstep is also at half.
+        assert_eq!(lights_at("19:45"), 0.4);
+        assert!(lights_at("19:55") < lights_at("19:45"));
+        assert_eq!(lights_at("20:00"), 0.0);
+    }
+
+    #[test]
+    fn ramp_curves() {
+        for curve in [RampCurve::Linear, RampCurve::Sine, RampCurve::Smoothstep] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(0.5) - 0.5).abs() < 1e-6);
+            assert_eq!(curve.apply(1.0), 1.0);
+            assert!(curve.apply(0.25) < curve.apply(0.75));
+        }
+        // Sine and smoothstep ease in, so they start slower than linear.
+        assert!(RampCurve::Sine.apply(0.1) < RampCurve::Linear.apply(0.1));
+        assert!(RampCurve::Smoothstep.apply(0.1) < RampCurve::Linear.apply(0.1));
+    }
+
+    #[test]
+    fn validate_ramps() {
+        let ramp = |secs| {
+            Update::Set(LightRamp {
+                duration_secs: BoundedSecs::new(secs).unwrap(),
+                curve: RampCurve::Sine,
+            })
+        };
+        let mut cfg = TerrariumConfig::default();
+        let mut update = TerrariumConfigUpdate {
+            schedule: Update::Set(ScheduleUpdate {
+                lights: Update::Set(TimeRange {
+                    start: "08:00".parse().unwrap(),
+                    stop: "10:00".parse().unwrap(),
+                    days: None,
+                }),
+                sunrise: ramp(3600),
+                sunset: ramp(3600),
+                ..ScheduleUpdate::default()
+            }),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(cfg.update(&update).is_ok());
+
+        // The ramps are checked against the lights that are already set, even
+        // when the update only changes one of them.
+        update.schedule = Update::Set(ScheduleUpdate {
+            sunset: ramp(3601),
+            ..ScheduleUpdate::default()
+        });
+        let err = cfg
+            .update(&update)
+            .unwrap_err()
+            .downcast::<ValidationReport>()
+            .unwrap()
+            .errors
+            .remove(0);
+        assert_eq!(err.path, "schedule.lights");
+        assert_eq!(err.code, ErrorCode::Conflict);
+
+        let mut update = TerrariumConfigUpdate::default();
+        update.profiles.insert(
+            DEFAULT_PROFILE.to_string(),
+            Update::Set(ScheduleUpdate {
+                sunrise: ramp(3601),
+                ..ScheduleUpdate::default()
+            }),
+        );
+        let err = cfg
+            .update(&update)
+            .unwrap_err()
+            .downcast::<ValidationReport>()
+            .unwrap()
+            .errors
+            .remove(0);
+        assert_eq!(err.path, "profiles.default.lights");
+    }
+
+    fn singapore() -> SolarLights {
+        SolarLights {
+            latitude: 1.3521,
+            longitude: 103.8198,
+            timezone: Some("Asia/Singapore".to_string()),
+            sunrise_offset_mins: 0,
+            sunset_offset_mins: 0,
+            min_day_length_mins: None,
+            max_day_length_mins: None,
+        }
+    }
+
+    #[test]
+    fn evaluate_solar_lights() {
+        // Singapore's day, followed on the wall clock of a terrarium in Los
+        // Angeles. Sunrise there is around 07:13 and sunset around 19:17.
+        let sch = Schedule {
+            light_intensity: Some(Intensity::new(0.5).unwrap()),
+            solar_lights: Some(singapore()),
+            ..Schedule::default()
+        };
+        let lights_at = |time: &str| {
+            let now: Zoned = format!("2025-01-15T{time}[America/Los_Angeles]")
+                .parse()
+                .unwrap();
+            sch.evaluate(&now).lights
+        };
+        assert_eq!(lights_at("07:00"), 0.0);
+        assert_eq!(lights_at("07:30"), 0.5);
+        assert_eq!(lights_at("19:00"), 0.5);
+        assert_eq!(lights_at("19:30"), 0.0);
+    }
+
+    #[test]
+    fn solar_lights_day_length_limits() {
+        let tz = TimeZone::get("Europe/London").unwrap();
+        let london = SolarLights {
+            latitude: 51.5074,
+            longitude: -0.1278,
+            timezone: None,
+            sunrise_offset_mins: 30,
+            sunset_offset_mins: 0,
+            min_day_length_mins: Some(10 * 60),
+            max_day_length_mins: Some(14 * 60),
+        };
+        let day_length = |date| {
+            let (start, stop) = london.light_period(date, &tz).unwrap();
+            start.duration_until(&stop)
+        };
+        // Without limits, summer days are over 16 hours and winter days are
+        // under 8.
+        assert_eq!(
+            day_length(Date::constant(2025, 6, 21)),
+            SignedDuration::from_hours(14)
+        );
+        assert_eq!(
+            day_length(Date::constant(2025, 12, 21)),
+            SignedDuration::from_hours(10)
+        );
+        // In spring, the sunrise offset shortens the day to a bit under 12
+        // hours, which is within the limits.
+        let spring = day_length(Date::constant(2025, 3, 20));
+        assert!(spring > SignedDuration::from_mins(11 * 60 + 30));
+        assert!(spring < SignedDuration::from_hours(12));
+    }
+
+    #[test]
+    fn solar_lights_validation() {
+        let update = |solar_lights| ScheduleUpdate {
+            solar_lights: Update::Set(solar_lights),
+            ..ScheduleUpdate::default()
+        };
+        assert!(update(singapore()).validate().is_ok());
+        assert!(
+            update(SolarLights {
+                latitude: 91.0,
+                ..singapore()
+            })
+            .validate()
+            .is_err()
+        );
+        assert!(
+            update(SolarLights {
+                timezone: Some("Not/AZone".to_string()),
+                ..singapore()
+            })
+            .validate()
+            .is_err()
+        );
+        assert!(
+            update(SolarLights {
+                min_day_length_mins: Some(13 * 60),
+                max_day_length_mins: Some(12 * 60),
+                ..singapore()
+            })
+            .validate()
+            .is_err()
+        );
+    }
+
+    fn keyframe(time: &str, intensity: f32) -> LightKeyframe {
+        LightKeyframe {
+            time: time.parse().unwrap(),
+            intensity: Intensity::new(intensity).unwrap(),
+        }
+    }
+
+    #[test]
+    fn evaluate_light_profile_night_shift() {
+        // Lights on from 20:00 to 08:00 with a short fade at either end. The
+        // profile takes precedence over the regular light period.
+        let sch = Schedule {
+            lights: Some(TimeRange {
+                start: "10:00".parse().unwrap(),
+                stop: "12:00".parse().unwrap(),
+                days: None,
+            }),
+            light_intensity: Some(Intensity::new(1.0).unwrap()),
+            light_profile: vec![
+                keyframe("08:00", 0.6),
+                keyframe("08:10", 0.0),
+                keyframe("19:50", 0.0),
+                keyframe("20:00", 0.6),
+            ],
+            ..Schedule::default()
+        };
+
+        let lights_at = |time| sch.evaluate(&at(time)).lights;
+        assert_eq!(lights_at("11:00"), 0.0);
+        assert_eq!(lights_at("19:55"), 0.3);
+        assert_eq!(lights_at("23:00"), 0.6);
+        assert_eq!(lights_at("00:00"), 0.6);
+        assert_eq!(lights_at("07:59"), 0.6);
+        assert_eq!(lights_at("08:05"), 0.3);
+    }
+
+    #[test]
+    fn evaluate_light_profile_siesta() {
+        let profile = vec![
+            keyframe("06:00", 0.0),
+            keyframe("08:00", 0.8),
+            keyframe("12:00", 0.8),
+            keyframe("13:00", 0.2),
+            keyframe("14:00", 0.8),
+            keyframe("20:00", 0.8),
+            keyframe("22:00", 0.0),
+        ];
+        let lights_at = |time: &str| evaluate_light_profile(&profile, time.parse().unwrap());
+        assert_eq!(lights_at("03:00"), 0.0);
+        assert_eq!(lights_at("07:00"), 0.4);
+        assert_eq!(lights_at("13:00"), 0.2);
+        assert_eq!(lights_at("13:30"), 0.5);
+        assert_eq!(lights_at("21:00"), 0.4);
+
+        // A single keyframe means constant intensity.
+        let constant = vec![keyframe("09:00", 0.3)];
+        assert_eq!(
+            evaluate_light_profile(&constant, "01:00".parse().unwrap()),
+            0.3
+        );
+        assert_eq!(
+            evaluate_light_profile(&constant, "18:00".parse().unwrap()),
+            0.3
+        );
+    }
+
+    #[test]
+    fn deserialize_without_light_profile() {
+        // Schedules saved before light profiles existed only have a light
+        // period.
+        let json = r#"{"lights":{"start":"10:00:00","stop":"22:00:00"},"light_intensity":0.7,"fans":[],"mist":[],"auto_mist_enabled":false,"humidity_setpoint":null}"#;
+        let sch: Schedule = serde_json::from_str(json).unwrap();
+        assert!(sch.light_profile.is_empty());
+        assert_eq!(sch.evaluate(&at("12:00")).lights, 0.7);
+        assert_eq!(serde_json::to_string(&sch).unwrap(), json);
+    }
+
+    #[test]
+    fn light_profile_validation() {
+        let update = |light_profile| ScheduleUpdate {
+            light_profile: Update::Set(light_profile),
+            ..ScheduleUpdate::default()
+        };
+        let increasing = vec![keyframe("08:00", 0.0), keyframe("20:00", 1.0)];
+        assert!(update(increasing).validate().is_ok());
+        let decreasing = vec![keyframe("20:00", 0.0), keyframe("08:00", 1.0)];
+        assert!(update(decreasing).validate().is_err());
+        assert!(
+            serde_json::from_str::<LightKeyframe>(r#"{"time": "08:00:00", "intensity": 1.5}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn validate_days() {
+        let update = |days| ScheduleUpdate {
+            mist: Update::Set(vec![ScheduledEvent {
+                start_time: "11:00".parse().unwrap(),
+                duration_secs: BoundedSecs::new(60).unwrap(),
+                repeat: None,
+                days,
+                cron: None,
+            }]),
+            ..ScheduleUpdate::default()
+        };
+        assert!(update(None).validate().is_ok());
+        assert!(
+            update(Some(vec![Weekday::Mon, Weekday::Fri]))
+                .validate()
+                .is_ok()
+        );
+        assert!(update(Some(vec![])).validate().is_err());
+        assert!(
+            update(Some(vec![Weekday::Mon, Weekday::Mon]))
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn validate_cron() {
+        let update = |repeat, days| ScheduleUpdate {
+            fans: Update::Set(vec![ScheduledEvent {
+                start_time: Time::midnight(),
+                duration_secs: BoundedSecs::new(60).unwrap(),
+                repeat,
+                days,
+                cron: Some("0 * * * *".parse().unwrap()),
+            }]),
+            ..ScheduleUpdate::default()
+        };
+        assert!(update(None, None).validate().is_ok());
+        assert!(update(None, Some(vec![Weekday::Mon])).validate().is_err());
+        assert!(
+            update(
+                Some(RepeatInfo {
+                    n_hours: RepeatHours::new(1).unwrap(),
+                    stop_time: "22:00".parse().unwrap(),
+                }),
+                None
+            )
+            .validate()
+            .is_err()
+        );
+
+        let bad: Result<ScheduleUpdate, _> = serde_json::from_str(
+            r#"{"mist": [{"cron": "0 25 * * *", "duration_secs": 60, "repeat": null}]}"#,
+        );
+        assert!(bad.is_err());
+    }
 }
 
 #[cfg(test)]
@@ -502,7 +2379,7 @@ mod config_update {
     #[test]
     fn update_set_one_field() {
         let mut upd = TerrariumConfigUpdate::default();
-        upd.name = Update::Set("justin".to_string());
+        upd.name = Update::Set(Hostname::try_from("justin").unwrap());
         assert_eq!(
             serde_json::to_string(&upd).unwrap(),
             "{\"name\":\"justin\"}"
@@ -512,7 +2389,7 @@ mod config_update {
     #[test]
     fn update_set_one_field_clear_one_field() {
         let mut upd = TerrariumConfigUpdate::default();
-        upd.name = Update::Set("justin".to_string());
+        upd.name = Update::Set(Hostname::try_from("justin").unwrap());
         upd.wifi = Update::Clear;
         assert_eq!(
             serde_json::to_string(&upd).unwrap(),
@@ -525,12 +2402,276 @@ mod config_update {
         let json = "{\"name\":\"justin\",\"wifi\":null}";
         let upd: TerrariumConfigUpdate = serde_json::from_str(json).unwrap();
         let upd_expect = TerrariumConfigUpdate {
-            name: Update::Set("justin".to_string()),
+            name: Update::Set(Hostname::try_from("justin").unwrap()),
             wifi: Update::Clear,
             schedule: Update::NoChange,
+            profiles: BTreeMap::new(),
+            rename_profiles: vec![],
+            active_profile: Update::NoChange,
             timezone: Update::NoChange,
             influxdb: Update::NoChange,
+            thermal: Update::NoChange,
         };
         assert_eq!(upd, upd_expect);
     }
+
+    fn profiles_config() -> TerrariumConfig {
+        TerrariumConfig {
+            profiles: BTreeMap::from([
+                (
+                    "normal".to_string(),
+                    Schedule::new_with_reasonable_defaults(),
+                ),
+                ("vacation".to_string(), Schedule::default()),
+            ]),
+            active_profile: Some("normal".to_string()),
+            ..TerrariumConfig::default()
+        }
+    }
+
+    #[test]
+    fn rename_and_delete_profiles() {
+        let mut cfg = profiles_config();
+        let update: TerrariumConfigUpdate = serde_json::from_str(
+            r#"{
+                "rename_profiles": [{"from": "normal", "to": "summer"}],
+                "profiles": {"vacation": null}
+            }"#,
+        )
+        .unwrap();
+        update.validate().unwrap();
+        cfg.update(&update).unwrap();
+        assert_eq!(cfg.profiles.keys().collect::<Vec<_>>(), vec!["summer"]);
+        // The active profile follows the rename.
+        assert_eq!(cfg.active_profile, Some("summer".to_string()));
+
+        // Renaming onto an existing profile or deleting a missing one fails.
+        let mut cfg = profiles_config();
+        let update = TerrariumConfigUpdate {
+            rename_profiles: vec![ProfileRename {
+                from: "normal".to_string(),
+                to: "vacation".to_string(),
+            }],
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(cfg.update(&update).is_err());
+        let update = TerrariumConfigUpdate {
+            profiles: BTreeMap::from([("missing".to_string(), Update::Clear)]),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(cfg.update(&update).is_err());
+
+        // The active profile can't be deleted without switching to another.
+        let mut cfg = profiles_config();
+        let update = TerrariumConfigUpdate {
+            profiles: BTreeMap::from([("normal".to_string(), Update::Clear)]),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(cfg.update(&update).is_err());
+        let update = TerrariumConfigUpdate {
+            profiles: BTreeMap::from([("normal".to_string(), Update::Clear)]),
+            active_profile: Update::Set("vacation".to_string()),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(profiles_config().update(&update).is_ok());
+    }
+
+    #[test]
+    fn schedule_updates_active_profile() {
+        let mut cfg = profiles_config();
+        let update = TerrariumConfigUpdate {
+            schedule: Update::Set(ScheduleUpdate {
+                light_intensity: Update::Set(Intensity::new(0.2).unwrap()),
+                ..ScheduleUpdate::default()
+            }),
+            ..TerrariumConfigUpdate::default()
+        };
+        cfg.update(&update).unwrap();
+        assert_eq!(
+            cfg.profiles["normal"].light_intensity.map(Intensity::get),
+            Some(0.2)
+        );
+        assert_eq!(cfg.profiles["vacation"].light_intensity, None);
+
+        // With no active profile, the default one is created.
+        let mut cfg = TerrariumConfig::default();
+        cfg.update(&update).unwrap();
+        assert_eq!(cfg.active_profile, Some(DEFAULT_PROFILE.to_string()));
+        assert_eq!(
+            cfg.active_schedule()
+                .unwrap()
+                .light_intensity
+                .map(Intensity::get),
+            Some(0.2)
+        );
+    }
+
+    #[test]
+    fn copy_profile() {
+        let schedule = Schedule::new_with_reasonable_defaults();
+        let mut copy = Schedule {
+            humidity_setpoint: Some(RelativeHumidity::new(0.5).unwrap()),
+            ..Schedule::default()
+        };
+        copy.update(&ScheduleUpdate::from(&schedule));
+        assert_eq!(copy, schedule);
+    }
+
+    #[test]
+    fn validate_profile_names() {
+        let update = |name: &str| TerrariumConfigUpdate {
+            active_profile: Update::Set(name.to_string()),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(update("grow-in_2").validate().is_ok());
+        assert!(update("").validate().is_err());
+        assert!(update("has space").validate().is_err());
+        assert!(update(&"x".repeat(33)).validate().is_err());
+    }
+
+    #[test]
+    fn validation_report() {
+        let update: TerrariumConfigUpdate = serde_json::from_str(
+            r#"{
+                "timezone": "Mars/Olympus_Mons",
+                "schedule": {
+                    "mist": [
+                        {"start_time": "09:00:00", "duration_secs": 10},
+                        {"start_time": "10:00:00", "duration_secs": 10, "days": ["mon", "mon"]},
+                        {
+                            "start_time": "11:00:00",
+                            "duration_secs": 10,
+                            "repeat": {"n_hours": 1, "stop_time": "08:00:00"}
+                        }
+                    ]
+                },
+                "profiles": {"has space": {}}
+            }"#,
+        )
+        .unwrap();
+        let report = update.validate().unwrap_err();
+        let found: Vec<(&str, ErrorCode)> = report
+            .errors
+            .iter()
+            .map(|e| (e.path.as_str(), e.code))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("schedule.mist[1].days[1]", ErrorCode::Duplicate),
+                ("schedule.mist[2].repeat.stop_time", ErrorCode::InvalidOrder),
+                ("profiles.has space", ErrorCode::InvalidCharacters),
+                ("timezone", ErrorCode::InvalidTimezone),
+            ]
+        );
+        assert!(
+            report
+                .to_string()
+                .starts_with("schedule.mist[1].days[1]: days contains Mon more than once\n")
+        );
+
+        // Problems that depend on the current config are reported the same
+        // way.
+        let mut cfg = profiles_config();
+        let update = TerrariumConfigUpdate {
+            active_profile: Update::Set("missing".to_string()),
+            ..TerrariumConfigUpdate::default()
+        };
+        let err = cfg.update(&update).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationReport>().unwrap().errors[0].path,
+            "active_profile"
+        );
+    }
+
+    #[test]
+    fn secrets() {
+        let mut cfg = TerrariumConfig::new_with_reasonable_defaults();
+        cfg.wifi = Some(WifiDetails {
+            ssid: "home".to_string(),
+            password: "hunter2".to_string(),
+        });
+        cfg.influxdb = Some(influxdb::Config {
+            address: "http://influx".to_string(),
+            org: "org".to_string(),
+            bucket: "oasis".to_string(),
+            token: String::new(),
+        });
+        let redacted = cfg.redacted();
+        assert_eq!(redacted.wifi.as_ref().unwrap().password, REDACTED);
+        assert_eq!(redacted.wifi.as_ref().unwrap().ssid, "home");
+        // Secrets that aren't set stay empty.
+        assert_eq!(redacted.influxdb.as_ref().unwrap().token, "");
+        assert!(
+            !serde_json::to_string(&redacted)
+                .unwrap()
+                .contains("hunter2")
+        );
+
+        // Echoing the redacted config back doesn't change the secrets.
+        let update = TerrariumConfigUpdate {
+            wifi: Update::Set(WifiDetails {
+                ssid: "cabin".to_string(),
+                password: REDACTED.to_string(),
+            }),
+            ..TerrariumConfigUpdate::default()
+        };
+        cfg.update(&update).unwrap();
+        assert_eq!(
+            cfg.wifi,
+            Some(WifiDetails {
+                ssid: "cabin".to_string(),
+                password: "hunter2".to_string(),
+            })
+        );
+
+        // But it can't stand in for a secret that was never set.
+        let update = TerrariumConfigUpdate {
+            influxdb: Update::Set(influxdb::Config {
+                token: REDACTED.to_string(),
+                ..redacted.influxdb.clone().unwrap()
+            }),
+            ..TerrariumConfigUpdate::default()
+        };
+        let err = cfg.update(&update).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ValidationReport>().unwrap().errors[0].path,
+            "influxdb.token"
+        );
+    }
+
+    #[test]
+    fn from_json() {
+        let update = TerrariumConfigUpdate::from_json(br#"{"name": "oasis"}"#).unwrap();
+        assert_eq!(
+            update.name,
+            Update::Set(Hostname::try_from("oasis").unwrap())
+        );
+
+        let report_for = |json: &str| {
+            TerrariumConfigUpdate::from_json(json.as_bytes())
+                .unwrap_err()
+                .downcast::<ValidationReport>()
+                .unwrap()
+                .errors
+                .remove(0)
+        };
+        let err = report_for(r#"{"schedule": {"light_intensity": 1.5}}"#);
+        assert_eq!(err.path, "schedule.light_intensity");
+        assert_eq!(err.code, ErrorCode::InvalidValue);
+        assert_eq!(
+            err.message,
+            "light intensity must be between 0.0 and 1.0, got 1.5"
+        );
+        let err = report_for(
+            r#"{"schedule": {"mist": [{"start_time": "09:00:00", "duration_secs": 100000}]}}"#,
+        );
+        assert_eq!(err.path, "schedule.mist[0].duration_secs");
+        assert_eq!(report_for(r#"{"name": "my oasis"}"#).path, "name");
+        assert_eq!(report_for("5").path, "");
+
+        // Json that doesn't parse isn't a validation problem.
+        let err = TerrariumConfigUpdate::from_json(b"{").unwrap_err();
+        assert!(err.downcast_ref::<ValidationReport>().is_none());
+    }
 }
@@ -0,0 +1,192 @@
+// Field-level differences between two configs, used to show what a config
+// update would change before it's applied.
+
+use super::{REDACTED, TerrariumConfig, to_json_value};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+// A single field that differs. Fields that aren't set are null.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct ConfigChange {
+    // Path of the field, in the same form as ValidationError paths, such as
+    // `profiles.default.lights.start`.
+    pub path: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, self.old, self.new)
+    }
+}
+
+// Response to a dry run of a config update: the config that the update would
+// produce, and how it differs from the current one. Secrets are redacted in
+// both.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct DryRunResult {
+    pub config: TerrariumConfig,
+    pub changes: Vec<ConfigChange>,
+}
+
+impl DryRunResult {
+    pub fn new(old: &TerrariumConfig, new: &TerrariumConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            config: new.redacted(),
+            changes: diff(old, new)?,
+        })
+    }
+}
+
+// Returns the fields that differ between @old and @new. Objects and arrays are
+// compared field by field and element by element, so only the values that
+// actually changed are listed.
+pub fn diff(old: &TerrariumConfig, new: &TerrariumConfig) -> anyhow::Result<Vec<ConfigChange>> {
+    let mut changes = Vec::new();
+    diff_values(
+        "",
+        &to_json_value(&old.redacted())?,
+        &to_json_value(&new.redacted())?,
+        &mut changes,
+    );
+
+    // Redacting hides a change from one secret to another, but it should
+    // still be listed, just without the values.
+    let secrets = [
+        (
+            "wifi.password",
+            old.wifi.as_ref().map(|wifi| &wifi.password),
+            new.wifi.as_ref().map(|wifi| &wifi.password),
+        ),
+        (
+            "influxdb.token",
+            old.influxdb.as_ref().map(|influxdb| &influxdb.token),
+            new.influxdb.as_ref().map(|influxdb| &influxdb.token),
+        ),
+    ];
+    for (path, old_secret, new_secret) in secrets {
+        if let (Some(old_secret), Some(new_secret)) = (old_secret, new_secret)
+            && !old_secret.is_empty()
+            && !new_secret.is_empty()
+            && old_secret != new_secret
+        {
+            changes.push(ConfigChange {
+                path: path.to_string(),
+                old: REDACTED.into(),
+                new: REDACTED.into(),
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, changes: &mut Vec<ConfigChange>) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let mut keys: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_values(
+                    &super::field(path, key),
+                    old_fields.get(key).unwrap_or(&Value::Null),
+                    new_fields.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for i in 0..old_items.len().max(new_items.len()) {
+                diff_values(
+                    &super::index(path, i),
+                    old_items.get(i).unwrap_or(&Value::Null),
+                    new_items.get(i).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+        _ => {
+            if old != new {
+                changes.push(ConfigChange {
+                    path: path.to_string(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod diff {
+    use super::*;
+    use crate::config::{TerrariumConfigUpdate, Update, WifiDetails};
+    use serde_json::json;
+
+    fn changes(old: &TerrariumConfig, update: serde_json::Value) -> Vec<String> {
+        let update: TerrariumConfigUpdate = serde_json::from_value(update).unwrap();
+        let mut new = old.clone();
+        new.update(&update).unwrap();
+        diff(old, &new)
+            .unwrap()
+            .iter()
+            .map(ConfigChange::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn fields() {
+        let cfg = TerrariumConfig::new_with_reasonable_defaults();
+        assert!(changes(&cfg, json!({})).is_empty());
+        assert_eq!(
+            changes(
+                &cfg,
+                json!({"timezone": "Europe/Berlin", "schedule": {"lights": {"start": "09:00:00", "stop": "21:00:00"}}})
+            ),
+            vec![
+                r#"profiles.default.lights.start: "10:00:00" -> "09:00:00""#,
+                r#"profiles.default.lights.stop: "22:00:00" -> "21:00:00""#,
+                r#"timezone: null -> "Europe/Berlin""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let cfg = TerrariumConfig::new_with_reasonable_defaults();
+        let mist = cfg.active_schedule().unwrap().mist.len();
+        let mut added = cfg.active_schedule().unwrap().mist.clone();
+        added.push(added[0].clone());
+        let found = changes(&cfg, json!({"schedule": {"mist": added}}));
+        assert_eq!(found.len(), 1);
+        assert!(found[0].starts_with(&format!("profiles.default.mist[{mist}]: null -> {{")));
+    }
+
+    #[test]
+    fn secrets() {
+        let mut cfg = TerrariumConfig::new_with_reasonable_defaults();
+        cfg.wifi = Some(WifiDetails {
+            ssid: "home".to_string(),
+            password: "hunter2".to_string(),
+        });
+        let update = |password: &str| json!({"wifi": {"ssid": "home", "password": password}});
+        assert!(changes(&cfg, update(REDACTED)).is_empty());
+        assert_eq!(
+            changes(&cfg, update("correct horse")),
+            vec![r#"wifi.password: "<redacted>" -> "<redacted>""#]
+        );
+
+        let mut new = cfg.clone();
+        new.update(&TerrariumConfigUpdate {
+            wifi: Update::Clear,
+            ..TerrariumConfigUpdate::default()
+        })
+        .unwrap();
+        let result = DryRunResult::new(&cfg, &new).unwrap();
+        assert!(!serde_json::to_string(&result).unwrap().contains("hunter2"));
+    }
+}
@@ -0,0 +1,189 @@
+// A record of the last few configs, so that a bad config update can be rolled
+// back.
+
+use super::TerrariumConfig;
+use super::diff::diff;
+use jiff::Timestamp;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Configs are stored on the esp32, so only keep a few of them.
+pub const MAX_HISTORY_ENTRIES: usize = 8;
+
+// Summaries list at most this many of the changed fields.
+const MAX_SUMMARY_FIELDS: usize = 3;
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct HistoryEntry {
+    pub id: u32,
+    // When the config was applied.
+    pub timestamp: Timestamp,
+    pub summary: String,
+    pub config: TerrariumConfig,
+}
+
+// A HistoryEntry without its config, which is what GET /config/history
+// returns. Leaving out the configs keeps the response small and the secrets in
+// them private.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct HistoryEntrySummary {
+    pub id: u32,
+    pub timestamp: Timestamp,
+    pub summary: String,
+}
+
+// Oldest entry first. The last entry is the current config.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct ConfigHistory {
+    entries: Vec<HistoryEntry>,
+}
+
+impl ConfigHistory {
+    pub fn get(&self, id: u32) -> Option<&HistoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    pub fn summaries(&self) -> Vec<HistoryEntrySummary> {
+        self.entries
+            .iter()
+            .map(|entry| HistoryEntrySummary {
+                id: entry.id,
+                timestamp: entry.timestamp,
+                summary: entry.summary.clone(),
+            })
+            .collect()
+    }
+
+    // Records that the config changed from @old to @new at @now. Nothing is
+    // recorded if they're the same. The first time, @old is recorded too, so
+    // that the first update can be rolled back.
+    pub fn record(
+        &mut self,
+        old: &TerrariumConfig,
+        new: &TerrariumConfig,
+        now: Timestamp,
+    ) -> anyhow::Result<()> {
+        let changes = diff(old, new)?;
+        if changes.is_empty() {
+            return Ok(());
+        }
+        if self.entries.is_empty() {
+            self.push(old.clone(), "Config before the first recorded update", now);
+        }
+
+        let mut summary = changes
+            .iter()
+            .take(MAX_SUMMARY_FIELDS)
+            .map(|change| change.path.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if changes.len() > MAX_SUMMARY_FIELDS {
+            summary += &format!(" and {} more", changes.len() - MAX_SUMMARY_FIELDS);
+        }
+        self.push(new.clone(), &format!("Changed {summary}"), now);
+        Ok(())
+    }
+
+    // Returns the config recorded as @id and records it as the current config
+    // again, or None if there's no such entry.
+    pub fn rollback(&mut self, id: u32, now: Timestamp) -> Option<TerrariumConfig> {
+        let config = self.get(id)?.config.clone();
+        self.push(config.clone(), &format!("Rolled back to #{id}"), now);
+        Some(config)
+    }
+
+    fn push(&mut self, config: TerrariumConfig, summary: &str, now: Timestamp) {
+        let id = self.entries.last().map_or(1, |entry| entry.id + 1);
+        self.entries.push(HistoryEntry {
+            id,
+            timestamp: now,
+            summary: summary.to_string(),
+            config,
+        });
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            self.entries.remove(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod history {
+    use super::*;
+
+    fn with_timezone(timezone: &str) -> TerrariumConfig {
+        let mut cfg = TerrariumConfig::new_with_reasonable_defaults();
+        cfg.timezone = Some(timezone.to_string());
+        cfg
+    }
+
+    #[test]
+    fn record() {
+        let now = Timestamp::UNIX_EPOCH;
+        let mut history = ConfigHistory::default();
+        let original = TerrariumConfig::new_with_reasonable_defaults();
+        history.record(&original, &original, now).unwrap();
+        assert!(history.summaries().is_empty());
+
+        let berlin = with_timezone("Europe/Berlin");
+        history.record(&original, &berlin, now).unwrap();
+        let summaries: Vec<(u32, String)> = history
+            .summaries()
+            .into_iter()
+            .map(|entry| (entry.id, entry.summary))
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![
+                (1, "Config before the first recorded update".to_string()),
+                (2, "Changed timezone".to_string()),
+            ]
+        );
+        assert_eq!(history.get(1).unwrap().config, original);
+
+        let mut renamed = berlin.clone();
+        renamed.name = None;
+        renamed.active_profile = None;
+        renamed.timezone = None;
+        renamed.profiles.clear();
+        history.record(&berlin, &renamed, now).unwrap();
+        assert_eq!(
+            history.summaries()[2].summary,
+            "Changed active_profile, name, profiles.default and 1 more"
+        );
+    }
+
+    #[test]
+    fn rollback() {
+        let now = Timestamp::UNIX_EPOCH;
+        let mut history = ConfigHistory::default();
+        let original = TerrariumConfig::new_with_reasonable_defaults();
+        history
+            .record(&original, &with_timezone("Europe/Berlin"), now)
+            .unwrap();
+
+        assert_eq!(history.rollback(1, now), Some(original.clone()));
+        let latest = history.summaries().pop().unwrap();
+        assert_eq!(latest.id, 3);
+        assert_eq!(latest.summary, "Rolled back to #1");
+        assert_eq!(history.rollback(7, now), None);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let now = Timestamp::UNIX_EPOCH;
+        let mut history = ConfigHistory::default();
+        let mut cfg = TerrariumConfig::new_with_reasonable_defaults();
+        for timezone in ["UTC", "Europe/Berlin"].repeat(MAX_HISTORY_ENTRIES) {
+            let next = with_timezone(timezone);
+            history.record(&cfg, &next, now).unwrap();
+            cfg = next;
+        }
+        let summaries = history.summaries();
+        assert_eq!(summaries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!(
+            summaries.last().unwrap().id,
+            2 * MAX_HISTORY_ENTRIES as u32 + 1
+        );
+        assert!(history.get(1).is_none());
+    }
+}
@@ -0,0 +1,337 @@
+// Upgrades stored configs from older versions of the format.
+//
+// Each change to the config format that can't be handled by serde defaults
+// gets a new version number and a migration from the previous version. The
+// migrations work on the raw json document rather than on TerrariumConfig, so
+// that they keep working as TerrariumConfig continues to change. Old documents
+// are upgraded by running each migration in turn.
+//
+// Versions:
+//
+// 0: The original format, with a single `schedule`. There was no `version`
+//    field, so documents without one are version 0.
+// 1: `schedule` was replaced by named `profiles` and `active_profile`.
+// 2: Values are checked when they're parsed. `name` has to be a valid
+//    hostname, event and ramp durations are limited, intensities have to be
+//    between 0 and 1, humidity setpoints have to be below
+//    MAX_HUMIDITY_SETPOINT, and events can't repeat every 0 hours. Version 1
+//    documents that break these rules are fixed up rather than rejected.
+//
+// Every version has an example document in testdata/config/.
+
+use super::{EventSecs, MAX_HUMIDITY_SETPOINT, RampSecs};
+use crate::types::Hostname;
+use anyhow::{Context, anyhow};
+use serde_json::{Map, Value, json};
+
+pub const CURRENT_VERSION: u32 = 2;
+
+type Migration = fn(&mut Map<String, Value>) -> anyhow::Result<()>;
+
+// MIGRATIONS[i] upgrades a document from version i to version i + 1.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [v0_to_v1, v1_to_v2];
+
+// Upgrades @doc to CURRENT_VERSION. Also returns the version it started at.
+pub fn migrate(mut doc: Value) -> anyhow::Result<(Value, u32)> {
+    let cfg = doc
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("config must be a json object"))?;
+    let version = match cfg.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow!("invalid config version: {version}"))?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(anyhow!(
+            "config version {version} is newer than the latest supported version ({CURRENT_VERSION})"
+        ));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(cfg).with_context(|| {
+            format!(
+                "failed to migrate config from version {from} to {}",
+                from + 1
+            )
+        })?;
+        cfg.insert("version".to_string(), json!(from + 1));
+    }
+
+    Ok((doc, version))
+}
+
+fn v0_to_v1(cfg: &mut Map<String, Value>) -> anyhow::Result<()> {
+    match cfg.remove("schedule") {
+        Some(Value::Object(schedule)) => {
+            let profiles =
+                Map::from_iter([(super::DEFAULT_PROFILE.to_string(), Value::Object(schedule))]);
+            cfg.insert("profiles".to_string(), Value::Object(profiles));
+            cfg.insert("active_profile".to_string(), json!(super::DEFAULT_PROFILE));
+        }
+        None | Some(Value::Null) => {
+            cfg.insert("profiles".to_string(), json!({}));
+            cfg.insert("active_profile".to_string(), Value::Null);
+        }
+        Some(other) => return Err(anyhow!("expected schedule to be an object, got {other}")),
+    }
+    Ok(())
+}
+
+fn v1_to_v2(cfg: &mut Map<String, Value>) -> anyhow::Result<()> {
+    if let Some(Value::String(name)) = cfg.get("name") {
+        let name = sanitize_hostname(name);
+        cfg.insert("name".to_string(), name.map_or(Value::Null, Value::String));
+    }
+
+    let Some(Value::Object(profiles)) = cfg.get_mut("profiles") else {
+        return Ok(());
+    };
+    for schedule in profiles.values_mut().filter_map(Value::as_object_mut) {
+        clamp(schedule.get_mut("light_intensity"), 1.0);
+        // MAX_HUMIDITY_SETPOINT itself isn't allowed, so this goes a percent
+        // under it.
+        clamp(
+            schedule.get_mut("humidity_setpoint"),
+            (f64::from(MAX_HUMIDITY_SETPOINT) * 100.0 - 1.0).round() / 100.0,
+        );
+        for events in ["mist", "fans"] {
+            if let Some(Value::Array(events)) = schedule.get_mut(events) {
+                for event in events {
+                    clamp(event.get_mut("duration_secs"), EventSecs::MAX as f64);
+                    // Repeating every 0 hours hung the firmware, so those
+                    // events can't have worked. They run once instead.
+                    if let Some(repeat) = event.get_mut("repeat")
+                        && repeat.get("n_hours") == Some(&json!(0))
+                    {
+                        *repeat = Value::Null;
+                    }
+                }
+            }
+        }
+        for ramp in ["sunrise", "sunset"] {
+            if let Some(ramp) = schedule.get_mut(ramp) {
+                clamp(ramp.get_mut("duration_secs"), RampSecs::MAX as f64);
+            }
+        }
+        if let Some(Value::Array(keyframes)) = schedule.get_mut("light_profile") {
+            for keyframe in keyframes {
+                clamp(keyframe.get_mut("intensity"), 1.0);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Turns @name into a valid hostname by replacing anything that isn't allowed
+// with '-', or returns None if there's nothing left of it.
+fn sanitize_hostname(name: &str) -> Option<String> {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(Hostname::MAX_LEN)
+        .collect();
+    let name = name.trim_matches('-');
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Limits @value to between 0 and @max, if it's a number.
+fn clamp(value: Option<&mut Value>, max: f64) {
+    if let Some(value) = value
+        && let Some(n) = value.as_f64()
+        && !(0.0..=max).contains(&n)
+    {
+        *value = if value.is_f64() {
+            json!(n.clamp(0.0, max))
+        } else {
+            json!(n.clamp(0.0, max) as u64)
+        };
+    }
+}
+
+#[cfg(test)]
+mod migration {
+    use super::*;
+    use crate::config::validation::ValidationReport;
+    use crate::config::{
+        DEFAULT_PROFILE, LightRamp, RampCurve, Schedule, ScheduleUpdate, TerrariumConfig, Weekday,
+        WifiDetails,
+    };
+    use crate::influxdb;
+    use crate::types::{BoundedSecs, Hostname, RelativeHumidity};
+    use std::collections::BTreeMap;
+
+    const V0: &str = include_str!("../../testdata/config/v0.json");
+    const V0_NO_SCHEDULE: &str = include_str!("../../testdata/config/v0_no_schedule.json");
+    const V1: &str = include_str!("../../testdata/config/v1.json");
+    const V1_INVALID: &str = include_str!("../../testdata/config/v1_invalid.json");
+    const V2: &str = include_str!("../../testdata/config/v2.json");
+
+    fn load(json: &str) -> (TerrariumConfig, u32) {
+        TerrariumConfig::from_json(json.as_bytes()).unwrap()
+    }
+
+    fn v1_profiles() -> BTreeMap<String, Schedule> {
+        let normal = Schedule::new_with_reasonable_defaults();
+        let mut vacation = Schedule {
+            sunrise: Some(LightRamp {
+                duration_secs: BoundedSecs::new(1800).unwrap(),
+                curve: RampCurve::Sine,
+            }),
+            auto_mist_enabled: true,
+            humidity_setpoint: Some(RelativeHumidity::new(0.7).unwrap()),
+            ..normal.clone()
+        };
+        vacation.mist.truncate(1);
+        vacation.mist[0].days = Some(vec![Weekday::Mon, Weekday::Thu]);
+        BTreeMap::from([
+            ("normal".to_string(), normal),
+            ("vacation".to_string(), vacation),
+        ])
+    }
+
+    #[test]
+    fn v0() {
+        let (cfg, version) = load(V0);
+        assert_eq!(version, 0);
+        assert_eq!(
+            cfg,
+            TerrariumConfig {
+                wifi: Some(WifiDetails {
+                    ssid: "home".to_string(),
+                    password: "hunter22".to_string(),
+                }),
+                timezone: Some("America/New_York".to_string()),
+                influxdb: Some(influxdb::Config {
+                    address: "http://influx.local:8086".to_string(),
+                    org: "home".to_string(),
+                    bucket: "terrarium".to_string(),
+                    token: "secret-token".to_string(),
+                }),
+                ..TerrariumConfig::new_with_reasonable_defaults()
+            }
+        );
+        assert_eq!(cfg.active_profile, Some(DEFAULT_PROFILE.to_string()));
+    }
+
+    #[test]
+    fn v0_no_schedule() {
+        let (cfg, version) = load(V0_NO_SCHEDULE);
+        assert_eq!(version, 0);
+        assert_eq!(
+            cfg,
+            TerrariumConfig {
+                name: Some(Hostname::try_from("oasis").unwrap()),
+                ..TerrariumConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn v1() {
+        let (cfg, version) = load(V1);
+        assert_eq!(version, 1);
+        assert_eq!(cfg.profiles, v1_profiles());
+        assert_eq!(cfg.active_profile, Some("vacation".to_string()));
+    }
+
+    #[test]
+    fn v1_invalid() {
+        let (cfg, version) = load(V1_INVALID);
+        assert_eq!(version, 1);
+        assert_eq!(
+            cfg.name,
+            Some(Hostname::try_from("my-oasis--living-room").unwrap())
+        );
+        assert_eq!(cfg.wifi.unwrap().ssid, "home");
+        let schedule = &cfg.profiles["normal"];
+        assert_eq!(schedule.light_intensity.unwrap().get(), 1.0);
+        assert_eq!(
+            schedule.sunrise.as_ref().unwrap().duration_secs.get(),
+            12 * 60 * 60
+        );
+        assert_eq!(schedule.mist[0].duration_secs.get(), 24 * 60 * 60);
+        assert_eq!(schedule.fans[0].repeat, None);
+        assert_eq!(schedule.humidity_setpoint.unwrap().get(), 0.94);
+        // The migrated schedule is valid as an update too, such as when a
+        // profile is copied.
+        assert!(ScheduleUpdate::from(schedule).validate().is_ok());
+    }
+
+    #[test]
+    fn v2() {
+        let (cfg, version) = load(V2);
+        assert_eq!(version, 2);
+        assert_eq!(cfg.profiles, v1_profiles());
+
+        // The current version round-trips unchanged.
+        let expected: Value = serde_json::from_str(V2).unwrap();
+        let json = serde_json::to_string(&cfg).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    }
+
+    #[test]
+    fn invalid_schedule() {
+        let path_for = |edit: &dyn Fn(&mut Value)| {
+            let mut doc: Value = serde_json::from_str(V2).unwrap();
+            edit(&mut doc["profiles"]["normal"]);
+            TerrariumConfig::from_json(doc.to_string().as_bytes())
+                .unwrap_err()
+                .downcast::<ValidationReport>()
+                .unwrap()
+                .errors
+                .remove(0)
+                .path
+        };
+        assert_eq!(
+            path_for(&|s| s["lights"]["stop"] = json!("09:00:00")),
+            "profiles.normal.lights.stop"
+        );
+        assert_eq!(
+            path_for(&|s| {
+                s["light_profile"] = json!([
+                    {"time": "12:00:00", "intensity": 0.5},
+                    {"time": "08:00:00", "intensity": 1.0},
+                ])
+            }),
+            "profiles.normal.light_profile[1].time"
+        );
+        assert_eq!(
+            path_for(&|s| s["fans"][0]["repeat"]["stop_time"] = json!("09:00:00")),
+            "profiles.normal.fans[0].repeat.stop_time"
+        );
+
+        // A repeat every 0 hours isn't valid json for the current version.
+        let mut doc: Value = serde_json::from_str(V2).unwrap();
+        doc["profiles"]["normal"]["fans"][0]["repeat"]["n_hours"] = json!(0);
+        assert!(TerrariumConfig::from_json(doc.to_string().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn sanitize_hostname() {
+        assert_eq!(super::sanitize_hostname("oasis"), Some("oasis".to_string()));
+        assert_eq!(
+            super::sanitize_hostname("_my.oasis_"),
+            Some("my-oasis".to_string())
+        );
+        assert_eq!(
+            super::sanitize_hostname(&"x".repeat(40)),
+            Some("x".repeat(Hostname::MAX_LEN))
+        );
+        assert_eq!(super::sanitize_hostname("!!!"), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(migrate(json!([])).is_err());
+        assert!(migrate(json!({"version": "1"})).is_err());
+        assert!(migrate(json!({"version": CURRENT_VERSION + 1})).is_err());
+
+        let err = migrate(json!({"schedule": 5})).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to migrate config from version 0 to 1"
+        );
+    }
+}
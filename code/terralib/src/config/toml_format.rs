@@ -0,0 +1,424 @@
+// Reading and writing configs as toml.
+//
+// The config types are only designed to be (de)serialized as json, so rather
+// than serializing them to toml directly, toml documents are converted to and
+// from json values. Two things need special handling:
+//
+// - toml has native dates and times, like `start = 10:00:00`, which are
+//   converted to json strings. Going the other way, any string that is a valid
+//   toml date or time is written as one, so that times don't need quoting.
+// - toml has no null, so null fields are left out of the toml. For configs this
+//   is equivalent when reading it back, but updates that clear a field can't be
+//   written as toml.
+//
+// When writing toml, each field gets a short comment saying what it is, the
+// first time it appears. See field_comment().
+
+use anyhow::anyhow;
+use serde_json::{Map, Number, Value};
+
+// Parses the toml document @s into the equivalent json value.
+pub fn toml_to_json(s: &str) -> anyhow::Result<Value> {
+    let doc: toml::Table = s.parse()?;
+    Ok(table_to_json(doc))
+}
+
+fn table_to_json(table: toml::Table) -> Value {
+    Value::Object(
+        table
+            .into_iter()
+            .map(|(k, v)| (k, value_to_json(v)))
+            .collect(),
+    )
+}
+
+fn value_to_json(v: toml::Value) -> Value {
+    match v {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(value_to_json).collect()),
+        toml::Value::Table(t) => table_to_json(t),
+    }
+}
+
+// Formats the json value @v, which must be an object, as a toml document.
+// @header is written as a comment at the top of the document.
+pub fn json_to_toml(v: &Value, header: &str) -> anyhow::Result<String> {
+    let Value::Object(obj) = v else {
+        return Err(anyhow!("toml document must be a table"));
+    };
+    let table = object_to_toml(obj, "")?;
+    let mut out = String::new();
+    for line in header.lines() {
+        if line.is_empty() {
+            out.push_str("#\n");
+        } else {
+            out.push_str(&format!("# {line}\n"));
+        }
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(&add_field_comments(&toml::to_string(&table)?));
+    Ok(out)
+}
+
+// Adds a comment from field_comment() above each key and table header in the
+// toml document @toml, the first time each one appears.
+fn add_field_comments(toml: &str) -> String {
+    let mut out = String::new();
+    let mut commented = std::collections::HashSet::new();
+    // The path of the current table, as in `[profiles.normal.lights]`.
+    let mut table: Vec<String> = vec![];
+    let mut in_multiline_string = false;
+    for line in toml.lines() {
+        let comment = if in_multiline_string {
+            None
+        } else if let Some(header) = line
+            .strip_prefix("[[")
+            .and_then(|l| l.strip_suffix("]]"))
+            .or_else(|| line.strip_prefix('[').and_then(|l| l.strip_suffix(']')))
+        {
+            table = split_key_path(header);
+            table.split_last().and_then(|(key, parent)| {
+                // Every profile gets the comment for `profiles`, once.
+                let (parent, key) = match table_kind(parent) {
+                    "profiles" => ("", "profiles"),
+                    parent => (parent, key.as_str()),
+                };
+                field_comment(parent, key)
+                    .filter(|_| commented.insert((parent.to_string(), key.to_string())))
+            })
+        } else if let Some((key, _)) = line.split_once(" = ") {
+            let parent = table_kind(&table);
+            field_comment(parent, key)
+                .filter(|_| commented.insert((parent.to_string(), key.to_string())))
+        } else {
+            None
+        };
+        if let Some(comment) = comment {
+            if line.starts_with('[') && !out.is_empty() && !out.ends_with("\n\n") {
+                out.push('\n');
+            }
+            for comment_line in comment.lines() {
+                out.push_str(&format!("# {comment_line}\n"));
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+        // Multi-line strings are the only values that span lines, and their
+        // contents mustn't be mistaken for keys.
+        if line.matches("\"\"\"").count() % 2 == 1 || line.matches("'''").count() % 2 == 1 {
+            in_multiline_string = !in_multiline_string;
+        }
+    }
+    out
+}
+
+// Splits a dotted toml key like `profiles."my profile".mist` into its parts.
+fn split_key_path(path: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut part = String::new();
+    let mut quote = None;
+    for c in path.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '.') => parts.push(std::mem::take(&mut part)),
+            _ => part.push(c),
+        }
+    }
+    parts.push(part);
+    parts
+}
+
+// Returns the name that field_comment() knows the table at @path by. Profiles
+// have arbitrary names, so they're all "schedule", like the schedule in an
+// update.
+fn table_kind(path: &[String]) -> &str {
+    match path {
+        [] => "",
+        [.., parent, _] if parent == "profiles" => "schedule",
+        [.., last] => last,
+    }
+}
+
+// Describes the field @key of the table @table (see table_kind()), or returns
+// None if there's nothing useful to say about it.
+fn field_comment(table: &str, key: &str) -> Option<&'static str> {
+    Some(match (table, key) {
+        ("", "version") => "Version of the config format. Older versions are migrated when read.",
+        ("", "name") => "Hostname of the terrarium: letters, digits and '-', at most 30 long.",
+        ("", "wifi") => "Network to connect to.",
+        ("", "profiles") => "Named schedules. Only the schedule of active_profile is run.",
+        ("", "schedule") => "Changes to the schedule of the active profile.",
+        ("", "rename_profiles") => "Applied in order, before any other profile changes.",
+        ("", "active_profile") => "If not set, nothing is scheduled.",
+        ("", "timezone") => {
+            "Timezone name, such as \"America/Los_Angeles\". If not set, US West Coast\ntime is used."
+        }
+        ("", "influxdb") => "Where sensor readings are sent.",
+        ("", "thermal") => {
+            "When to dim the lights to keep the terrarium from overheating. If not set,\nthe defaults are used."
+        }
+
+        ("schedule", "lights") => "The lights are on from start to stop.",
+        ("schedule", "light_intensity") => "From 0 to 1.",
+        ("schedule", "sunrise") => "Ramps the lights up at the start of the light period.",
+        ("schedule", "sunset") => "Ramps the lights down at the end of the light period.",
+        ("schedule", "light_profile") => {
+            "Light intensity keyframes, interpolated linearly. If any are given, they\nare used instead of lights, light_intensity, sunrise and sunset."
+        }
+        ("schedule", "solar_lights") => {
+            "Follows sunrise and sunset at a real-world location instead of lights."
+        }
+        ("schedule", "fans") => {
+            "Runs the fans for duration_secs at start_time, or each time cron fires."
+        }
+        ("schedule", "mist") => "Mists for duration_secs at start_time, or each time cron fires.",
+        ("schedule", "auto_mist_enabled") => "Mists to keep the humidity above humidity_setpoint.",
+        ("schedule", "humidity_setpoint") => "Relative humidity, from 0 to 1.",
+        ("schedule", "humidity_control") => {
+            "How auto-mist holds the humidity. If not set, the defaults are used."
+        }
+
+        (_, "days") => "\"mon\" to \"sun\". If not set, every day.",
+        ("sunrise" | "sunset", "duration_secs") => "Length of the ramp, in seconds.",
+        ("sunrise" | "sunset", "curve") => "\"linear\", \"sine\" or \"smoothstep\".",
+        ("light_profile", "intensity") => "From 0 to 1.",
+        ("solar_lights", "latitude") => "Degrees north.",
+        ("solar_lights", "longitude") => "Degrees east.",
+        ("solar_lights", "timezone") => {
+            "Timezone of the location. If not set, the terrarium's timezone is used."
+        }
+        ("solar_lights", "sunrise_offset_mins" | "sunset_offset_mins") => "Positive is later.",
+        ("solar_lights", "min_day_length_mins" | "max_day_length_mins") => {
+            "Limits on the length of the light period, in minutes."
+        }
+        ("fans" | "mist", "start_time") => "Ignored if cron is set.",
+        ("fans" | "mist", "duration_secs") => "In seconds.",
+        ("fans" | "mist", "repeat") => {
+            "Runs again every n_hours, as long as it starts by stop_time."
+        }
+        ("fans" | "mist", "cron") => "minute hour day-of-month month day-of-week",
+
+        ("humidity_control", "hysteresis") => {
+            "How far below the setpoint the humidity has to drop before misting starts."
+        }
+        ("humidity_control", "max_on_secs") => "At most this much misting within any window_secs.",
+        ("humidity_control", "sampling") => {
+            "If set, auto-mist only uses readings taken after running the fans."
+        }
+        ("thermal", "air") => "Air temperature, in degrees Celsius.",
+        ("thermal", "cpu") => "Temperature of the esp32 chip, in degrees Celsius.",
+        ("air" | "cpu", "warn_c") => "The lights are dimmed above this.",
+        ("air" | "cpu", "critical_c") => "The lights are turned off above this.",
+        _ => return None,
+    })
+}
+
+fn object_to_toml(obj: &Map<String, Value>, path: &str) -> anyhow::Result<toml::Table> {
+    let mut table = toml::Table::new();
+    for (k, v) in obj {
+        let path = if path.is_empty() {
+            k.clone()
+        } else {
+            format!("{path}.{k}")
+        };
+        if v.is_null() {
+            continue;
+        }
+        table.insert(k.clone(), json_value_to_toml(v, &path)?);
+    }
+    Ok(table)
+}
+
+fn json_value_to_toml(v: &Value, path: &str) -> anyhow::Result<toml::Value> {
+    Ok(match v {
+        Value::Null => {
+            return Err(anyhow!(
+                "{path} is null, which can't be represented in toml"
+            ));
+        }
+        Value::Bool(b) => toml::Value::Boolean(*b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                toml::Value::Integer(i)
+            } else if let Some(f) = n.as_f64()
+                && n.is_f64()
+            {
+                toml::Value::Float(f)
+            } else {
+                return Err(anyhow!("{path} is too large to represent in toml: {n}"));
+            }
+        }
+        Value::String(s) => match s.parse::<toml::value::Datetime>() {
+            Ok(dt) if dt.to_string() == *s => toml::Value::Datetime(dt),
+            _ => toml::Value::String(s.clone()),
+        },
+        Value::Array(a) => toml::Value::Array(
+            a.iter()
+                .enumerate()
+                .map(|(i, v)| json_value_to_toml(v, &format!("{path}[{i}]")))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(obj) => toml::Value::Table(object_to_toml(obj, path)?),
+    })
+}
+
+#[cfg(test)]
+mod toml_format {
+    use crate::config::{
+        ScheduleUpdate, ScheduledEvent, TerrariumConfig, TerrariumConfigUpdate, TimeRange, Update,
+        Weekday,
+    };
+    use crate::types::{BoundedSecs, Intensity};
+    use jiff::civil::time;
+
+    #[test]
+    fn config_round_trip() {
+        let (cfg, _) =
+            TerrariumConfig::from_json(include_bytes!("../../testdata/config/v2.json")).unwrap();
+        let toml = cfg.to_toml().unwrap();
+        assert!(toml.starts_with("# Oasis terrarium config.\n"));
+        // Times are written as toml times, and f32s without extra digits.
+        assert!(toml.contains("start = 10:00:00\n"));
+        assert!(toml.contains("humidity_setpoint = 0.7\n"));
+        // Unset fields are left out.
+        assert!(!toml.contains("timezone ="));
+        assert_eq!(TerrariumConfig::from_toml(&toml).unwrap(), (cfg, 2));
+    }
+
+    #[test]
+    fn field_comments() {
+        let (cfg, _) = TerrariumConfig::from_toml(
+            r#"
+            version = 2
+            active_profile = "living-room"
+
+            [profiles.living-room]
+            fans = []
+            mist = []
+            auto_mist_enabled = false
+            light_intensity = 0.5
+
+            [profiles.living-room.lights]
+            start = 08:00:00
+            stop = 20:00:00
+
+            [profiles.other]
+            fans = []
+            mist = []
+            auto_mist_enabled = false
+            light_intensity = 0.5
+            "#,
+        )
+        .unwrap();
+        let toml = cfg.to_toml().unwrap();
+        assert!(toml.contains(
+            "# Named schedules. Only the schedule of active_profile is run.\n[profiles.living-room]\n"
+        ));
+        assert!(
+            toml.contains(
+                "# The lights are on from start to stop.\n[profiles.living-room.lights]\n"
+            )
+        );
+        // Each field is only described the first time.
+        assert_eq!(toml.matches("# From 0 to 1.\n").count(), 1);
+        assert!(toml.contains("\n\n[profiles.other]\n"));
+        assert_eq!(TerrariumConfig::from_toml(&toml).unwrap(), (cfg, 2));
+    }
+
+    #[test]
+    fn config_migration() {
+        let (cfg, version) = TerrariumConfig::from_toml(
+            r#"
+            name = "oasis"
+
+            [schedule]
+            fans = []
+            mist = []
+            auto_mist_enabled = false
+
+            [schedule.lights]
+            start = 08:00:00
+            stop = 20:00:00
+            "#,
+        )
+        .unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(
+            cfg.active_schedule().unwrap().lights,
+            Some(TimeRange {
+                start: time(8, 0, 0, 0),
+                stop: time(20, 0, 0, 0),
+                days: None,
+            })
+        );
+    }
+
+    #[test]
+    fn update() {
+        let update = TerrariumConfigUpdate::from_toml(
+            r#"
+            # Switch to the summer schedule.
+            active_profile = "summer"
+
+            [profiles.summer]
+            light_intensity = 1 # full brightness
+            lights = { start = 07:00:00, stop = 21:00:00 }
+
+            # Mist twice a week.
+            [[profiles.summer.mist]]
+            start_time = 09:30:00
+            duration_secs = 45
+            days = ["tue", "sat"]
+            "#,
+        )
+        .unwrap();
+        let expected = TerrariumConfigUpdate {
+            active_profile: Update::Set("summer".to_string()),
+            profiles: [(
+                "summer".to_string(),
+                Update::Set(ScheduleUpdate {
+                    light_intensity: Update::Set(Intensity::new(1.0).unwrap()),
+                    lights: Update::Set(TimeRange {
+                        start: time(7, 0, 0, 0),
+                        stop: time(21, 0, 0, 0),
+                        days: None,
+                    }),
+                    mist: Update::Set(vec![ScheduledEvent {
+                        start_time: time(9, 30, 0, 0),
+                        duration_secs: BoundedSecs::new(45).unwrap(),
+                        days: Some(vec![Weekday::Tue, Weekday::Sat]),
+                        ..ScheduledEvent::default()
+                    }]),
+                    ..ScheduleUpdate::default()
+                }),
+            )]
+            .into(),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert_eq!(update, expected);
+
+        let toml = update.to_toml().unwrap();
+        assert!(toml.contains("# Fields that are left out aren't changed"));
+        assert_eq!(TerrariumConfigUpdate::from_toml(&toml).unwrap(), expected);
+    }
+
+    #[test]
+    fn update_clear_not_supported() {
+        let update = TerrariumConfigUpdate {
+            timezone: Update::Clear,
+            ..TerrariumConfigUpdate::default()
+        };
+        let err = update.to_toml().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "update clears fields, which can't be represented in toml"
+        );
+    }
+}
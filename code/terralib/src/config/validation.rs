@@ -0,0 +1,95 @@
+// Reporting of problems found when validating a config update.
+//
+// Validation doesn't stop at the first problem. Everything that's wrong with
+// an update is collected into a ValidationReport so that it can all be shown
+// to the user at once, next to the fields that need fixing.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    OutOfRange,
+    Empty,
+    TooLong,
+    TooMany,
+    InvalidCharacters,
+    InvalidOrder,
+    Duplicate,
+    Conflict,
+    InvalidTimezone,
+    NotFound,
+    AlreadyExists,
+    // The value couldn't be parsed as the field's type.
+    InvalidValue,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct ValidationError {
+    // Path of the offending field within the update, such as
+    // `schedule.mist[2].repeat.stop_time`.
+    pub path: String,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationError>,
+}
+
+impl ValidationReport {
+    // A report with just one error.
+    pub fn single(path: &str, code: ErrorCode, message: impl Into<String>) -> Self {
+        let mut report = Self::default();
+        report.add(path, code, message);
+        report
+    }
+
+    pub fn add(&mut self, path: &str, code: ErrorCode, message: impl Into<String>) {
+        self.errors.push(ValidationError {
+            path: path.to_string(),
+            code,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    // Ok if no problems were found.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, err) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", err.path, err.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationReport {}
+
+// Returns the path of field @name of the object at @path.
+pub(crate) fn field(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
+
+// Returns the path of element @i of the array at @path.
+pub(crate) fn index(path: &str, i: usize) -> String {
+    format!("{path}[{i}]")
+}
@@ -1,6 +1,16 @@
-use crate::config::{Schedule, TerrariumConfig, TerrariumConfigUpdate, Update};
-use crate::terrarium::Terrarium;
-use crate::types::{Actuator, ActuatorOverrideSet, ActuatorValue, ActuatorValues};
+use crate::config::diff::DryRunResult;
+use crate::config::history::ConfigHistory;
+use crate::config::{SchedulePreviewRequest, TerrariumConfig, TerrariumConfigUpdate};
+use crate::events::{Event, EventKind, EventLog};
+use crate::humidity::{HumidityController, SampleStep, SensorSampler};
+use crate::mist_interlock::MistInterlock;
+use crate::reasons::{ActuatorReason, ActuatorReasons};
+use crate::terrarium::{Terrarium, get_terrarium_state};
+use crate::thermal::ThermalProtection;
+use crate::types::{
+    ActiveOverride, Actuator, ActuatorOverrideSet, ActuatorValue, ActuatorValues, OverrideHold,
+    OverrideSequence, TerrariumState,
+};
 use anyhow::anyhow;
 use embassy_time::Timer;
 use std::collections::HashMap;
@@ -10,12 +20,61 @@ use std::time::{Duration, Instant};
 // Max amount of time that a control override can specify is 30 minutes.
 const MAX_OVERRIDE_DURATION_SECS: u32 = 30 * 60;
 
-const DEFAULT_TIMEZONE: &'static str = "America/Los_Angeles";
+// Sequences are kept in memory on the esp32, so limit how long they can be.
+const MAX_SEQUENCE_STEPS: usize = 16;
+
+// Every transition in the schedule preview is returned in one response, and a
+// schedule with frequent cron or repeating events has thousands of them a day,
+// so limit how far ahead it can look to keep the response small enough for the
+// esp32.
+pub const MAX_SCHEDULE_PREVIEW_HOURS: u32 = 72;
+
+// Temperatures change slowly, so there's no need to read them on every
+// iteration of the run() loop.
+const THERMAL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 
 struct ActuatorOverride {
     value: ActuatorValue,
     start: Instant,
     duration: Duration,
+    // Whether this was applied by the running OverrideSequence, and so is
+    // cancelled along with it.
+    from_sequence: bool,
+    hold: Option<OverrideHold>,
+    // Whether the schedule had the actuator on when the override started, for
+    // OverrideHold::NextTransition. Set by the first call to expired().
+    scheduled_on: Option<bool>,
+}
+
+impl ActuatorOverride {
+    // Whether the override is over at @now. @scheduled_on is whether the
+    // schedule has the actuator on at @now.
+    fn expired(&mut self, scheduled_on: bool, now: Instant) -> bool {
+        match self.hold {
+            None => now - self.start > self.duration,
+            Some(OverrideHold::NextTransition) => {
+                *self.scheduled_on.get_or_insert(scheduled_on) != scheduled_on
+            }
+            Some(OverrideHold::Manual) => false,
+        }
+    }
+
+    // Time left at @now, or None if the override is held.
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        match self.hold {
+            None => Some(self.duration.saturating_sub(now - self.start)),
+            Some(_) => None,
+        }
+    }
+}
+
+// An OverrideSequence that's being executed by run().
+struct RunningSequence {
+    sequence: OverrideSequence,
+    // Index of the next step to apply. Once they've all been applied, the
+    // sequence runs until the last step is over.
+    next_step: usize,
+    next_step_at: Instant,
 }
 
 // The TerrariumController manages the terrarium hardware and its configuration
@@ -23,20 +82,48 @@ struct ActuatorOverride {
 // (temporary controls). Note that although wifi details are part of
 // TerrariumConfig, wifi management is handled external to the controller.
 pub struct TerrariumController {
+    // The terrarium behind @mist_interlock, which is what everything that uses
+    // the controller's terrarium goes through.
     terrarium: Arc<Mutex<dyn Terrarium + Send>>,
+    mist_interlock: Arc<Mutex<MistInterlock>>,
     config: TerrariumConfig,
+    // Configs applied by update_config() and rollback_config().
+    history: ConfigHistory,
     active_overrides: HashMap<Actuator, ActuatorOverride>,
+    sequence: Option<RunningSequence>,
+    humidity_controller: HumidityController,
+    sensor_sampler: SensorSampler,
+    last_sensor_sample: Option<jiff::Timestamp>,
+    thermal: ThermalProtection,
+    last_thermal_check: Option<Instant>,
+    events: EventLog,
+    // Why each actuator was set the way it was on the last run().
+    reasons: ActuatorReasons,
     // TODO: use a mutex for external_light_control?
     external_light_control: bool,
+    // The effect that has taken over the lights, if that's what has.
+    light_effect: Option<String>,
 }
 
 impl TerrariumController {
     pub fn new(terrarium: Arc<Mutex<dyn Terrarium + Send>>, config: TerrariumConfig) -> Self {
+        let mist_interlock = Arc::new(Mutex::new(MistInterlock::new(terrarium)));
         Self {
-            terrarium,
+            terrarium: mist_interlock.clone(),
+            mist_interlock,
             config,
+            history: ConfigHistory::default(),
             active_overrides: HashMap::new(),
+            sequence: None,
+            humidity_controller: HumidityController::new(),
+            sensor_sampler: SensorSampler::new(),
+            last_sensor_sample: None,
+            thermal: ThermalProtection::new(),
+            last_thermal_check: None,
+            events: EventLog::default(),
+            reasons: ActuatorReasons::default(),
             external_light_control: false,
+            light_effect: None,
         }
     }
 
@@ -50,8 +137,15 @@ impl TerrariumController {
         self.external_light_control = true;
     }
 
+    // Like takeover_lights(), for running the light effect called @name.
+    pub fn takeover_lights_for_effect(&mut self, name: &str) {
+        self.takeover_lights();
+        self.light_effect = Some(name.to_string());
+    }
+
     pub fn release_lights(&mut self) {
         self.external_light_control = false;
+        self.light_effect = None;
     }
 
     pub fn terrarium(&self) -> Arc<Mutex<dyn Terrarium>> {
@@ -62,122 +156,238 @@ impl TerrariumController {
         &self.config
     }
 
-    pub fn update_config(&mut self, update: &TerrariumConfigUpdate) -> anyhow::Result<()> {
-        // validate updates first - we don't want to fail halfway through the
-        // update and end up with an inconsistent state. If the update is bad,
-        // fail early.
-        update.validate()?;
+    // The terrarium's current actuator values and sensor readings, along with
+    // what the controller knows about them.
+    pub fn state(&self) -> TerrariumState {
+        // @terrarium is the interlock, so only lock one of them at a time.
+        let mist_interlock = self.mist_interlock.lock().unwrap().status();
+        let state = get_terrarium_state(&mut *self.terrarium.lock().unwrap());
+        TerrariumState {
+            last_sensor_sample: self.last_sensor_sample,
+            thermal: self.thermal.status(),
+            mist_interlock,
+            overrides: self.active_overrides(Instant::now()),
+            reasons: self.reasons.clone(),
+            ..state
+        }
+    }
 
-        match &update.name {
-            Update::Set(name) => self.config.name = Some(name.clone()),
-            Update::Clear => self.config.name = None,
-            Update::NoChange => {}
-        };
+    fn active_overrides(&self, now: Instant) -> Vec<ActiveOverride> {
+        [Actuator::Lights, Actuator::Fans, Actuator::Mist]
+            .into_iter()
+            .filter_map(|actuator| {
+                let o = self.active_overrides.get(&actuator)?;
+                Some(ActiveOverride {
+                    actuator,
+                    value: o.value,
+                    // Rounded up, so an override isn't reported as having no
+                    // time left while it's still in effect.
+                    remaining_secs: o
+                        .remaining(now)
+                        .map(|left| left.as_secs_f32().ceil() as u32),
+                    hold: o.hold,
+                    from_sequence: o.from_sequence,
+                })
+            })
+            .collect()
+    }
 
-        match &update.wifi {
-            Update::Set(wifi) => self.config.wifi = Some(wifi.clone()),
-            Update::Clear => self.config.wifi = None,
-            Update::NoChange => {}
-        };
+    // Recent events, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.events()
+    }
 
-        match &update.schedule {
-            Update::Set(schedule_update) => {
-                if self.config.schedule.is_none() {
-                    self.config.schedule = Some(Schedule::default());
-                }
-                if let Some(schedule) = &mut self.config.schedule {
-                    schedule.update(schedule_update);
-                }
-            }
-            Update::Clear => self.config.schedule = None,
-            Update::NoChange => {}
-        };
+    pub fn update_config(&mut self, update: &TerrariumConfigUpdate) -> anyhow::Result<()> {
+        let config = self.updated_config(update)?;
+        self.history
+            .record(&self.config, &config, jiff::Timestamp::now())?;
+        self.config = config;
+        Ok(())
+    }
 
-        match &update.timezone {
-            Update::Set(timezone) => self.config.timezone = Some(timezone.clone()),
-            Update::Clear => self.config.timezone = None,
-            Update::NoChange => {}
-        }
+    pub fn history(&self) -> &ConfigHistory {
+        &self.history
+    }
 
-        match &update.influxdb {
-            Update::Set(influxdb) => self.config.influxdb = Some(influxdb.clone()),
-            Update::Clear => self.config.influxdb = None,
-            Update::NoChange => {}
-        };
+    // Replaces the history, such as with one that was saved before a restart.
+    pub fn set_history(&mut self, history: ConfigHistory) {
+        self.history = history;
+    }
 
+    // Goes back to the config recorded in history entry @id.
+    pub fn rollback_config(&mut self, id: u32) -> anyhow::Result<()> {
+        self.config = self
+            .history
+            .rollback(id, jiff::Timestamp::now())
+            .ok_or_else(|| anyhow!("There's no config history entry #{id}"))?;
         Ok(())
     }
 
+    // Checks @update the same way update_config() does, but instead of
+    // applying it, returns the config it would produce and what would change.
+    pub fn dry_run_config_update(
+        &self,
+        update: &TerrariumConfigUpdate,
+    ) -> anyhow::Result<DryRunResult> {
+        DryRunResult::new(&self.config, &self.updated_config(update)?)
+    }
+
+    fn updated_config(&self, update: &TerrariumConfigUpdate) -> anyhow::Result<TerrariumConfig> {
+        // validate updates first - we don't want to fail halfway through the
+        // update and end up with an inconsistent state. If the update is bad,
+        // fail early.
+        update.validate()?;
+
+        // Apply the update to a copy, so that if it turns out to be invalid
+        // for the current config, nothing is changed.
+        let mut config = self.config.clone();
+        config.update(update)?;
+        Ok(config)
+    }
+
     pub fn run(&mut self) -> anyhow::Result<()> {
-        let t = self.get_local_time();
+        let now = self.get_local_time();
+
+        let instant_now = Instant::now();
 
         let mut act_val = ActuatorValues::default();
+        let (scheduled, mut reasons) = self
+            .config
+            .active_schedule()
+            .map(|schedule| schedule.evaluate_with_reasons(&now))
+            .unwrap_or_default();
 
-        if let Some(schedule) = &self.config.schedule {
+        if let Some(schedule) = self.config.active_schedule() {
             // Turn on actuators based on the configured schedule.
-            act_val = schedule.evaluate(t);
+            act_val = scheduled;
 
             // Automatic misting based on humidity_setpoint
-            //
-            // TODO: this implementation is very simplistic and could be improved a lot
-            // - humidity readings lag partially due to the placement of the sensor and lack of air movement.
-            // - periodically turning on the fans could help the sensor to get an accurate reading more often
-            // - we should add some rate-limiting i.e. "mist for a maximum of one minute straight every ten minutes"
-            if schedule.auto_mist_enabled {
-                if let Some(setpoint) = schedule.humidity_setpoint {
-                    match self.terrarium.lock().unwrap().read_sensors() {
-                        Some(sensor_values) => {
-                            if sensor_values.humid < setpoint {
-                                act_val.mist = true; // Turn mist ON if below setpoint
+            match schedule.humidity_setpoint {
+                Some(setpoint) if schedule.auto_mist_enabled => {
+                    let humidity_control = schedule.humidity_control.clone().unwrap_or_default();
+                    let humidity = match &humidity_control.sampling {
+                        Some(sampling) => {
+                            match self.sensor_sampler.update(sampling, instant_now) {
+                                // Only ever turns the fans on, never off, and
+                                // fan overrides are applied on top of this.
+                                SampleStep::RunFans if !act_val.fans => {
+                                    act_val.fans = true;
+                                    reasons.fans = ActuatorReason::SensorSampling;
+                                }
+                                SampleStep::Read => {
+                                    let humidity = self.read_humidity();
+                                    self.sensor_sampler.record(sampling, humidity);
+                                    self.last_sensor_sample = Some(jiff::Timestamp::now());
+                                }
+                                SampleStep::RunFans | SampleStep::Wait | SampleStep::Settle => {}
                             }
+                            self.sensor_sampler.humidity()
                         }
-                        None => {
-                            log::warn!("Failed to read sensors for auto-mist control.");
-                        }
+                        None => self.read_humidity(),
+                    };
+                    if self.humidity_controller.update(
+                        &humidity_control,
+                        setpoint,
+                        humidity,
+                        instant_now,
+                    ) {
+                        act_val.mist = true;
+                        reasons.mist = ActuatorReason::AutoMist {
+                            humidity,
+                            setpoint: setpoint.get(),
+                        };
                     }
                 }
+                _ => {
+                    self.humidity_controller.reset();
+                    self.sensor_sampler.reset();
+                }
             }
+        } else {
+            self.humidity_controller.reset();
+            self.sensor_sampler.reset();
         }
 
-        let instant_now = Instant::now();
+        // Move the running sequence, if there is one, on to its next step.
+        self.advance_sequence(instant_now);
 
         // Apply overrides / temporary controls.
-        // If an override is expired, remove it. Otherwise, use its value to
+        // Remove expired overrides, then use the values of the rest to
         // override whatever is configured in the schedule.
+        self.active_overrides
+            .retain(|actuator, o| !o.expired(scheduled.is_on(*actuator), instant_now));
         if let Some(lights_override) = self.active_overrides.get(&Actuator::Lights) {
-            if instant_now - lights_override.start > lights_override.duration {
-                self.active_overrides.remove(&Actuator::Lights);
-            } else if let ActuatorValue::Float(l) = lights_override.value {
+            if let ActuatorValue::Float(l) = lights_override.value {
                 act_val.lights = l;
             } else {
                 // PANIC - this shouldn't happen
             }
         }
         if let Some(mist_override) = self.active_overrides.get(&Actuator::Mist) {
-            if instant_now - mist_override.start > mist_override.duration {
-                self.active_overrides.remove(&Actuator::Mist);
-            } else if let ActuatorValue::Bool(m) = mist_override.value {
+            if let ActuatorValue::Bool(m) = mist_override.value {
                 act_val.mist = m;
             } else {
                 // PANIC - this shouldn't happen
             }
         }
         if let Some(fan_override) = self.active_overrides.get(&Actuator::Fans) {
-            if instant_now - fan_override.start > fan_override.duration {
-                self.active_overrides.remove(&Actuator::Fans);
-            } else if let ActuatorValue::Bool(f) = fan_override.value {
+            if let ActuatorValue::Bool(f) = fan_override.value {
                 act_val.fans = f;
             } else {
                 // PANIC - this shouldn't happen
             }
         }
+        for (actuator, o) in &self.active_overrides {
+            *reasons.get_mut(*actuator) = ActuatorReason::Override {
+                hold: o.hold,
+                from_sequence: o.from_sequence,
+            };
+        }
+
+        // Thermal protection has the final say, over both the schedule and
+        // overrides.
+        if self
+            .last_thermal_check
+            .is_none_or(|last| instant_now - last >= THERMAL_CHECK_INTERVAL)
+        {
+            self.last_thermal_check = Some(instant_now);
+            self.check_temperatures();
+        }
+        let before_thermal = act_val;
+        self.thermal.apply(&mut act_val);
+        let thermal = ActuatorReason::ThermalProtection {
+            level: self.thermal.status().level,
+        };
+        if act_val.lights != before_thermal.lights {
+            reasons.lights = thermal.clone();
+        }
+        if act_val.fans != before_thermal.fans {
+            reasons.fans = thermal;
+        }
+        if self.external_light_control {
+            reasons.lights = match &self.light_effect {
+                Some(name) => ActuatorReason::Effect { name: name.clone() },
+                None => ActuatorReason::ExternalLightControl,
+            };
+        }
+
+        {
+            let mut terrarium = self.terrarium.lock().unwrap();
+            if !self.external_light_control && act_val.lights != terrarium.get_lights() {
+                terrarium.set_lights_with_fade(act_val.lights, 100);
+            }
+            terrarium.set_mist(act_val.mist);
+            terrarium.set_fans(act_val.fans);
+        }
 
-        let mut terrarium = self.terrarium.lock().unwrap();
-        if !self.external_light_control && act_val.lights != terrarium.get_lights() {
-            terrarium.set_lights_with_fade(act_val.lights, 100);
+        // @terrarium is the interlock, so this can only be checked once it's
+        // unlocked.
+        if act_val.mist
+            && let Some(reason) = self.mist_interlock.lock().unwrap().blocked()
+        {
+            reasons.mist = ActuatorReason::MistInterlock { reason };
         }
-        terrarium.set_mist(act_val.mist);
-        terrarium.set_fans(act_val.fans);
+        self.reasons = reasons;
 
         Ok(())
     }
@@ -192,82 +402,196 @@ impl TerrariumController {
         }
 
         for ud in &update_data.updates {
-            match ud.actuator {
-                Actuator::Mist => match ud.value {
-                    ActuatorValue::Bool(_) => {
-                        let duration = std::cmp::min(ud.duration_secs, MAX_OVERRIDE_DURATION_SECS);
-                        self.active_overrides.insert(
-                            Actuator::Mist,
-                            ActuatorOverride {
-                                value: ud.value,
-                                duration: Duration::from_secs(duration as u64),
-                                start: Instant::now(),
-                            },
-                        );
-                    }
-                    _ => return Err(anyhow!("Expected bool for mist")),
-                },
-                Actuator::Lights => match ud.value {
-                    ActuatorValue::Float(l) => {
-                        let duration = std::cmp::min(ud.duration_secs, MAX_OVERRIDE_DURATION_SECS);
-                        if !(0.0..=1.0).contains(&l) {
-                            return Err(anyhow!(
-                                "Lights value should be in the between 0 and 1, got {}",
-                                l
-                            ));
-                        }
-                        self.active_overrides.insert(
-                            Actuator::Lights,
-                            ActuatorOverride {
-                                value: ud.value,
-                                duration: Duration::from_secs(duration as u64),
-                                start: Instant::now(),
-                            },
-                        );
-                    }
-                    _ => return Err(anyhow!("Expected float for lights")),
-                },
-                Actuator::Fans => match ud.value {
-                    ActuatorValue::Bool(_) => {
-                        let duration = std::cmp::min(ud.duration_secs, MAX_OVERRIDE_DURATION_SECS);
-                        self.active_overrides.insert(
-                            Actuator::Fans,
-                            ActuatorOverride {
-                                value: ud.value,
-                                duration: Duration::from_secs(duration as u64),
-                                start: Instant::now(),
-                            },
-                        );
-                    }
-                    _ => return Err(anyhow!("Expected bool for fan")),
-                },
+            let value = Self::new_override(ud, Instant::now(), false)?;
+            self.active_overrides.insert(ud.actuator, value);
+        }
+
+        Ok(())
+    }
+
+    // Removes the override of @actuator, such as one that's being held until
+    // cleared. Returns false if there wasn't one.
+    pub fn clear_override(&mut self, actuator: Actuator) -> bool {
+        self.active_overrides.remove(&actuator).is_some()
+    }
+
+    // Removes all overrides, and cancels the running sequence so that it
+    // doesn't apply any more. Returns false if there was nothing to clear.
+    pub fn clear_overrides(&mut self) -> bool {
+        let cleared = !self.active_overrides.is_empty() || self.sequence.is_some();
+        self.active_overrides.clear();
+        self.sequence = None;
+        cleared
+    }
+
+    // Starts executing @sequence on the next call to run(), replacing any
+    // sequence that's already running. The whole sequence is checked first,
+    // so a bad step later on doesn't leave it half-run.
+    pub fn start_sequence(&mut self, sequence: &OverrideSequence) -> anyhow::Result<()> {
+        self.start_sequence_at(sequence, Instant::now())
+    }
+
+    fn start_sequence_at(
+        &mut self,
+        sequence: &OverrideSequence,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        if sequence.steps.is_empty() {
+            return Err(anyhow!("Empty control sequence"));
+        }
+        if sequence.steps.len() > MAX_SEQUENCE_STEPS {
+            return Err(anyhow!(
+                "Control sequences can have at most {MAX_SEQUENCE_STEPS} steps, got {}",
+                sequence.steps.len()
+            ));
+        }
+        for (i, step) in sequence.steps.iter().enumerate() {
+            if step.updates.is_empty() {
+                return Err(anyhow!("Step {} of the control sequence is empty", i + 1));
+            }
+            for ud in &step.updates {
+                if ud.hold.is_some() {
+                    return Err(anyhow!(
+                        "Step {} of the control sequence: overrides in a sequence can't be held",
+                        i + 1
+                    ));
+                }
+                Self::new_override(ud, now, true)
+                    .map_err(|e| anyhow!("Step {} of the control sequence: {e}", i + 1))?;
             }
         }
 
+        self.cancel_sequence();
+        self.sequence = Some(RunningSequence {
+            sequence: sequence.clone(),
+            next_step: 0,
+            next_step_at: now + Duration::from_secs(sequence.steps[0].delay_secs as u64),
+        });
         Ok(())
     }
 
+    // Stops the running sequence, along with any of its overrides that are
+    // still active. Returns false if there was no sequence running.
+    pub fn cancel_sequence(&mut self) -> bool {
+        if self.sequence.take().is_none() {
+            return false;
+        }
+        self.active_overrides.retain(|_, o| !o.from_sequence);
+        true
+    }
+
+    // Applies the sequence's next step, if it's time to.
+    fn advance_sequence(&mut self, now: Instant) {
+        let Some(running) = &mut self.sequence else {
+            return;
+        };
+        if now < running.next_step_at {
+            return;
+        }
+        let Some(step) = running.sequence.steps.get(running.next_step) else {
+            self.sequence = None;
+            return;
+        };
+        let mut step_duration = Duration::ZERO;
+        for ud in &step.updates {
+            // Steps were checked by start_sequence().
+            if let Ok(value) = Self::new_override(ud, now, true) {
+                step_duration = step_duration.max(value.duration);
+                self.active_overrides.insert(ud.actuator, value);
+            }
+        }
+        running.next_step += 1;
+        let delay = running
+            .sequence
+            .steps
+            .get(running.next_step)
+            .map_or(0, |next| next.delay_secs);
+        running.next_step_at = now + step_duration + Duration::from_secs(delay as u64);
+    }
+
+    // Checks that @ud has the right type of value for its actuator, and
+    // returns the override it describes, starting at @start.
+    fn new_override(
+        ud: &crate::types::ActuatorOverride,
+        start: Instant,
+        from_sequence: bool,
+    ) -> anyhow::Result<ActuatorOverride> {
+        match (ud.actuator, ud.value) {
+            (Actuator::Mist, ActuatorValue::Bool(_)) | (Actuator::Fans, ActuatorValue::Bool(_)) => {
+            }
+            (Actuator::Lights, ActuatorValue::Float(l)) => {
+                if !(0.0..=1.0).contains(&l) {
+                    return Err(anyhow!(
+                        "Lights value should be in the between 0 and 1, got {}",
+                        l
+                    ));
+                }
+            }
+            (Actuator::Mist, _) => return Err(anyhow!("Expected bool for mist")),
+            (Actuator::Lights, _) => return Err(anyhow!("Expected float for lights")),
+            (Actuator::Fans, _) => return Err(anyhow!("Expected bool for fan")),
+        }
+        // The duration is ignored for held overrides, which aren't limited.
+        let duration = std::cmp::min(ud.duration_secs, MAX_OVERRIDE_DURATION_SECS);
+        Ok(ActuatorOverride {
+            value: ud.value,
+            duration: Duration::from_secs(duration as u64),
+            start,
+            from_sequence,
+            hold: ud.hold,
+            scheduled_on: None,
+        })
+    }
+
+    // Returns what's needed to preview the scheduled actuator transitions over
+    // the next @hours hours, in the configured timezone. The preview doesn't
+    // include any active overrides. Call compute() on the result after
+    // releasing the controller's lock, since it can take a while.
+    pub fn schedule_preview(&self, hours: u32) -> anyhow::Result<SchedulePreviewRequest> {
+        if hours == 0 || hours > MAX_SCHEDULE_PREVIEW_HOURS {
+            return Err(anyhow!(
+                "Preview hours must be between 1 and {MAX_SCHEDULE_PREVIEW_HOURS}, got {hours}"
+            ));
+        }
+        Ok(SchedulePreviewRequest {
+            schedule: self.config.active_schedule().cloned().unwrap_or_default(),
+            start: self.get_local_time(),
+            duration: jiff::SignedDuration::from_hours(hours.into()),
+        })
+    }
+
+    fn check_temperatures(&mut self) {
+        let (air_temp, cpu_temp) = {
+            let mut terrarium = self.terrarium.lock().unwrap();
+            let air_temp = terrarium
+                .read_sensors()
+                .map(|sensor_values| sensor_values.temp);
+            (air_temp, terrarium.read_cpu_temp())
+        };
+        let limits = self.config.thermal.clone().unwrap_or_default();
+        if self.thermal.update(&limits, air_temp, cpu_temp) {
+            self.events.push(
+                EventKind::Thermal {
+                    level: self.thermal.status().level,
+                    air_temp_c: air_temp,
+                    cpu_temp_c: cpu_temp,
+                },
+                jiff::Timestamp::now(),
+            );
+        }
+    }
+
+    fn read_humidity(&self) -> Option<f32> {
+        let sensor_values = self.terrarium.lock().unwrap().read_sensors();
+        if sensor_values.is_none() {
+            log::warn!("Failed to read sensors for auto-mist control.");
+        }
+        sensor_values.map(|sensor_values| sensor_values.humid)
+    }
+
     // Uses the configured timezone if possible, otherwise defaults to US West Coast time.
-    fn get_local_time(&self) -> jiff::civil::Time {
-        jiff::Timestamp::now().to_zoned(self.get_timezone()).time()
-    }
-
-    fn get_timezone(&self) -> jiff::tz::TimeZone {
-        self.config
-            .timezone
-            .as_ref()
-            .and_then(|tz_name| {
-                jiff::tz::TimeZone::get(tz_name)
-                    .map_err(|err| {
-                        log::warn!("Invalid timezone: '{}', {}", tz_name, err);
-                        err
-                    })
-                    .ok()
-            })
-            .unwrap_or_else(|| {
-                jiff::tz::TimeZone::get(DEFAULT_TIMEZONE)
-                    .expect("Default timezone should always be valid")
-            })
+    fn get_local_time(&self) -> jiff::Zoned {
+        jiff::Timestamp::now().to_zoned(self.config.get_timezone())
     }
 }
 
@@ -298,14 +622,20 @@ pub async fn terrarium_controller_main_loop(controller: Arc<Mutex<TerrariumContr
 #[cfg(test)]
 mod controller {
     use super::*;
-    use crate::config::WifiDetails;
+    use crate::config::{
+        DEFAULT_PROFILE, Schedule, ScheduleUpdate, TimeRange, Update, WifiDetails,
+    };
+    use crate::humidity::{HumidityControl, SensorSampling};
+    use crate::mist_interlock::MistTripReason;
     use crate::terrarium::FakeTerrarium;
-    use crate::types::ActuatorOverride;
+    use crate::thermal::ThermalLevel;
+    use crate::types::{ActuatorOverride, Hostname, Intensity, RelativeHumidity, SequenceStep};
+    use std::collections::BTreeMap;
 
     #[test]
     fn update_config() {
         let mut cfg = TerrariumConfig::default();
-        cfg.name = Some("foo".to_string());
+        cfg.name = Some(Hostname::try_from("foo").unwrap());
         cfg.wifi = Some(WifiDetails {
             ssid: "ssid1".to_string(),
             password: "password1".to_string(),
@@ -313,7 +643,7 @@ mod controller {
         let mut ctl = TerrariumController::new(Arc::new(Mutex::new(FakeTerrarium::new())), cfg);
 
         let mut cfg_update = TerrariumConfigUpdate::default();
-        cfg_update.name = Update::Set("bar".to_string());
+        cfg_update.name = Update::Set(Hostname::try_from("bar").unwrap());
         assert!(ctl.update_config(&cfg_update).is_ok());
 
         assert_eq!(
@@ -323,7 +653,90 @@ mod controller {
                 password: "password1".to_string(),
             })
         );
-        assert_eq!(ctl.config.name, Some("bar".to_string()));
+        assert_eq!(ctl.config.name.as_ref().map(Hostname::as_str), Some("bar"));
+    }
+
+    #[test]
+    fn rollback_config() {
+        let cfg = TerrariumConfig::new_with_reasonable_defaults();
+        let mut ctl =
+            TerrariumController::new(Arc::new(Mutex::new(FakeTerrarium::new())), cfg.clone());
+
+        let mut cfg_update = TerrariumConfigUpdate::default();
+        cfg_update.timezone = Update::Set("Europe/Berlin".to_string());
+        ctl.update_config(&cfg_update).unwrap();
+        assert_eq!(ctl.history().summaries().len(), 2);
+
+        ctl.rollback_config(1).unwrap();
+        assert_eq!(ctl.config, cfg);
+        assert!(ctl.rollback_config(9).is_err());
+    }
+
+    #[test]
+    fn dry_run_config_update() {
+        let cfg = TerrariumConfig::new_with_reasonable_defaults();
+        let ctl = TerrariumController::new(Arc::new(Mutex::new(FakeTerrarium::new())), cfg.clone());
+
+        let mut cfg_update = TerrariumConfigUpdate::default();
+        cfg_update.name = Update::Set(Hostname::try_from("bar").unwrap());
+        let result = ctl.dry_run_config_update(&cfg_update).unwrap();
+        assert_eq!(
+            result.config.name.as_ref().map(Hostname::as_str),
+            Some("bar")
+        );
+        assert_eq!(result.changes.len(), 1);
+        assert_eq!(result.changes[0].path, "name");
+        // Nothing is applied.
+        assert_eq!(ctl.config, cfg);
+
+        // Invalid updates fail the same way they would if applied.
+        cfg_update.active_profile = Update::Set("missing".to_string());
+        assert!(ctl.dry_run_config_update(&cfg_update).is_err());
+    }
+
+    #[test]
+    fn switch_profiles() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::new_with_reasonable_defaults(),
+        );
+
+        // Create a profile with the lights always on and switch to it.
+        let update: TerrariumConfigUpdate = serde_json::from_str(
+            r#"{
+                "profiles": {"vacation": {"light_intensity": 1.0, "light_profile": [{"time": "00:00", "intensity": 1.0}]}},
+                "active_profile": "vacation"
+            }"#,
+        )
+        .unwrap();
+        ctl.update_config(&update).unwrap();
+        assert_eq!(ctl.config().profiles.len(), 2);
+        ctl.run().unwrap();
+        assert_eq!(ctl.terrarium().lock().unwrap().get_lights(), 1.0);
+
+        // Only the active profile is run.
+        let update = TerrariumConfigUpdate {
+            active_profile: Update::Set(DEFAULT_PROFILE.to_string()),
+            ..TerrariumConfigUpdate::default()
+        };
+        ctl.update_config(&update).unwrap();
+        assert_eq!(
+            ctl.config().active_schedule(),
+            Some(&Schedule::new_with_reasonable_defaults())
+        );
+
+        // A failed update doesn't partially apply.
+        let update = TerrariumConfigUpdate {
+            profiles: BTreeMap::from([("new".to_string(), Update::Set(ScheduleUpdate::default()))]),
+            active_profile: Update::Set("missing".to_string()),
+            ..TerrariumConfigUpdate::default()
+        };
+        assert!(ctl.update_config(&update).is_err());
+        assert_eq!(ctl.config().profiles.len(), 2);
+        assert_eq!(
+            ctl.config().active_profile,
+            Some(DEFAULT_PROFILE.to_string())
+        );
     }
 
     #[test]
@@ -341,16 +754,19 @@ mod controller {
                     actuator: Actuator::Mist,
                     value: ActuatorValue::Bool(true),
                     duration_secs: 5,
+                    hold: None,
                 },
                 ActuatorOverride {
                     actuator: Actuator::Fans,
                     value: ActuatorValue::Bool(true),
                     duration_secs: 10,
+                    hold: None,
                 },
                 ActuatorOverride {
                     actuator: Actuator::Lights,
                     value: ActuatorValue::Float(0.7),
                     duration_secs: 15,
+                    hold: None,
                 },
             ],
         };
@@ -372,6 +788,7 @@ mod controller {
                 actuator: Actuator::Mist,
                 value: ActuatorValue::Float(1000.0),
                 duration_secs: 100,
+                hold: None,
             }],
         };
         let result = ctl.handle_control_cmd(&ud);
@@ -379,19 +796,274 @@ mod controller {
         assert_eq!(result.unwrap_err().to_string(), "Expected bool for mist",);
     }
 
+    fn step(delay_secs: u32, actuator: Actuator, value: ActuatorValue, secs: u32) -> SequenceStep {
+        SequenceStep {
+            delay_secs,
+            updates: vec![ActuatorOverride {
+                actuator,
+                value,
+                duration_secs: secs,
+                hold: None,
+            }],
+        }
+    }
+
+    fn overridden(ctl: &TerrariumController) -> Vec<Actuator> {
+        let mut actuators: Vec<Actuator> = ctl.active_overrides.keys().copied().collect();
+        actuators.sort_by_key(|a| format!("{a:?}"));
+        actuators
+    }
+
     #[test]
-    fn test_auto_mist() {
-        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
-        let cfg = TerrariumConfig {
-            schedule: Some(Schedule {
-                auto_mist_enabled: true,
-                humidity_setpoint: Some(0.8),
+    fn sequence() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let start = Instant::now();
+        let t = |secs| start + Duration::from_secs(secs);
+        // Mist for 30 seconds, then fans for 2 minutes, then after another
+        // 10 seconds, the lights for 10 minutes.
+        let sequence = OverrideSequence {
+            steps: vec![
+                step(0, Actuator::Mist, ActuatorValue::Bool(true), 30),
+                step(0, Actuator::Fans, ActuatorValue::Bool(true), 120),
+                step(10, Actuator::Lights, ActuatorValue::Float(1.0), 600),
+            ],
+        };
+        ctl.start_sequence_at(&sequence, start).unwrap();
+
+        ctl.advance_sequence(t(0));
+        assert_eq!(overridden(&ctl), vec![Actuator::Mist]);
+        ctl.advance_sequence(t(29));
+        assert_eq!(overridden(&ctl), vec![Actuator::Mist]);
+        ctl.advance_sequence(t(30));
+        assert_eq!(overridden(&ctl), vec![Actuator::Fans, Actuator::Mist]);
+        ctl.advance_sequence(t(159));
+        assert_eq!(overridden(&ctl), vec![Actuator::Fans, Actuator::Mist]);
+        ctl.advance_sequence(t(160));
+        assert_eq!(
+            overridden(&ctl),
+            vec![Actuator::Fans, Actuator::Lights, Actuator::Mist]
+        );
+        assert_eq!(ctl.active_overrides[&Actuator::Lights].start, t(160));
+
+        // The sequence is over once the last step is.
+        ctl.advance_sequence(t(760));
+        assert!(!ctl.cancel_sequence());
+    }
+
+    #[test]
+    fn cancel_sequence() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        ctl.handle_control_cmd(&ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator: Actuator::Lights,
+                value: ActuatorValue::Float(0.5),
+                duration_secs: 100,
+                hold: None,
+            }],
+        })
+        .unwrap();
+        ctl.start_sequence(&OverrideSequence {
+            steps: vec![
+                step(0, Actuator::Mist, ActuatorValue::Bool(true), 100),
+                step(0, Actuator::Fans, ActuatorValue::Bool(true), 100),
+            ],
+        })
+        .unwrap();
+        ctl.run().unwrap();
+        assert!(ctl.terrarium().lock().unwrap().get_mist());
+
+        // Only the sequence's overrides are cancelled with it.
+        assert!(ctl.cancel_sequence());
+        ctl.run().unwrap();
+        assert!(!ctl.terrarium().lock().unwrap().get_mist());
+        assert_eq!(ctl.terrarium().lock().unwrap().get_lights(), 0.5);
+        assert!(!ctl.cancel_sequence());
+    }
+
+    #[test]
+    fn invalid_sequence() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let result = ctl.start_sequence(&OverrideSequence {
+            steps: vec![
+                step(0, Actuator::Mist, ActuatorValue::Bool(true), 10),
+                step(0, Actuator::Lights, ActuatorValue::Bool(true), 10),
+            ],
+        });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Step 2 of the control sequence: Expected float for lights"
+        );
+        // Nothing from the sequence was started.
+        ctl.run().unwrap();
+        assert!(!ctl.terrarium().lock().unwrap().get_mist());
+
+        let result = ctl.start_sequence(&OverrideSequence { steps: vec![] });
+        assert!(result.is_err());
+    }
+
+    fn hold(actuator: Actuator, value: ActuatorValue, hold: OverrideHold) -> ActuatorOverrideSet {
+        ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator,
+                value,
+                duration_secs: 0,
+                hold: Some(hold),
+            }],
+        }
+    }
+
+    #[test]
+    fn manual_override() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let ud = hold(
+            Actuator::Fans,
+            ActuatorValue::Bool(true),
+            OverrideHold::Manual,
+        );
+        ctl.handle_control_cmd(&ud).unwrap();
+        ctl.run().unwrap();
+        assert!(ctl.terrarium().lock().unwrap().get_fans());
+
+        // Long after a timed override would have run out, it's still held.
+        let o = ctl.active_overrides.get_mut(&Actuator::Fans).unwrap();
+        assert!(!o.expired(false, Instant::now() + Duration::from_secs(24 * 60 * 60)));
+
+        assert!(ctl.clear_override(Actuator::Fans));
+        ctl.run().unwrap();
+        assert!(!ctl.terrarium().lock().unwrap().get_fans());
+        assert!(!ctl.clear_override(Actuator::Fans));
+    }
+
+    #[test]
+    fn active_overrides() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let ud = hold(
+            Actuator::Fans,
+            ActuatorValue::Bool(true),
+            OverrideHold::Manual,
+        );
+        ctl.handle_control_cmd(&ud).unwrap();
+        ctl.start_sequence(&OverrideSequence {
+            steps: vec![step(0, Actuator::Lights, ActuatorValue::Float(0.5), 90)],
+        })
+        .unwrap();
+        ctl.run().unwrap();
+
+        let start = ctl.active_overrides[&Actuator::Lights].start;
+        assert_eq!(
+            ctl.active_overrides(start + Duration::from_millis(30_500)),
+            vec![
+                ActiveOverride {
+                    actuator: Actuator::Lights,
+                    value: ActuatorValue::Float(0.5),
+                    remaining_secs: Some(60),
+                    hold: None,
+                    from_sequence: true,
+                },
+                ActiveOverride {
+                    actuator: Actuator::Fans,
+                    value: ActuatorValue::Bool(true),
+                    remaining_secs: None,
+                    hold: Some(OverrideHold::Manual),
+                    from_sequence: false,
+                },
+            ]
+        );
+        assert_eq!(ctl.state().overrides.len(), 2);
+
+        assert!(ctl.clear_overrides());
+        ctl.run().unwrap();
+        assert!(ctl.state().overrides.is_empty());
+        assert!(!ctl.terrarium().lock().unwrap().get_fans());
+        assert!(!ctl.clear_overrides());
+    }
+
+    #[test]
+    fn next_transition_override() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let ud = hold(
+            Actuator::Lights,
+            ActuatorValue::Float(0.3),
+            OverrideHold::NextTransition,
+        );
+        ctl.handle_control_cmd(&ud).unwrap();
+        ctl.run().unwrap();
+        assert_eq!(ctl.terrarium().lock().unwrap().get_lights(), 0.3);
+
+        // The schedule turning the lights on ends the override.
+        ctl.config.profiles = BTreeMap::from([(
+            DEFAULT_PROFILE.to_string(),
+            Schedule {
+                lights: Some(TimeRange {
+                    start: jiff::civil::time(0, 0, 0, 0),
+                    stop: jiff::civil::time(23, 59, 59, 999_999_999),
+                    days: None,
+                }),
+                light_intensity: Some(Intensity::new(0.8).unwrap()),
                 ..Schedule::default()
-            }),
+            },
+        )]);
+        ctl.config.active_profile = Some(DEFAULT_PROFILE.to_string());
+        ctl.run().unwrap();
+        assert_eq!(ctl.terrarium().lock().unwrap().get_lights(), 0.8);
+        assert!(!ctl.clear_override(Actuator::Lights));
+    }
+
+    #[test]
+    fn held_override_in_sequence() {
+        let mut ctl = TerrariumController::new(
+            Arc::new(Mutex::new(FakeTerrarium::new())),
+            TerrariumConfig::default(),
+        );
+        let result = ctl.start_sequence(&OverrideSequence {
+            steps: vec![SequenceStep {
+                delay_secs: 0,
+                updates: hold(
+                    Actuator::Mist,
+                    ActuatorValue::Bool(true),
+                    OverrideHold::Manual,
+                )
+                .updates,
+            }],
+        });
+        assert!(result.is_err());
+    }
+
+    fn auto_mist_config(humidity_control: HumidityControl) -> TerrariumConfig {
+        TerrariumConfig {
+            profiles: BTreeMap::from([(
+                DEFAULT_PROFILE.to_string(),
+                Schedule {
+                    auto_mist_enabled: true,
+                    humidity_setpoint: Some(RelativeHumidity::new(0.8).unwrap()),
+                    humidity_control: Some(humidity_control),
+                    ..Schedule::default()
+                },
+            )]),
+            active_profile: Some(DEFAULT_PROFILE.to_string()),
             ..TerrariumConfig::default()
-        };
-        let mut ctl = TerrariumController::new(terrarium.clone(), cfg);
-        // start at humidity 0.5
+        }
+    }
+
+    fn set_humidity(terrarium: &Mutex<FakeTerrarium>, humid: f32) {
         terrarium
             .lock()
             .unwrap()
@@ -399,13 +1071,140 @@ mod controller {
             .sensors
             .as_mut()
             .unwrap()
-            .humid = 0.5;
+            .humid = humid;
+    }
+
+    #[test]
+    fn test_auto_mist() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        // Switch immediately, rather than waiting out the minimum on and off
+        // times.
+        let cfg = auto_mist_config(HumidityControl {
+            min_on_secs: 0,
+            min_off_secs: 0,
+            ..HumidityControl::default()
+        });
+        let mut ctl = TerrariumController::new(terrarium.clone(), cfg);
+        // start at humidity 0.5
+        set_humidity(&terrarium, 0.5);
         ctl.run().unwrap();
         assert!(
             terrarium.lock().unwrap().get_mist(),
             "Mist should be on when humidity is low"
         );
 
+        set_humidity(&terrarium, 0.81);
+        ctl.run().unwrap();
+        assert!(
+            !terrarium.lock().unwrap().get_mist(),
+            "Mist should be off when humidity is high"
+        );
+        assert_eq!(ctl.state().last_sensor_sample, None);
+    }
+
+    #[test]
+    fn test_auto_mist_sampling() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        let cfg = auto_mist_config(HumidityControl {
+            min_on_secs: 0,
+            min_off_secs: 0,
+            // Read the sensor right away.
+            sampling: Some(SensorSampling {
+                interval_secs: 60,
+                fan_secs: 0,
+                settle_secs: 0,
+            }),
+            ..HumidityControl::default()
+        });
+        let mut ctl = TerrariumController::new(terrarium.clone(), cfg);
+        set_humidity(&terrarium, 0.5);
+        ctl.run().unwrap();
+        assert!(terrarium.lock().unwrap().get_mist());
+        let sampled = ctl.state().last_sensor_sample;
+        assert!(sampled.is_some());
+
+        // The new reading isn't used until the next sample.
+        set_humidity(&terrarium, 0.81);
+        ctl.run().unwrap();
+        assert!(terrarium.lock().unwrap().get_mist());
+        assert_eq!(ctl.state().last_sensor_sample, sampled);
+    }
+
+    #[test]
+    fn reasons() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        let mut ctl = TerrariumController::new(
+            terrarium.clone(),
+            auto_mist_config(HumidityControl::default()),
+        );
+        set_humidity(&terrarium, 0.5);
+        ctl.handle_control_cmd(&ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator: Actuator::Fans,
+                value: ActuatorValue::Bool(true),
+                duration_secs: 100,
+                hold: None,
+            }],
+        })
+        .unwrap();
+        ctl.takeover_lights_for_effect("breathe");
+        ctl.run().unwrap();
+        assert_eq!(
+            ctl.state().reasons,
+            ActuatorReasons {
+                lights: ActuatorReason::Effect {
+                    name: "breathe".to_string()
+                },
+                fans: ActuatorReason::Override {
+                    hold: None,
+                    from_sequence: false,
+                },
+                mist: ActuatorReason::AutoMist {
+                    humidity: Some(0.5),
+                    setpoint: 0.8,
+                },
+            }
+        );
+
+        ctl.release_lights();
+        ctl.run().unwrap();
+        assert_eq!(ctl.state().reasons.lights, ActuatorReason::Unscheduled);
+    }
+
+    #[test]
+    fn mist_interlock_reason() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        let mut ctl = TerrariumController::new(terrarium.clone(), TerrariumConfig::default());
+        let mist = |on| ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator: Actuator::Mist,
+                value: ActuatorValue::Bool(on),
+                duration_secs: 100,
+                hold: None,
+            }],
+        };
+        // Turning the mister straight back on after turning it off breaks
+        // the minimum rest.
+        ctl.handle_control_cmd(&mist(true)).unwrap();
+        ctl.run().unwrap();
+        ctl.handle_control_cmd(&mist(false)).unwrap();
+        ctl.run().unwrap();
+        ctl.handle_control_cmd(&mist(true)).unwrap();
+        ctl.run().unwrap();
+        assert!(!terrarium.lock().unwrap().get_mist());
+        assert_eq!(
+            ctl.state().reasons.mist,
+            ActuatorReason::MistInterlock {
+                reason: MistTripReason::MinRest
+            }
+        );
+    }
+
+    #[test]
+    fn test_thermal_protection() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        let mut ctl = TerrariumController::new(terrarium.clone(), TerrariumConfig::default());
+        // Halfway between the default warning and critical temperatures.
         terrarium
             .lock()
             .unwrap()
@@ -413,12 +1212,68 @@ mod controller {
             .sensors
             .as_mut()
             .unwrap()
-            .humid = 0.81;
+            .temp = 35.0;
+        ctl.handle_control_cmd(&ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator: Actuator::Lights,
+                value: ActuatorValue::Float(1.0),
+                duration_secs: 100,
+                hold: None,
+            }],
+        })
+        .unwrap();
         ctl.run().unwrap();
-        assert!(
-            !terrarium.lock().unwrap().get_mist(),
-            "Mist should be off when humidity is high"
+
+        // Even overridden lights are dimmed.
+        assert_eq!(terrarium.lock().unwrap().get_lights(), 0.5);
+        assert!(terrarium.lock().unwrap().get_fans());
+        assert_eq!(ctl.state().thermal.level, ThermalLevel::Derating);
+        assert_eq!(
+            ctl.state().reasons.lights,
+            ActuatorReason::ThermalProtection {
+                level: ThermalLevel::Derating
+            }
         );
+        let events = ctl.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].kind,
+            EventKind::Thermal {
+                level: ThermalLevel::Derating,
+                air_temp_c: Some(35.0),
+                cpu_temp_c: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_sampling_runs_fans() {
+        let terrarium = Arc::new(Mutex::new(FakeTerrarium::new()));
+        let cfg = auto_mist_config(HumidityControl {
+            sampling: Some(SensorSampling::default()),
+            ..HumidityControl::default()
+        });
+        let mut ctl = TerrariumController::new(terrarium.clone(), cfg);
+        set_humidity(&terrarium, 0.5);
+        ctl.run().unwrap();
+        // The fans run before the first sample, and there's no reading to
+        // mist based on yet.
+        assert!(terrarium.lock().unwrap().get_fans());
+        assert!(!terrarium.lock().unwrap().get_mist());
+        assert_eq!(ctl.state().last_sensor_sample, None);
+
+        // Overrides win over sampling.
+        ctl.handle_control_cmd(&ActuatorOverrideSet {
+            updates: vec![ActuatorOverride {
+                actuator: Actuator::Fans,
+                value: ActuatorValue::Bool(false),
+                duration_secs: 100,
+                hold: None,
+            }],
+        })
+        .unwrap();
+        ctl.run().unwrap();
+        assert!(!terrarium.lock().unwrap().get_fans());
     }
 }
 
@@ -435,11 +1290,13 @@ mod json_format {
                     actuator: Actuator::Mist,
                     value: ActuatorValue::Bool(true),
                     duration_secs: 10,
+                    hold: None,
                 },
                 ActuatorOverride {
                     actuator: Actuator::Lights,
                     value: ActuatorValue::Float(0.5),
                     duration_secs: 15,
+                    hold: None,
                 },
             ],
         };
@@ -0,0 +1,328 @@
+// Parsing and evaluation of cron expressions for scheduled events.
+//
+// This supports the standard five-field format:
+//
+//   minute hour day-of-month month day-of-week
+//
+// Each field can be `*`, a single value, a range (`9-17`), a step (`*/20` or
+// `9-17/2`), or a comma-separated list of any of those. Months and days of the
+// week can also be given by their three-letter names (`jan`, `mon`). Day of
+// the week is 0-7, where both 0 and 7 are Sunday.
+//
+// As in most cron implementations, if both day-of-month and day-of-week are
+// restricted, a day matches if either of them match.
+
+use anyhow::anyhow;
+use jiff::civil::{Date, DateTime};
+use jiff::{SignedDuration, ToSpan, Zoned};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct CronExpr {
+    // The expression as it was written. This is what gets serialized so that
+    // configs round-trip unchanged.
+    source: String,
+    // Bit n is set if value n matches.
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    // Sunday is bit 0.
+    days_of_week: u8,
+    // Whether day-of-month/day-of-week were given as `*`.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronExpr {
+    // Returns true if the event should be running at @now, given that it runs
+    // for @duration_secs after each time the expression fires.
+    pub fn is_active(&self, now: &Zoned, duration_secs: u32) -> bool {
+        self.active_since(now, duration_secs).is_some()
+    }
+
+    // Returns when the run that's active at @now started, or None if there
+    // isn't one.
+    pub fn active_since(&self, now: &Zoned, duration_secs: u32) -> Option<DateTime> {
+        let earliest = now
+            .checked_sub(SignedDuration::from_secs(duration_secs.into()))
+            .map(|z| z.datetime())
+            .unwrap_or(DateTime::MIN);
+        self.prev_fire(now.datetime(), earliest)
+    }
+
+    // Returns the latest minute at or before @now that this expression fires
+    // on, or None if it doesn't fire between @earliest and @now.
+    pub fn prev_fire(&self, now: DateTime, earliest: DateTime) -> Option<DateTime> {
+        let mut t = now.with().second(0).subsec_nanosecond(0).build().ok()?;
+        while t >= earliest {
+            // Skip backwards a whole day or hour at a time where possible so
+            // this stays fast for events that fire rarely.
+            if !self.matches_date(t.date()) {
+                t = t.date().yesterday().ok()?.at(23, 59, 0, 0);
+            } else if !bit(self.hours.into(), t.hour()) {
+                t = t
+                    .with()
+                    .minute(59)
+                    .build()
+                    .ok()?
+                    .checked_sub(1.hour())
+                    .ok()?;
+            } else if !bit(self.minutes, t.minute()) {
+                t = t.checked_sub(1.minute()).ok()?;
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn matches_date(&self, date: Date) -> bool {
+        if !bit(self.months.into(), date.month()) {
+            return false;
+        }
+        let dom = bit(self.days_of_month.into(), date.day());
+        let dow = bit(
+            self.days_of_week.into(),
+            date.weekday().to_sunday_zero_offset(),
+        );
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (false, true) => dom,
+            (true, false) => dow,
+            (false, false) => dom || dow,
+        }
+    }
+}
+
+fn bit(mask: u64, n: i8) -> bool {
+    mask & (1 << n) != 0
+}
+
+// Parses one field of the expression into a bitmask of the values it matches.
+fn parse_field(field: &str, min: u8, max: u8, names: &[&str]) -> anyhow::Result<u64> {
+    let parse_value = |s: &str| -> anyhow::Result<u8> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(i) = names.iter().position(|name| *name == lower) {
+            return Ok(min + i as u8);
+        }
+        let value: u8 = s.parse().map_err(|_| anyhow!("invalid value '{s}'"))?;
+        if value < min || value > max {
+            return Err(anyhow!("{value} is out of range {min}-{max}"));
+        }
+        Ok(value)
+    };
+
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u8 = step.parse().map_err(|_| anyhow!("invalid step '{step}'"))?;
+                if step == 0 {
+                    return Err(anyhow!("step can not be zero"));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, stop) = if range == "*" {
+            (min, max)
+        } else if let Some((start, stop)) = range.split_once('-') {
+            (parse_value(start)?, parse_value(stop)?)
+        } else {
+            let value = parse_value(range)?;
+            // `5/15` means "every 15 starting at 5".
+            (value, if step > 1 { max } else { value })
+        };
+        if start > stop {
+            return Err(anyhow!("invalid range '{range}'"));
+        }
+        for value in (start..=stop).step_by(step.into()) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl FromStr for CronExpr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(anyhow!(
+                "cron expression '{s}' must have 5 fields (minute hour day-of-month month day-of-week), got {}",
+                fields.len()
+            ));
+        };
+        let err =
+            |name: &str, e: anyhow::Error| anyhow!("invalid {name} in cron expression '{s}': {e}");
+
+        let mut days_of_week =
+            parse_field(day_of_week, 0, 7, &WEEKDAY_NAMES).map_err(|e| err("day-of-week", e))?;
+        // 7 is also Sunday.
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & 0x7f;
+        }
+
+        Ok(CronExpr {
+            source: fields.join(" "),
+            minutes: parse_field(minute, 0, 59, &[]).map_err(|e| err("minute", e))?,
+            hours: parse_field(hour, 0, 23, &[]).map_err(|e| err("hour", e))? as u32,
+            days_of_month: parse_field(day_of_month, 1, 31, &[])
+                .map_err(|e| err("day-of-month", e))? as u32,
+            months: parse_field(month, 1, 12, &MONTH_NAMES).map_err(|e| err("month", e))? as u16,
+            days_of_week: days_of_week as u8,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl JsonSchema for CronExpr {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "CronExpr".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "description": "Five-field cron expression: minute hour day-of-month month day-of-week.",
+            "pattern": r"^\s*\S+(\s+\S+){4}\s*$",
+        })
+    }
+}
+
+impl TryFrom<String> for CronExpr {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<CronExpr> for String {
+    fn from(expr: CronExpr) -> String {
+        expr.source
+    }
+}
+
+#[cfg(test)]
+mod cron {
+    use super::*;
+
+    fn dt(s: &str) -> DateTime {
+        s.parse().unwrap()
+    }
+
+    fn fires_at(expr: &str, t: &str) -> bool {
+        let expr: CronExpr = expr.parse().unwrap();
+        expr.prev_fire(dt(t), dt(t)).is_some()
+    }
+
+    #[test]
+    fn parse_errors() {
+        for expr in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "17-9 * * * *",
+            "a * * * *",
+            "* * * foo *",
+        ] {
+            assert!(expr.parse::<CronExpr>().is_err(), "{expr}");
+        }
+    }
+
+    #[test]
+    fn every_20_minutes_during_the_day() {
+        let expr = "*/20 9-16 * * *";
+        assert!(fires_at(expr, "2025-04-07T09:00"));
+        assert!(fires_at(expr, "2025-04-07T09:40"));
+        assert!(fires_at(expr, "2025-04-07T16:40"));
+        assert!(!fires_at(expr, "2025-04-07T09:10"));
+        assert!(!fires_at(expr, "2025-04-07T17:00"));
+        assert!(!fires_at(expr, "2025-04-07T08:40"));
+    }
+
+    #[test]
+    fn lists_and_names() {
+        let expr = "15,45 11 * * Mon,wed,FRI";
+        // April 7th, 2025 is a Monday.
+        assert!(fires_at(expr, "2025-04-07T11:15"));
+        assert!(fires_at(expr, "2025-04-09T11:45"));
+        assert!(!fires_at(expr, "2025-04-08T11:15"));
+        assert!(!fires_at(expr, "2025-04-07T11:30"));
+
+        // Sunday as both 0 and 7.
+        assert!(fires_at("0 8 * * 0", "2025-04-06T08:00"));
+        assert!(fires_at("0 8 * * 7", "2025-04-06T08:00"));
+        assert!(fires_at("0 8 * * 5-7", "2025-04-06T08:00"));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // The 1st of the month, or any Monday.
+        let expr = "0 12 1 * mon";
+        assert!(fires_at(expr, "2025-04-01T12:00"));
+        assert!(fires_at(expr, "2025-04-07T12:00"));
+        assert!(!fires_at(expr, "2025-04-08T12:00"));
+    }
+
+    #[test]
+    fn prev_fire() {
+        let expr: CronExpr = "30 6 * * sat".parse().unwrap();
+        // Monday, looking back more than a week.
+        let now = dt("2025-04-07T10:00:30");
+        assert_eq!(
+            expr.prev_fire(now, dt("2025-03-30T00:00")),
+            Some(dt("2025-04-05T06:30"))
+        );
+        assert_eq!(expr.prev_fire(now, dt("2025-04-06T00:00")), None);
+    }
+
+    #[test]
+    fn is_active() {
+        let expr: CronExpr = "0 */2 * * *".parse().unwrap();
+        let at = |time: &str| -> Zoned { format!("2025-04-07T{time}[UTC]").parse().unwrap() };
+        assert!(expr.is_active(&at("10:00:00"), 30));
+        assert!(expr.is_active(&at("10:00:30"), 30));
+        assert!(!expr.is_active(&at("10:00:31"), 30));
+        assert!(!expr.is_active(&at("11:00:00"), 30));
+        // Just after midnight, from the previous day's run.
+        let expr: CronExpr = "0 23 * * *".parse().unwrap();
+        assert!(expr.is_active(&at("00:30:00"), 2 * 60 * 60));
+        assert!(!expr.is_active(&at("01:30:00"), 2 * 60 * 60));
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let expr: CronExpr = "  */20   9-16 * * mon-fri ".parse().unwrap();
+        assert_eq!(expr.to_string(), "*/20 9-16 * * mon-fri");
+        let json = serde_json::to_string(&expr).unwrap();
+        assert_eq!(json, "\"*/20 9-16 * * mon-fri\"");
+        assert_eq!(serde_json::from_str::<CronExpr>(&json).unwrap(), expr);
+        assert!(serde_json::from_str::<CronExpr>("\"* * *\"").is_err());
+    }
+}
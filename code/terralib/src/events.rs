@@ -0,0 +1,118 @@
+// A short log of notable things that the controller did on its own, such as
+// dimming the lights because the terrarium got too hot. Returned by GET
+// /events.
+
+use crate::thermal::ThermalLevel;
+use jiff::Timestamp;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+
+// The log is kept in memory on the esp32, so only keep the latest few events.
+pub const MAX_EVENTS: usize = 32;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    // Thermal protection changed level. Temperatures are in degrees Celsius,
+    // and are None if they couldn't be read.
+    Thermal {
+        level: ThermalLevel,
+        air_temp_c: Option<f32>,
+        cpu_temp_c: Option<f32>,
+    },
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let temp = |temp: &Option<f32>| temp.map_or("unknown".to_string(), |t| format!("{t:.1}C"));
+        match self {
+            EventKind::Thermal {
+                level,
+                air_temp_c,
+                cpu_temp_c,
+            } => write!(
+                f,
+                "Thermal protection {level:?} (air {}, cpu {})",
+                temp(air_temp_c),
+                temp(cpu_temp_c)
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct Event {
+    pub timestamp: Timestamp,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+// Oldest event first.
+#[derive(Default)]
+pub struct EventLog {
+    events: VecDeque<Event>,
+}
+
+impl EventLog {
+    pub fn push(&mut self, kind: EventKind, now: Timestamp) {
+        log::info!("Event: {kind}");
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(Event {
+            timestamp: now,
+            kind,
+        });
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod events {
+    use super::*;
+
+    fn thermal(level: ThermalLevel) -> EventKind {
+        EventKind::Thermal {
+            level,
+            air_temp_c: Some(35.0),
+            cpu_temp_c: None,
+        }
+    }
+
+    #[test]
+    fn oldest_events_are_dropped() {
+        let mut log = EventLog::default();
+        log.push(thermal(ThermalLevel::Critical), Timestamp::UNIX_EPOCH);
+        for _ in 0..MAX_EVENTS {
+            log.push(thermal(ThermalLevel::Normal), Timestamp::UNIX_EPOCH);
+        }
+        let events = log.events();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert!(
+            events
+                .iter()
+                .all(|e| e.kind == thermal(ThermalLevel::Normal))
+        );
+    }
+
+    #[test]
+    fn json_format() {
+        let mut log = EventLog::default();
+        log.push(thermal(ThermalLevel::Derating), Timestamp::UNIX_EPOCH);
+        assert_eq!(
+            serde_json::to_value(log.events()).unwrap(),
+            serde_json::json!([{
+                "timestamp": "1970-01-01T00:00:00Z",
+                "kind": "thermal",
+                "level": "derating",
+                "air_temp_c": 35.0,
+                "cpu_temp_c": null
+            }])
+        );
+    }
+}
@@ -0,0 +1,578 @@
+// Auto-mist control: decides when to run the mister to keep the humidity near
+// a schedule's humidity_setpoint.
+//
+// The humidity sensor reacts slowly to misting, so simply misting whenever the
+// humidity is below the setpoint keeps the mister running long after enough
+// water is in the air, and then flips it on and off as the reading hovers
+// around the setpoint. Instead, misting starts once the humidity drops a
+// margin (the hysteresis) below the setpoint and stops once it's back up to
+// the setpoint. Minimum on and off times keep it from switching too often, and
+// the total time spent misting within a window is capped, with a cooldown once
+// the cap is hit, so that a slow or broken sensor can't keep it misting.
+//
+// Part of the lag is because the sensor sits in still air, so optionally the
+// fans are run briefly before each reading (see SensorSampling).
+
+use crate::config::validation::{ErrorCode, ValidationReport, field};
+use crate::types::RelativeHumidity;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Tuning for HumidityController. Fields that are left out get their default.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct HumidityControl {
+    // How far below the setpoint the humidity has to drop before misting
+    // starts.
+    pub hysteresis: RelativeHumidity,
+    // Once started, misting continues for at least this long.
+    pub min_on_secs: u32,
+    // Once stopped, misting doesn't start again for at least this long.
+    pub min_off_secs: u32,
+    // At most @max_on_secs of misting within any @window_secs.
+    pub max_on_secs: u32,
+    pub window_secs: u32,
+    // How long misting is held off after hitting the @max_on_secs limit.
+    pub cooldown_secs: u32,
+    // If set, auto-mist only uses readings taken after running the fans.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SensorSampling>,
+}
+
+// Periodically runs the fans for @fan_secs, waits @settle_secs, and then reads
+// the sensor, once every @interval_secs. Auto-mist decides based on the latest
+// of these readings, so misting continues until a sample shows that the
+// humidity is back up to the setpoint, or until it hits the HumidityControl
+// limits. Fans that are already on for the schedule count towards @fan_secs,
+// and fans that are overridden stay as they are.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct SensorSampling {
+    pub interval_secs: u32,
+    pub fan_secs: u32,
+    pub settle_secs: u32,
+}
+
+impl Default for SensorSampling {
+    fn default() -> Self {
+        Self {
+            interval_secs: 2 * 60,
+            fan_secs: 15,
+            settle_secs: 5,
+        }
+    }
+}
+
+impl Default for HumidityControl {
+    fn default() -> Self {
+        Self {
+            hysteresis: RelativeHumidity::new(0.05).unwrap(),
+            min_on_secs: 10,
+            min_off_secs: 60,
+            max_on_secs: 60,
+            window_secs: 10 * 60,
+            cooldown_secs: 5 * 60,
+            sampling: None,
+        }
+    }
+}
+
+// Longest window that misting time is tracked over.
+const MAX_WINDOW_SECS: u32 = 24 * 60 * 60;
+
+impl HumidityControl {
+    pub(crate) fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        if self.window_secs == 0 || self.window_secs > MAX_WINDOW_SECS {
+            report.add(
+                &field(path, "window_secs"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "window_secs must be between 1 and {MAX_WINDOW_SECS}, got {}",
+                    self.window_secs
+                ),
+            );
+        }
+        if self.max_on_secs > self.window_secs {
+            report.add(
+                &field(path, "max_on_secs"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "max_on_secs can't be longer than window_secs ({}), got {}",
+                    self.window_secs, self.max_on_secs
+                ),
+            );
+        }
+        if self.min_on_secs > self.max_on_secs {
+            report.add(
+                &field(path, "min_on_secs"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "min_on_secs can't be longer than max_on_secs ({}), got {}",
+                    self.max_on_secs, self.min_on_secs
+                ),
+            );
+        }
+        if let Some(sampling) = &self.sampling {
+            sampling.validate_at(&field(path, "sampling"), report);
+        }
+    }
+}
+
+impl SensorSampling {
+    fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        let sample_secs = u64::from(self.fan_secs) + u64::from(self.settle_secs);
+        if self.interval_secs == 0 || u64::from(self.interval_secs) < sample_secs {
+            report.add(
+                &field(path, "interval_secs"),
+                ErrorCode::OutOfRange,
+                format!(
+                    "interval_secs must be at least fan_secs + settle_secs ({sample_secs}) and more than 0, got {}",
+                    self.interval_secs
+                ),
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct HumidityController {
+    // When misting last started, if it's on.
+    on_since: Option<Instant>,
+    // When misting last stopped, if it has.
+    off_since: Option<Instant>,
+    // Start and end of each time misting ran that overlaps the current window.
+    on_periods: VecDeque<(Instant, Instant)>,
+    cooldown_until: Option<Instant>,
+}
+
+impl HumidityController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on_since.is_some()
+    }
+
+    // Returns whether the mister should run at @now, given the latest
+    // @humidity reading. @humidity is None if the sensor couldn't be read, in
+    // which case misting stops as soon as it's allowed to.
+    pub fn update(
+        &mut self,
+        settings: &HumidityControl,
+        setpoint: RelativeHumidity,
+        humidity: Option<f32>,
+        now: Instant,
+    ) -> bool {
+        let window = Duration::from_secs(settings.window_secs.into());
+        while let Some((_, end)) = self.on_periods.front()
+            && now.saturating_duration_since(*end) > window
+        {
+            self.on_periods.pop_front();
+        }
+        let on_in_window = self.on_time_in_window(window, now);
+        let cooling_down = self.cooldown_until.is_some_and(|until| now < until);
+
+        match self.on_since {
+            Some(on_since) => {
+                let on_for = now.saturating_duration_since(on_since);
+                if on_in_window >= Duration::from_secs(settings.max_on_secs.into()) {
+                    log::info!("Auto-mist hit its limit, cooling down");
+                    self.cooldown_until =
+                        Some(now + Duration::from_secs(settings.cooldown_secs.into()));
+                    self.turn_off(now);
+                } else if on_for < Duration::from_secs(settings.min_on_secs.into()) {
+                    // Keep misting for at least the minimum time.
+                } else if humidity.is_none_or(|humidity| humidity >= setpoint.get()) {
+                    self.turn_off(now);
+                }
+            }
+            None => {
+                let off_long_enough = self.off_since.is_none_or(|off_since| {
+                    now.saturating_duration_since(off_since)
+                        >= Duration::from_secs(settings.min_off_secs.into())
+                });
+                let too_dry = humidity
+                    .is_some_and(|humidity| humidity < setpoint.get() - settings.hysteresis.get());
+                // Only start if there's enough left of the limit to mist for
+                // the minimum time, otherwise it would start and then have to
+                // stop again right away.
+                let room_to_mist = on_in_window + Duration::from_secs(settings.min_on_secs.into())
+                    <= Duration::from_secs(settings.max_on_secs.into());
+                if off_long_enough && !cooling_down && too_dry && room_to_mist {
+                    self.on_since = Some(now);
+                }
+            }
+        }
+
+        self.is_on()
+    }
+
+    // Forgets about past misting, such as when auto-mist is turned off.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn turn_off(&mut self, now: Instant) {
+        if let Some(on_since) = self.on_since.take() {
+            self.on_periods.push_back((on_since, now));
+        }
+        self.off_since = Some(now);
+    }
+
+    // How long misting has run within the @window before @now.
+    fn on_time_in_window(&self, window: Duration, now: Instant) -> Duration {
+        let window_start = now.checked_sub(window);
+        let current = self.on_since.map(|on_since| (on_since, now));
+        self.on_periods
+            .iter()
+            .copied()
+            .chain(current)
+            .map(|(start, end)| {
+                let start = window_start.map_or(start, |window_start| start.max(window_start));
+                end.saturating_duration_since(start)
+            })
+            .sum()
+    }
+}
+
+// What SensorSampler needs the controller to do.
+#[derive(PartialEq, Debug)]
+pub enum SampleStep {
+    // Nothing until it's time for the next sample.
+    Wait,
+    // Run the fans to move air past the sensor.
+    RunFans,
+    // Give the air a moment to settle after the fans.
+    Settle,
+    // Read the sensor now and pass the result to SensorSampler::record().
+    Read,
+}
+
+enum SamplePhase {
+    // Waiting to start the next sample at the given time, or right away if
+    // None.
+    Waiting(Option<Instant>),
+    RunningFans { started: Instant, until: Instant },
+    Settling { started: Instant, until: Instant },
+}
+
+// Steps through the sampling routine described by SensorSampling.
+pub struct SensorSampler {
+    phase: SamplePhase,
+    // Humidity from the last sample, or None if there hasn't been one or the
+    // sensor couldn't be read.
+    humidity: Option<f32>,
+}
+
+impl Default for SensorSampler {
+    fn default() -> Self {
+        Self {
+            phase: SamplePhase::Waiting(None),
+            humidity: None,
+        }
+    }
+}
+
+impl SensorSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn humidity(&self) -> Option<f32> {
+        self.humidity
+    }
+
+    pub fn update(&mut self, settings: &SensorSampling, now: Instant) -> SampleStep {
+        let secs = |secs: u32| Duration::from_secs(secs.into());
+        // Phases with a length of zero are skipped right away.
+        loop {
+            match self.phase {
+                SamplePhase::Waiting(next) => {
+                    if next.is_some_and(|next| now < next) {
+                        return SampleStep::Wait;
+                    }
+                    self.phase = SamplePhase::RunningFans {
+                        started: now,
+                        until: now + secs(settings.fan_secs),
+                    };
+                }
+                SamplePhase::RunningFans { started, until } => {
+                    if now < until {
+                        return SampleStep::RunFans;
+                    }
+                    self.phase = SamplePhase::Settling {
+                        started,
+                        until: now + secs(settings.settle_secs),
+                    };
+                }
+                // Reading the sensor is up to the caller, and record() moves
+                // on to waiting for the next sample.
+                SamplePhase::Settling { until, .. } => {
+                    return if now < until {
+                        SampleStep::Settle
+                    } else {
+                        SampleStep::Read
+                    };
+                }
+            }
+        }
+    }
+
+    // Records the @humidity read for the sample that update() asked for.
+    pub fn record(&mut self, settings: &SensorSampling, humidity: Option<f32>) {
+        if let SamplePhase::Settling { started, .. } = self.phase {
+            self.humidity = humidity;
+            self.phase = SamplePhase::Waiting(Some(
+                started + Duration::from_secs(settings.interval_secs.into()),
+            ));
+        }
+    }
+
+    // Forgets the last sample and starts a new one on the next update().
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod humidity {
+    use super::*;
+
+    // A terrarium whose humidity rises while misting and falls otherwise. The
+    // sensor only sees the change after a delay, like the real one.
+    struct Simulated {
+        // Actual humidity, one entry per second.
+        history: Vec<f32>,
+        sensor_lag_secs: usize,
+    }
+
+    impl Simulated {
+        fn new(humidity: f32, sensor_lag_secs: usize) -> Self {
+            Self {
+                history: vec![humidity],
+                sensor_lag_secs,
+            }
+        }
+
+        fn reading(&self) -> f32 {
+            let i = self.history.len().saturating_sub(self.sensor_lag_secs + 1);
+            self.history[i]
+        }
+
+        fn step(&mut self, mist: bool) {
+            let humidity = self.history.last().unwrap();
+            let next = if mist {
+                humidity + 0.002
+            } else {
+                humidity - 0.0001
+            };
+            self.history.push(next.clamp(0.0, 1.0));
+        }
+    }
+
+    // Runs @ctl against @terrarium for @secs seconds, once per second, and
+    // returns whether the mister was on for each second.
+    fn run(
+        ctl: &mut HumidityController,
+        settings: &HumidityControl,
+        terrarium: &mut Simulated,
+        secs: u64,
+    ) -> Vec<bool> {
+        let start = Instant::now();
+        let setpoint = RelativeHumidity::new(0.8).unwrap();
+        (0..secs)
+            .map(|i| {
+                let now = start + Duration::from_secs(i);
+                let mist = ctl.update(settings, setpoint, Some(terrarium.reading()), now);
+                terrarium.step(mist);
+                mist
+            })
+            .collect()
+    }
+
+    // Number of times the mister was turned on.
+    fn starts(mist: &[bool]) -> usize {
+        mist.windows(2).filter(|w| !w[0] && w[1]).count() + usize::from(mist[0])
+    }
+
+    #[test]
+    fn hysteresis() {
+        let settings = HumidityControl {
+            min_on_secs: 0,
+            min_off_secs: 0,
+            max_on_secs: 600,
+            ..HumidityControl::default()
+        };
+        let mut ctl = HumidityController::new();
+        let mut terrarium = Simulated::new(0.77, 0);
+        // Within the band below the setpoint, so misting doesn't start until
+        // the humidity drops below 75%.
+        let mist = run(&mut ctl, &settings, &mut terrarium, 600);
+        let first_on = mist.iter().position(|on| *on).unwrap();
+        assert!(terrarium.history[first_on] < 0.75);
+        assert!(terrarium.history[first_on - 1] >= 0.75);
+        // And then it runs until the humidity is back up to the setpoint.
+        let on_for = mist[first_on..].iter().take_while(|on| **on).count();
+        assert!(terrarium.history[first_on + on_for] >= 0.8);
+    }
+
+    #[test]
+    fn lagging_sensor() {
+        let mut terrarium = Simulated::new(0.7, 30);
+        let settings = HumidityControl::default();
+        let mist = run(
+            &mut HumidityController::new(),
+            &settings,
+            &mut terrarium,
+            60 * 60,
+        );
+
+        // It overshoots while waiting for the sensor to catch up, but not by
+        // much.
+        assert!(terrarium.history.iter().all(|h| *h < 0.9));
+        assert!(terrarium.history[600..].iter().all(|h| *h > 0.7));
+
+        // It doesn't chatter.
+        let mut on_runs = Vec::new();
+        let mut off_runs = Vec::new();
+        for run in mist.chunk_by(|a, b| a == b) {
+            if run[0] {
+                on_runs.push(run.len());
+            } else {
+                off_runs.push(run.len());
+            }
+        }
+        assert!(on_runs.iter().all(|len| *len >= 10));
+        assert!(off_runs[1..].iter().all(|len| *len >= 60));
+
+        // And it never mists for more than a minute in ten.
+        for window in mist.windows(600) {
+            assert!(window.iter().filter(|on| **on).count() <= 60);
+        }
+    }
+
+    // Runs a controller for @secs seconds with a sensor that's stuck reading
+    // dry air.
+    fn run_stuck(settings: &HumidityControl, secs: u64) -> Vec<bool> {
+        let mut ctl = HumidityController::new();
+        let start = Instant::now();
+        let setpoint = RelativeHumidity::new(0.8).unwrap();
+        (0..secs)
+            .map(|i| {
+                ctl.update(
+                    settings,
+                    setpoint,
+                    Some(0.1),
+                    start + Duration::from_secs(i),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn max_on_time() {
+        // A minute of misting, then nothing until the end of that minute
+        // leaves the ten minute window far enough to mist for the minimum
+        // time again. By then it has to mist for a full minute again before
+        // hitting the limit.
+        let mist = run_stuck(&HumidityControl::default(), 30 * 60);
+        assert!(mist[..60].iter().all(|on| *on));
+        assert!(mist[60..610].iter().all(|on| !*on));
+        assert!(mist[610..670].iter().all(|on| *on));
+        assert!(!mist[670]);
+        assert_eq!(starts(&mist), 3);
+    }
+
+    #[test]
+    fn cooldown() {
+        let settings = HumidityControl {
+            cooldown_secs: 15 * 60,
+            ..HumidityControl::default()
+        };
+        let mist = run_stuck(&settings, 30 * 60);
+        assert!(mist[..60].iter().all(|on| *on));
+        assert!(mist[60..960].iter().all(|on| !*on));
+        assert!(mist[960]);
+        assert_eq!(starts(&mist), 2);
+    }
+
+    #[test]
+    fn sensor_failure() {
+        let settings = HumidityControl::default();
+        let mut ctl = HumidityController::new();
+        let start = Instant::now();
+        let setpoint = RelativeHumidity::new(0.8).unwrap();
+        assert!(ctl.update(&settings, setpoint, Some(0.5), start));
+        // Keeps going for the minimum time, then stops.
+        assert!(ctl.update(&settings, setpoint, None, start + Duration::from_secs(5)));
+        assert!(!ctl.update(&settings, setpoint, None, start + Duration::from_secs(10)));
+        assert!(!ctl.update(&settings, setpoint, None, start + Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn sensor_sampling() {
+        let settings = SensorSampling {
+            interval_secs: 60,
+            fan_secs: 10,
+            settle_secs: 5,
+        };
+        let mut sampler = SensorSampler::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(sampler.update(&settings, at(0)), SampleStep::RunFans);
+        assert_eq!(sampler.update(&settings, at(9)), SampleStep::RunFans);
+        assert_eq!(sampler.update(&settings, at(10)), SampleStep::Settle);
+        assert_eq!(sampler.update(&settings, at(15)), SampleStep::Read);
+        // Keeps asking until it gets a reading.
+        assert_eq!(sampler.update(&settings, at(16)), SampleStep::Read);
+        assert_eq!(sampler.humidity(), None);
+        sampler.record(&settings, Some(0.6));
+        assert_eq!(sampler.humidity(), Some(0.6));
+
+        // Samples start every @interval_secs.
+        assert_eq!(sampler.update(&settings, at(17)), SampleStep::Wait);
+        assert_eq!(sampler.update(&settings, at(59)), SampleStep::Wait);
+        assert_eq!(sampler.update(&settings, at(60)), SampleStep::RunFans);
+        assert_eq!(sampler.humidity(), Some(0.6));
+
+        sampler.reset();
+        assert_eq!(sampler.humidity(), None);
+        let instant = SensorSampling {
+            fan_secs: 0,
+            settle_secs: 0,
+            ..settings
+        };
+        assert_eq!(sampler.update(&instant, at(61)), SampleStep::Read);
+    }
+
+    #[test]
+    fn validate() {
+        let mut report = ValidationReport::default();
+        HumidityControl::default().validate_at("humidity_control", &mut report);
+        assert!(report.is_empty());
+
+        let settings = HumidityControl {
+            min_on_secs: 120,
+            window_secs: 0,
+            sampling: Some(SensorSampling {
+                interval_secs: 10,
+                ..SensorSampling::default()
+            }),
+            ..HumidityControl::default()
+        };
+        settings.validate_at("humidity_control", &mut report);
+        let paths: Vec<&str> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "humidity_control.window_secs",
+                "humidity_control.max_on_secs",
+                "humidity_control.min_on_secs",
+                "humidity_control.sampling.interval_secs"
+            ]
+        );
+    }
+}
@@ -3,6 +3,15 @@
 pub mod cancel_context;
 pub mod config;
 pub mod controller;
+pub mod cron;
+pub mod events;
+pub mod humidity;
 pub mod influxdb;
+pub mod mist_interlock;
+pub mod reasons;
+pub mod schema;
+pub mod storage;
+pub mod sun;
 pub mod terrarium;
+pub mod thermal;
 pub mod types;
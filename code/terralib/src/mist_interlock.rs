@@ -0,0 +1,336 @@
+// Safety limits on the mister, enforced below everything that controls it.
+//
+// The ultrasonic mister is damaged by running dry, so however it's being
+// turned on - the schedule, auto-mist, an override, or an effect - it's only
+// allowed to run for so long at a time, has to rest in between, and has a cap
+// on how long it runs per day. The limits are deliberately not part of the
+// config, so that a bad config can't turn them off.
+
+use crate::terrarium::Terrarium;
+use crate::types::SensorValues;
+use jiff::Timestamp;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Only the latest few trips are reported.
+const MAX_TRIPS: usize = 8;
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+pub struct MistLimits {
+    // Longest the mister can run without a break.
+    pub max_on: Duration,
+    // Shortest break between runs.
+    pub min_rest: Duration,
+    // Most the mister can run within the last 24 hours. This is counted in
+    // whole hours, so time spent misting is forgotten up to an hour early.
+    pub daily_cap: Duration,
+}
+
+impl Default for MistLimits {
+    fn default() -> Self {
+        Self {
+            max_on: Duration::from_secs(5 * 60),
+            min_rest: Duration::from_secs(60),
+            daily_cap: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MistTripReason {
+    MaxOnTime,
+    MinRest,
+    DailyCap,
+}
+
+// A time the interlock turned the mister off, or refused to turn it on.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct MistTrip {
+    pub timestamp: Timestamp,
+    pub reason: MistTripReason,
+}
+
+// Reported in TerrariumState.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
+pub struct MistInterlockStatus {
+    // Why the mister is being held off, if it is.
+    pub blocked: Option<MistTripReason>,
+    // Time spent misting within the last 24 hours.
+    pub daily_on_secs: u64,
+    // Oldest first.
+    pub trips: Vec<MistTrip>,
+}
+
+// Wraps a Terrarium, passing everything through to it except for turning on
+// the mister when that would break the limits.
+pub struct MistInterlock {
+    inner: Arc<Mutex<dyn Terrarium + Send>>,
+    limits: MistLimits,
+    start: Instant,
+    on_since: Option<Instant>,
+    off_since: Option<Instant>,
+    // Misting time accounted for so far, by hours since @start. Only the last
+    // 24 hours are kept.
+    hourly_on: VecDeque<(u64, Duration)>,
+    accounted_until: Instant,
+    blocked: Option<MistTripReason>,
+    trips: VecDeque<MistTrip>,
+}
+
+impl MistInterlock {
+    pub fn new(inner: Arc<Mutex<dyn Terrarium + Send>>) -> Self {
+        Self::with_limits(inner, MistLimits::default())
+    }
+
+    pub fn with_limits(inner: Arc<Mutex<dyn Terrarium + Send>>, limits: MistLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            limits,
+            start: now,
+            on_since: None,
+            off_since: None,
+            hourly_on: VecDeque::new(),
+            accounted_until: now,
+            blocked: None,
+            trips: VecDeque::new(),
+        }
+    }
+
+    // Why the mister is being held off, if it is.
+    pub fn blocked(&self) -> Option<MistTripReason> {
+        self.blocked
+    }
+
+    pub fn status(&self) -> MistInterlockStatus {
+        MistInterlockStatus {
+            blocked: self.blocked,
+            daily_on_secs: self.daily_on().as_secs(),
+            trips: self.trips.iter().cloned().collect(),
+        }
+    }
+
+    // Turns the mister on or off as @requested at @now, unless the limits
+    // don't allow it to be on.
+    fn set_mist_at(&mut self, requested: bool, now: Instant) {
+        self.account(now);
+        let allowed = if requested { self.check(now) } else { Ok(()) };
+        let on = match allowed {
+            Ok(()) => {
+                self.blocked = None;
+                requested
+            }
+            Err(reason) => {
+                // Only the first time a request is refused is a trip, not
+                // every time it's repeated after that.
+                if self.blocked.is_none() {
+                    log::warn!("Mist interlock tripped: {reason:?}");
+                    if self.trips.len() == MAX_TRIPS {
+                        self.trips.pop_front();
+                    }
+                    self.trips.push_back(MistTrip {
+                        timestamp: Timestamp::now(),
+                        reason,
+                    });
+                }
+                self.blocked = Some(reason);
+                false
+            }
+        };
+
+        match (on, self.on_since) {
+            (true, None) => self.on_since = Some(now),
+            (false, Some(_)) => {
+                self.on_since = None;
+                self.off_since = Some(now);
+            }
+            _ => {}
+        }
+        self.inner.lock().unwrap().set_mist(on);
+    }
+
+    // Checks whether the mister can be on at @now.
+    fn check(&self, now: Instant) -> Result<(), MistTripReason> {
+        if let Some(on_since) = self.on_since
+            && now.saturating_duration_since(on_since) >= self.limits.max_on
+        {
+            return Err(MistTripReason::MaxOnTime);
+        }
+        if self.on_since.is_none()
+            && let Some(off_since) = self.off_since
+            && now.saturating_duration_since(off_since) < self.limits.min_rest
+        {
+            return Err(MistTripReason::MinRest);
+        }
+        if self.daily_on() >= self.limits.daily_cap {
+            return Err(MistTripReason::DailyCap);
+        }
+        Ok(())
+    }
+
+    // Adds the time the mister has been on since the last call to the hourly
+    // totals.
+    fn account(&mut self, now: Instant) {
+        let hour =
+            |instant: Instant| instant.saturating_duration_since(self.start).as_secs() / 3600;
+        if self.on_since.is_some() {
+            let mut from = self.accounted_until;
+            while from < now {
+                let h = hour(from);
+                let until = now.min(self.start + HOUR * (h as u32 + 1));
+                match self.hourly_on.back_mut() {
+                    Some((last, total)) if *last == h => *total += until - from,
+                    _ => self.hourly_on.push_back((h, until - from)),
+                }
+                from = until;
+            }
+        }
+        self.accounted_until = now;
+        let current = hour(now);
+        while let Some((h, _)) = self.hourly_on.front()
+            && current - h >= 24
+        {
+            self.hourly_on.pop_front();
+        }
+    }
+
+    fn daily_on(&self) -> Duration {
+        self.hourly_on.iter().map(|(_, total)| *total).sum()
+    }
+}
+
+impl Terrarium for MistInterlock {
+    fn set_lights(&mut self, val: f32) {
+        self.inner.lock().unwrap().set_lights(val);
+    }
+    fn set_lights_with_fade(&mut self, val: f32, fade_ms: i32) {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_lights_with_fade(val, fade_ms);
+    }
+    fn get_lights(&self) -> f32 {
+        self.inner.lock().unwrap().get_lights()
+    }
+
+    fn set_mist(&mut self, on: bool) {
+        self.set_mist_at(on, Instant::now());
+    }
+    fn get_mist(&self) -> bool {
+        self.inner.lock().unwrap().get_mist()
+    }
+
+    fn set_fans(&mut self, on: bool) {
+        self.inner.lock().unwrap().set_fans(on);
+    }
+    fn get_fans(&self) -> bool {
+        self.inner.lock().unwrap().get_fans()
+    }
+
+    fn read_sensors(&mut self) -> Option<SensorValues> {
+        self.inner.lock().unwrap().read_sensors()
+    }
+
+    fn read_cpu_temp(&mut self) -> Option<f32> {
+        self.inner.lock().unwrap().read_cpu_temp()
+    }
+}
+
+#[cfg(test)]
+mod mist_interlock {
+    use super::*;
+    use crate::terrarium::FakeTerrarium;
+
+    fn interlock() -> (MistInterlock, Arc<Mutex<FakeTerrarium>>) {
+        let fake = Arc::new(Mutex::new(FakeTerrarium::new()));
+        (MistInterlock::new(fake.clone()), fake)
+    }
+
+    // Keeps requesting mist, once a second for @secs seconds starting at
+    // @from, and returns whether it was on for each of them.
+    fn request(interlock: &mut MistInterlock, from: Duration, secs: u64) -> Vec<bool> {
+        (0..secs)
+            .map(|i| {
+                interlock.set_mist_at(true, interlock.start + from + Duration::from_secs(i));
+                interlock.get_mist()
+            })
+            .collect()
+    }
+
+    fn reasons(interlock: &MistInterlock) -> Vec<MistTripReason> {
+        interlock.trips.iter().map(|trip| trip.reason).collect()
+    }
+
+    #[test]
+    fn passes_through() {
+        let (mut interlock, fake) = interlock();
+        interlock.set_fans(true);
+        interlock.set_mist(true);
+        assert!(fake.lock().unwrap().get_fans());
+        assert!(fake.lock().unwrap().get_mist());
+        interlock.set_mist(false);
+        assert!(!interlock.get_mist());
+        assert_eq!(interlock.status(), MistInterlockStatus::default());
+    }
+
+    #[test]
+    fn stuck_on() {
+        let (mut interlock, _) = interlock();
+        // Five minutes on, a minute of rest, and so on.
+        let mist = request(&mut interlock, Duration::ZERO, 700);
+        assert!(mist[..300].iter().all(|on| *on));
+        assert!(mist[300..360].iter().all(|on| !*on));
+        assert!(mist[360..660].iter().all(|on| *on));
+        assert!(!mist[660]);
+        // A trip each time it's cut off, but not for the rest that follows.
+        assert_eq!(
+            reasons(&interlock),
+            vec![MistTripReason::MaxOnTime, MistTripReason::MaxOnTime]
+        );
+        assert_eq!(interlock.status().blocked, Some(MistTripReason::MinRest));
+        assert_eq!(interlock.status().daily_on_secs, 600);
+    }
+
+    #[test]
+    fn min_rest() {
+        let (mut interlock, _) = interlock();
+        let start = interlock.start;
+        let t = |secs| start + Duration::from_secs(secs);
+        let (on, off, again) = (t(0), t(10), t(20));
+        interlock.set_mist_at(true, on);
+        interlock.set_mist_at(false, off);
+        interlock.set_mist_at(true, again);
+        assert!(!interlock.get_mist());
+        assert_eq!(reasons(&interlock), vec![MistTripReason::MinRest]);
+
+        // Turning it off clears the block.
+        interlock.set_mist_at(false, t(30));
+        assert_eq!(interlock.status().blocked, None);
+        interlock.set_mist_at(true, t(70));
+        assert!(interlock.get_mist());
+    }
+
+    #[test]
+    fn daily_cap() {
+        let (mut interlock, _) = interlock();
+        // Five minutes of misting every half hour.
+        for i in 0..12 {
+            let from = Duration::from_secs(i * 30 * 60);
+            assert!(request(&mut interlock, from, 300).iter().all(|on| *on));
+            interlock.set_mist_at(false, interlock.start + from + Duration::from_secs(300));
+        }
+        assert_eq!(interlock.status().daily_on_secs, 60 * 60);
+        let mist = request(&mut interlock, Duration::from_secs(6 * 60 * 60), 1);
+        assert_eq!(mist, vec![false]);
+        assert_eq!(reasons(&interlock), vec![MistTripReason::DailyCap]);
+
+        // A day later, the earliest misting has been forgotten.
+        let mist = request(&mut interlock, Duration::from_secs(25 * 60 * 60), 1);
+        assert_eq!(mist, vec![true]);
+    }
+}
@@ -0,0 +1,170 @@
+// Why each actuator has the value it does, for figuring out things like why
+// the mister ran when nobody expected it to. Recorded by the controller on
+// each run() and reported in TerrariumState.
+
+use crate::mist_interlock::MistTripReason;
+use crate::thermal::ThermalLevel;
+use crate::types::{Actuator, OverrideHold};
+use jiff::civil::Time;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ActuatorReason {
+    // Nothing turned it on.
+    #[default]
+    Unscheduled,
+    // Event @index of the schedule's mist or fans events, in the occurrence
+    // that started at @start_time.
+    ScheduleEvent {
+        index: usize,
+        start_time: Time,
+    },
+    // The schedule's lights time range.
+    LightTimeRange {
+        start: Time,
+        stop: Time,
+    },
+    LightProfile,
+    SolarLights,
+    // Auto-mist, because @humidity was below the setpoint. Both are relative
+    // humidities from 0 to 1.
+    AutoMist {
+        humidity: Option<f32>,
+        setpoint: f32,
+    },
+    // The fans are run before taking a humidity sample for auto-mist.
+    SensorSampling,
+    Override {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hold: Option<OverrideHold>,
+        from_sequence: bool,
+    },
+    // A light effect, such as the breathing when the reset button is held.
+    Effect {
+        name: String,
+    },
+    // Something outside of the controller has taken over the lights.
+    ExternalLightControl,
+    // Thermal protection dimmed the lights or turned on the fans.
+    ThermalProtection {
+        level: ThermalLevel,
+    },
+    // The mist interlock is holding the mister off.
+    MistInterlock {
+        reason: MistTripReason,
+    },
+}
+
+impl fmt::Display for ActuatorReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActuatorReason::Unscheduled => write!(f, "not scheduled"),
+            ActuatorReason::ScheduleEvent { index, start_time } => {
+                write!(f, "schedule event #{index} (started {start_time})")
+            }
+            ActuatorReason::LightTimeRange { start, stop } => {
+                write!(f, "lights schedule ({start} to {stop})")
+            }
+            ActuatorReason::LightProfile => write!(f, "light profile"),
+            ActuatorReason::SolarLights => write!(f, "solar lights"),
+            ActuatorReason::AutoMist { humidity, setpoint } => {
+                let humidity =
+                    humidity.map_or("unknown".to_string(), |h| format!("{:.1}%", h * 100.0));
+                write!(
+                    f,
+                    "auto-mist (humidity {humidity}, setpoint {:.1}%)",
+                    setpoint * 100.0
+                )
+            }
+            ActuatorReason::SensorSampling => write!(f, "humidity sampling"),
+            ActuatorReason::Override {
+                hold,
+                from_sequence,
+            } => {
+                write!(f, "override")?;
+                match hold {
+                    Some(OverrideHold::NextTransition) => write!(f, " until the next change")?,
+                    Some(OverrideHold::Manual) => write!(f, " until cleared")?,
+                    None => {}
+                }
+                if *from_sequence {
+                    write!(f, " (sequence)")?;
+                }
+                Ok(())
+            }
+            ActuatorReason::Effect { name } => write!(f, "{name} effect"),
+            ActuatorReason::ExternalLightControl => write!(f, "external light control"),
+            ActuatorReason::ThermalProtection { level } => {
+                write!(f, "thermal protection ({level:?})")
+            }
+            ActuatorReason::MistInterlock { reason } => {
+                write!(f, "mist interlock ({reason:?})")
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug, Default)]
+pub struct ActuatorReasons {
+    pub lights: ActuatorReason,
+    pub fans: ActuatorReason,
+    pub mist: ActuatorReason,
+}
+
+impl ActuatorReasons {
+    pub fn get_mut(&mut self, actuator: Actuator) -> &mut ActuatorReason {
+        match actuator {
+            Actuator::Lights => &mut self.lights,
+            Actuator::Fans => &mut self.fans,
+            Actuator::Mist => &mut self.mist,
+        }
+    }
+}
+
+#[cfg(test)]
+mod reasons {
+    use super::*;
+
+    #[test]
+    fn json_format() {
+        let reasons = ActuatorReasons {
+            lights: ActuatorReason::LightTimeRange {
+                start: jiff::civil::time(8, 0, 0, 0),
+                stop: jiff::civil::time(20, 0, 0, 0),
+            },
+            fans: ActuatorReason::Unscheduled,
+            mist: ActuatorReason::ScheduleEvent {
+                index: 1,
+                start_time: jiff::civil::time(9, 30, 0, 0),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&reasons).unwrap(),
+            serde_json::json!({
+                "lights": {"kind": "light_time_range", "start": "08:00:00", "stop": "20:00:00"},
+                "fans": {"kind": "unscheduled"},
+                "mist": {"kind": "schedule_event", "index": 1, "start_time": "09:30:00"},
+            })
+        );
+    }
+
+    #[test]
+    fn display() {
+        let reason = ActuatorReason::AutoMist {
+            humidity: Some(0.62),
+            setpoint: 0.8,
+        };
+        assert_eq!(
+            reason.to_string(),
+            "auto-mist (humidity 62.0%, setpoint 80.0%)"
+        );
+        let reason = ActuatorReason::Override {
+            hold: Some(OverrideHold::Manual),
+            from_sequence: false,
+        };
+        assert_eq!(reason.to_string(), "override until cleared");
+    }
+}
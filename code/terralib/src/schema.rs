@@ -0,0 +1,158 @@
+// JSON Schema documents for the json that the terrarium's http api accepts and
+// returns. These are generated from the rust types, so they stay in sync with
+// what the terrarium actually parses.
+
+use crate::config::diff::DryRunResult;
+use crate::config::history::HistoryEntrySummary;
+use crate::config::validation::ValidationReport;
+use crate::config::{TerrariumConfig, TerrariumConfigUpdate};
+use crate::events::Event;
+use crate::storage::StorageStatus;
+use crate::types::{ActuatorOverrideSet, OverrideSequence, TerrariumState};
+use schemars::{Schema, schema_for};
+
+// Names of the available schemas, which are the names of the types they
+// describe.
+pub const NAMES: [&str; 10] = [
+    // GET /config
+    "TerrariumConfig",
+    // POST /config
+    "TerrariumConfigUpdate",
+    // Response to POST /config when the update is rejected
+    "ValidationReport",
+    // Response to POST /config?dry_run=1
+    "DryRunResult",
+    // GET /config/history returns a list of these
+    "HistoryEntrySummary",
+    // POST /control
+    "ActuatorOverrideSet",
+    // POST /control/sequence
+    "OverrideSequence",
+    // GET /state
+    "TerrariumState",
+    // GET /status
+    "StorageStatus",
+    // GET /events returns a list of these
+    "Event",
+];
+
+// Returns the schema called @name, or None if there isn't one.
+pub fn schema(name: &str) -> Option<Schema> {
+    Some(match name {
+        "TerrariumConfig" => schema_for!(TerrariumConfig),
+        "TerrariumConfigUpdate" => schema_for!(TerrariumConfigUpdate),
+        "ValidationReport" => schema_for!(ValidationReport),
+        "DryRunResult" => schema_for!(DryRunResult),
+        "HistoryEntrySummary" => schema_for!(HistoryEntrySummary),
+        "ActuatorOverrideSet" => schema_for!(ActuatorOverrideSet),
+        "OverrideSequence" => schema_for!(OverrideSequence),
+        "TerrariumState" => schema_for!(TerrariumState),
+        "StorageStatus" => schema_for!(StorageStatus),
+        "Event" => schema_for!(Event),
+        _ => return None,
+    })
+}
+
+// Returns a json object mapping each schema name to its schema.
+pub fn all_schemas() -> serde_json::Value {
+    NAMES
+        .iter()
+        .map(|name| (name.to_string(), schema(name).unwrap().to_value()))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+#[cfg(test)]
+mod schema {
+    use super::*;
+    use serde_json::json;
+
+    fn validates(name: &str, v: serde_json::Value) -> bool {
+        jsonschema::is_valid(&schema(name).unwrap().to_value(), &v)
+    }
+
+    #[test]
+    fn all_schemas() {
+        let all = super::all_schemas();
+        for name in NAMES {
+            assert_eq!(all[name]["title"], name);
+        }
+        assert!(schema("Foo").is_none());
+    }
+
+    #[test]
+    fn config() {
+        let cfg = TerrariumConfig::new_with_reasonable_defaults();
+        assert!(validates(
+            "TerrariumConfig",
+            serde_json::to_value(&cfg).unwrap()
+        ));
+        let stored: serde_json::Value =
+            serde_json::from_str(include_str!("../testdata/config/v1.json")).unwrap();
+        assert!(validates("TerrariumConfig", stored));
+        assert!(!validates("TerrariumConfig", json!({"name": "oasis"})));
+    }
+
+    #[test]
+    fn config_update() {
+        // Leaving fields out, setting them, and clearing them are all valid.
+        assert!(validates("TerrariumConfigUpdate", json!({})));
+        assert!(validates(
+            "TerrariumConfigUpdate",
+            json!({"timezone": null, "name": "oasis", "schedule": {"lights": null}})
+        ));
+        assert!(!validates("TerrariumConfigUpdate", json!({"name": 5})));
+        assert!(!validates(
+            "TerrariumConfigUpdate",
+            json!({"schedule": {"fans": "11:00"}})
+        ));
+
+        let schema = schema("TerrariumConfigUpdate").unwrap().to_value();
+        assert!(
+            schema["properties"]["name"]["description"]
+                .as_str()
+                .unwrap()
+                .contains("null to clear")
+        );
+    }
+
+    #[test]
+    fn control() {
+        assert!(validates(
+            "ActuatorOverrideSet",
+            json!({"updates": [
+                {"actuator": "lights", "value": 0.5, "duration_secs": 60},
+                {"actuator": "mist", "value": true, "duration_secs": 10},
+            ]})
+        ));
+        assert!(!validates(
+            "ActuatorOverrideSet",
+            json!({"updates": [{"actuator": "heater", "value": true, "duration_secs": 10}]})
+        ));
+        assert!(!validates(
+            "ActuatorOverrideSet",
+            json!({"updates": [{"actuator": "mist", "value": true}]})
+        ));
+        assert!(validates(
+            "ActuatorOverrideSet",
+            json!({"updates": [
+                {"actuator": "lights", "value": 0.0, "duration_secs": 0, "hold": "next_transition"},
+            ]})
+        ));
+        assert!(!validates(
+            "ActuatorOverrideSet",
+            json!({"updates": [
+                {"actuator": "lights", "value": 0.0, "duration_secs": 0, "hold": "forever"},
+            ]})
+        ));
+        // A step's delay can be left out.
+        assert!(validates(
+            "OverrideSequence",
+            json!({"steps": [
+                {"updates": [{"actuator": "mist", "value": true, "duration_secs": 30}]},
+                {"delay_secs": 10, "updates": [{"actuator": "fans", "value": true, "duration_secs": 120}]},
+            ]})
+        ));
+        assert!(!validates("OverrideSequence", json!({"steps": [{}]})));
+    }
+}
@@ -0,0 +1,338 @@
+// Crash-safe storage of files like the config on the esp32's flash.
+//
+// Files are written to a temporary file that is then renamed over the real
+// one, so losing power partway through a write leaves either the old file or
+// the new one, never a truncated mix. A checksum of the contents is stored at
+// the end of each file so that corruption is noticed when reading it back, and
+// the previous good copy is kept as a backup to fall back to.
+
+use anyhow::anyhow;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Files end with a trailer line holding the CRC-32 of everything before it,
+// like "\ncrc32:1a2b3c4d\n". Files without one were written before checksums
+// were added, and are read without being checked. That means a file that lost
+// its whole trailer isn't caught here, but it's also missing the end of its
+// json, so it fails to parse instead.
+const TRAILER_PREFIX: &[u8] = b"\ncrc32:";
+const TRAILER_LEN: usize = TRAILER_PREFIX.len() + 8 + 1;
+
+// Where the config that the terrarium is running was loaded from at boot.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    File,
+    // The config file was missing or corrupt, so the last good copy was used.
+    Backup,
+    // There was no usable config file, so the defaults were used.
+    Defaults,
+}
+
+// Returned by GET /status.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+pub struct StorageStatus {
+    pub config_source: ConfigSource,
+    // Why the config file wasn't used, if it wasn't.
+    pub config_error: Option<String>,
+}
+
+// A value read by read().
+#[derive(PartialEq, Debug)]
+pub struct Loaded<T> {
+    pub value: T,
+    pub from_backup: bool,
+    // Why the file itself couldn't be used, if the backup was.
+    pub error: Option<String>,
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+// Writes @data to @path. The file that was there before is kept as the backup,
+// unless it's corrupt, in which case the existing backup is kept instead.
+pub fn write(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = write_tmp(path, data)?;
+    if fs::read(path).is_ok_and(|stored| verify(&stored).is_ok()) {
+        fs::rename(path, backup_path(path))?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Like write(), but always keeps the existing backup. This is for replacing a
+// file that read() fell back to the backup for: the file can pass its checksum
+// and still not parse, and write() would then replace the good backup with it.
+pub fn write_keep_backup(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = write_tmp(path, data)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Writes @data and its trailer to a temporary file next to @path, and returns
+// the temporary file's path.
+fn write_tmp(path: &Path, data: &[u8]) -> anyhow::Result<PathBuf> {
+    let tmp_path = with_suffix(path, ".tmp");
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.write_all(&trailer(data))?;
+    file.sync_all()?;
+    Ok(tmp_path)
+}
+
+// Reads the file at @path and parses it with @parse, falling back to the
+// backup if the file is missing, corrupt, or doesn't parse. Returns None if
+// neither exists.
+pub fn read<T>(
+    path: &Path,
+    parse: impl Fn(&[u8]) -> anyhow::Result<T>,
+) -> anyhow::Result<Option<Loaded<T>>> {
+    let load = |path: &Path| -> anyhow::Result<Option<T>> {
+        let stored = match fs::read(path) {
+            Ok(stored) => stored,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Some(parse(verify(&stored)?)?))
+    };
+
+    let error = match load(path) {
+        Ok(Some(value)) => {
+            return Ok(Some(Loaded {
+                value,
+                from_backup: false,
+                error: None,
+            }));
+        }
+        Ok(None) => format!("{} doesn't exist", path.display()),
+        Err(err) => format!("{}: {err:#}", path.display()),
+    };
+    let backup = backup_path(path);
+    match load(&backup) {
+        Ok(Some(value)) => Ok(Some(Loaded {
+            value,
+            from_backup: true,
+            error: Some(error),
+        })),
+        Ok(None) if !path.exists() => Ok(None),
+        Ok(None) => Err(anyhow!("{error}, and there's no backup")),
+        Err(err) => Err(anyhow!("{error}, and {}: {err:#}", backup.display())),
+    }
+}
+
+// Removes the file at @path along with its backup.
+pub fn remove(path: &Path) -> anyhow::Result<()> {
+    for path in [path.to_path_buf(), backup_path(path)] {
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn trailer(data: &[u8]) -> Vec<u8> {
+    let mut trailer = TRAILER_PREFIX.to_vec();
+    trailer.extend(format!("{:08x}\n", crc32(data)).as_bytes());
+    trailer
+}
+
+// Returns the contents of @stored without the trailer, checking that they
+// match the checksum in it.
+fn verify(stored: &[u8]) -> anyhow::Result<&[u8]> {
+    let Some(start) = stored
+        .windows(TRAILER_PREFIX.len())
+        .rposition(|window| window == TRAILER_PREFIX)
+    else {
+        return Ok(stored);
+    };
+    if stored.len() - start != TRAILER_LEN {
+        return Err(anyhow!("invalid checksum trailer"));
+    }
+    let (data, trailer) = stored.split_at(start);
+    let expected = std::str::from_utf8(&trailer[TRAILER_PREFIX.len()..TRAILER_LEN - 1])
+        .ok()
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| anyhow!("invalid checksum trailer"))?;
+    let actual = crc32(data);
+    if actual != expected {
+        return Err(anyhow!(
+            "checksum mismatch, expected {expected:08x} but the contents have {actual:08x}"
+        ));
+    }
+    Ok(data)
+}
+
+// The CRC-32 used by zip, png, etc. Computed a bit at a time, which is plenty
+// fast for files of a few kilobytes.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod storage {
+    use super::*;
+
+    // A fresh directory for each test to put its files in.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("terralib-storage-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn parse_str(data: &[u8]) -> anyhow::Result<String> {
+        Ok(std::str::from_utf8(data)?.to_string())
+    }
+
+    fn read_str(path: &Path) -> anyhow::Result<Option<Loaded<String>>> {
+        read(path, parse_str)
+    }
+
+    #[test]
+    fn checksum() {
+        // The standard check value for CRC-32.
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+
+        let mut stored = b"{}".to_vec();
+        stored.extend(trailer(b"{}"));
+        assert_eq!(verify(&stored).unwrap(), b"{}");
+        stored[0] = b'[';
+        assert!(verify(&stored).is_err());
+        // Files from before checksums were added are read as-is.
+        assert_eq!(verify(b"{}").unwrap(), b"{}");
+    }
+
+    #[test]
+    fn write_and_read() {
+        let path = test_dir("write_and_read").join("config.json");
+        assert_eq!(read_str(&path).unwrap(), None);
+
+        write(&path, b"first").unwrap();
+        write(&path, b"second").unwrap();
+        let loaded = read_str(&path).unwrap().unwrap();
+        assert_eq!(loaded.value, "second");
+        assert!(!loaded.from_backup);
+        assert!(!with_suffix(&path, ".tmp").exists());
+        assert_eq!(
+            verify(&fs::read(backup_path(&path)).unwrap()).unwrap(),
+            b"first"
+        );
+
+        remove(&path).unwrap();
+        assert!(!path.exists() && !backup_path(&path).exists());
+    }
+
+    #[test]
+    fn falls_back_to_backup() {
+        let path = test_dir("falls_back_to_backup").join("config.json");
+        write(&path, b"first").unwrap();
+        write(&path, b"second").unwrap();
+
+        // Lose the end of the file, like a power loss partway through a write
+        // by firmware that didn't write atomically.
+        let stored = fs::read(&path).unwrap();
+        fs::write(&path, &stored[..stored.len() - 3]).unwrap();
+        let loaded = read_str(&path).unwrap().unwrap();
+        assert_eq!(loaded.value, "first");
+        assert!(loaded.from_backup);
+        assert!(loaded.error.unwrap().contains("checksum"));
+
+        // Writing again doesn't replace the good backup with the corrupt file.
+        write(&path, b"third").unwrap();
+        assert_eq!(read_str(&path).unwrap().unwrap().value, "third");
+        assert_eq!(
+            verify(&fs::read(backup_path(&path)).unwrap()).unwrap(),
+            b"first"
+        );
+
+        // A file that doesn't parse also falls back.
+        let loaded = read(&path, |data| {
+            if data == b"third" {
+                Err(anyhow!("unsupported"))
+            } else {
+                parse_str(data)
+            }
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(loaded.value, "first");
+
+        // Power lost between moving the file to the backup and moving the new
+        // one into place.
+        fs::remove_file(&path).unwrap();
+        let loaded = read_str(&path).unwrap().unwrap();
+        assert_eq!(loaded.value, "first");
+        assert!(loaded.from_backup);
+    }
+
+    #[test]
+    fn unparseable_file_keeps_backup() {
+        let path = test_dir("unparseable_file_keeps_backup").join("config.json");
+        write(&path, b"first").unwrap();
+        // The checksum is fine, but the contents can't be used, such as a
+        // config that fails to migrate.
+        write(&path, b"second").unwrap();
+        let parse = |data: &[u8]| {
+            if data == b"second" {
+                Err(anyhow!("unsupported"))
+            } else {
+                parse_str(data)
+            }
+        };
+        let loaded = read(&path, parse).unwrap().unwrap();
+        assert_eq!(loaded.value, "first");
+        assert!(loaded.from_backup);
+
+        // Replacing the file keeps the backup, which is the only good copy.
+        write_keep_backup(&path, b"first, upgraded").unwrap();
+        assert!(!with_suffix(&path, ".tmp").exists());
+        assert_eq!(
+            read(&path, parse).unwrap().unwrap().value,
+            "first, upgraded"
+        );
+        assert_eq!(
+            verify(&fs::read(backup_path(&path)).unwrap()).unwrap(),
+            b"first"
+        );
+
+        // From then on the file is good, so the next write backs it up.
+        write(&path, b"third").unwrap();
+        assert_eq!(
+            verify(&fs::read(backup_path(&path)).unwrap()).unwrap(),
+            b"first, upgraded"
+        );
+    }
+
+    #[test]
+    fn both_corrupt() {
+        let path = test_dir("both_corrupt").join("config.json");
+        fs::write(&path, b"x\ncrc32:00000000\n").unwrap();
+        assert!(read_str(&path).is_err());
+        fs::write(backup_path(&path), b"y\ncrc32:00000000\n").unwrap();
+        let err = read_str(&path).unwrap_err().to_string();
+        assert!(err.contains("config.json:") && err.contains("config.json.bak:"));
+    }
+}
@@ -0,0 +1,118 @@
+// Sunrise and sunset calculations, used to make the light schedule follow the
+// length of the day at a real-world location.
+//
+// This uses the NOAA "sunrise equation" approximation, which is accurate to
+// within a minute or two for non-polar latitudes:
+// https://en.wikipedia.org/wiki/Sunrise_equation
+
+use jiff::civil::Date;
+use jiff::{SignedDuration, Timestamp};
+
+// Julian date of the unix epoch and of the J2000 epoch.
+const JULIAN_UNIX_EPOCH: f64 = 2440587.5;
+const JULIAN_2000: f64 = 2451545.0;
+
+const SECS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+// Solar noon and the amount of daylight for one day at a particular location.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SolarDay {
+    pub noon: Timestamp,
+    // Time between sunrise and sunset. This is zero during polar night and 24
+    // hours during polar day.
+    pub day_length: SignedDuration,
+}
+
+impl SolarDay {
+    pub fn sunrise(&self) -> Timestamp {
+        self.noon - self.day_length / 2
+    }
+
+    pub fn sunset(&self) -> Timestamp {
+        self.noon + self.day_length / 2
+    }
+}
+
+// Computes solar noon and day length on @date at the given location. Latitude
+// is in degrees north and longitude is in degrees east.
+pub fn solar_day(date: Date, latitude: f64, longitude: f64) -> SolarDay {
+    let days_since_epoch = Date::constant(1970, 1, 1).until(date).unwrap().get_days();
+    let julian_day = JULIAN_UNIX_EPOCH + 0.5 + f64::from(days_since_epoch);
+    let n = (julian_day - JULIAN_2000 + 0.0008).round();
+
+    // Mean solar time, solar mean anomaly, equation of the center, and
+    // ecliptic longitude. All angles are in degrees.
+    let mean_solar_time = n - longitude / 360.0;
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.0);
+    let m = mean_anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (mean_anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let l = ecliptic_longitude.to_radians();
+
+    let julian_transit =
+        JULIAN_2000 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * l).sin();
+
+    // Declination of the sun and the hour angle at which its upper edge
+    // crosses the horizon (accounting for atmospheric refraction).
+    let sin_declination = l.sin() * 23.4397_f64.to_radians().sin();
+    let cos_declination = sin_declination.asin().cos();
+    let lat = latitude.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - lat.sin() * sin_declination)
+        / (lat.cos() * cos_declination);
+
+    let day_fraction = if cos_hour_angle <= -1.0 {
+        // The sun never sets.
+        1.0
+    } else if cos_hour_angle >= 1.0 {
+        // The sun never rises.
+        0.0
+    } else {
+        cos_hour_angle.acos().to_degrees() / 180.0
+    };
+
+    let noon_secs = (julian_transit - JULIAN_UNIX_EPOCH) * SECS_PER_DAY;
+    SolarDay {
+        noon: Timestamp::from_second(noon_secs.round() as i64).unwrap(),
+        day_length: SignedDuration::from_secs((day_fraction * SECS_PER_DAY).round() as i64),
+    }
+}
+
+#[cfg(test)]
+mod sun {
+    use super::*;
+    use jiff::tz::TimeZone;
+
+    // Asserts that @actual is within two minutes of the local time @expected.
+    fn assert_near(actual: Timestamp, tz: &str, expected: &str) {
+        let actual = actual.to_zoned(TimeZone::get(tz).unwrap());
+        let expected: jiff::Zoned = format!("{expected}[{tz}]").parse().unwrap();
+        let diff = actual.duration_since(&expected).abs();
+        assert!(
+            diff < SignedDuration::from_mins(2),
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn london_summer_solstice() {
+        let day = solar_day(Date::constant(2025, 6, 21), 51.5074, -0.1278);
+        assert_near(day.sunrise(), "Europe/London", "2025-06-21T04:43");
+        assert_near(day.sunset(), "Europe/London", "2025-06-21T21:21");
+    }
+
+    #[test]
+    fn singapore() {
+        let day = solar_day(Date::constant(2025, 1, 15), 1.3521, 103.8198);
+        assert_near(day.sunrise(), "Asia/Singapore", "2025-01-15T07:13");
+        assert_near(day.sunset(), "Asia/Singapore", "2025-01-15T19:17");
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        // Tromsø, Norway
+        let summer = solar_day(Date::constant(2025, 6, 21), 69.6492, 18.9553);
+        assert_eq!(summer.day_length, SignedDuration::from_hours(24));
+        let winter = solar_day(Date::constant(2025, 12, 21), 69.6492, 18.9553);
+        assert_eq!(winter.day_length, SignedDuration::ZERO);
+    }
+}
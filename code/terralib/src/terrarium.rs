@@ -1,4 +1,7 @@
-use crate::types::{ActuatorValues, SensorValues, TerrariumState};
+use crate::mist_interlock::MistInterlockStatus;
+use crate::reasons::ActuatorReasons;
+use crate::thermal::{ThermalLevel, ThermalStatus};
+use crate::types::{ActuatorValue, ActuatorValues, OverrideHold, SensorValues, TerrariumState};
 
 // Interface for terrarium. One implementation of this is a dummy that allows
 // code to be tested on your pc and one implementation runs only on the esp32
@@ -37,9 +40,9 @@ fn c_to_f(c: f32) -> f32 {
 }
 
 pub fn print_terrarium_state(ts: &TerrariumState) {
-    println!("Lights: {:.1}", ts.actuators.lights);
-    println!("Mist:   {}", ts.actuators.mist);
-    println!("Fans:    {}", ts.actuators.fans);
+    println!("Lights: {:.1} ({})", ts.actuators.lights, ts.reasons.lights);
+    println!("Mist:   {} ({})", ts.actuators.mist, ts.reasons.mist);
+    println!("Fans:    {} ({})", ts.actuators.fans, ts.reasons.fans);
     if let Some(sens) = ts.sensors {
         println!("Temp:   {:.1}C/{:.1}F", sens.temp, c_to_f(sens.temp));
         println!("Humid:  {:.1}%", sens.humid * 100.0);
@@ -51,6 +54,39 @@ pub fn print_terrarium_state(ts: &TerrariumState) {
     } else {
         println!("<cpu temp unknown>");
     }
+    if ts.thermal.level != ThermalLevel::Normal {
+        println!(
+            "Thermal: {:?}, lights at {:.0}%",
+            ts.thermal.level,
+            ts.thermal.light_factor * 100.0
+        );
+    }
+    if let Some(reason) = ts.mist_interlock.blocked {
+        println!("Mist blocked by interlock: {reason:?}");
+    }
+    for trip in &ts.mist_interlock.trips {
+        println!(
+            "Mist interlock tripped at {:.0}: {:?}",
+            trip.timestamp, trip.reason
+        );
+    }
+    if let Some(sampled) = ts.last_sensor_sample {
+        println!("Last sensor sample: {sampled:.0}");
+    }
+    for o in &ts.overrides {
+        let value = match o.value {
+            ActuatorValue::Bool(on) => if on { "on" } else { "off" }.to_string(),
+            ActuatorValue::Float(v) => format!("{v:.2}"),
+        };
+        let until = match (o.hold, o.remaining_secs) {
+            (Some(OverrideHold::NextTransition), _) => "until the next schedule change".to_string(),
+            (Some(OverrideHold::Manual), _) => "until cleared".to_string(),
+            (None, Some(secs)) => format!("for {secs}s more"),
+            (None, None) => String::new(),
+        };
+        let sequence = if o.from_sequence { " (sequence)" } else { "" };
+        println!("Override: {:?} {value} {until}{sequence}", o.actuator);
+    }
 }
 
 pub fn get_terrarium_state(t: &mut dyn Terrarium) -> TerrariumState {
@@ -62,6 +98,11 @@ pub fn get_terrarium_state(t: &mut dyn Terrarium) -> TerrariumState {
         },
         sensors: t.read_sensors(),
         cpu_temp: t.read_cpu_temp(),
+        last_sensor_sample: None,
+        thermal: ThermalStatus::default(),
+        mist_interlock: MistInterlockStatus::default(),
+        overrides: vec![],
+        reasons: ActuatorReasons::default(),
     }
 }
 
@@ -80,6 +121,11 @@ impl FakeTerrarium {
                     humid: 0.8,
                 }),
                 cpu_temp: None,
+                last_sensor_sample: None,
+                thermal: ThermalStatus::default(),
+                mist_interlock: MistInterlockStatus::default(),
+                overrides: vec![],
+                reasons: ActuatorReasons::default(),
             },
         }
     }
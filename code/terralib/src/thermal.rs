@@ -0,0 +1,305 @@
+// Protects the terrarium from overheating, mostly from its own LEDs.
+//
+// Above a warning temperature the lights are dimmed, more the closer it gets
+// to the critical temperature, and the fans are run to move the hot air out.
+// At the critical temperature the lights are turned off, and they stay off
+// until everything is back below the warning temperature. The fans keep
+// running until everything is HYSTERESIS_C below the warning temperature, so
+// that a temperature hovering around it doesn't flip the protection on and
+// off. Both the terrarium's air temperature and the esp32's own temperature
+// are checked, each against its own thresholds.
+
+use crate::config::validation::{ErrorCode, ValidationReport, field};
+use crate::types::ActuatorValues;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// How far below its warning threshold each temperature has to be before the
+// level goes back to normal.
+const HYSTERESIS_C: f32 = 1.0;
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Copy, Debug)]
+pub struct TempThresholds {
+    // In degrees Celsius.
+    pub warn_c: f32,
+    pub critical_c: f32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+#[serde(default)]
+pub struct ThermalLimits {
+    // Air temperature read by the humidity sensor.
+    pub air: TempThresholds,
+    // Temperature of the esp32 chip, which sits next to the LED driver.
+    pub cpu: TempThresholds,
+}
+
+impl Default for ThermalLimits {
+    fn default() -> Self {
+        Self {
+            air: TempThresholds {
+                warn_c: 32.0,
+                critical_c: 38.0,
+            },
+            cpu: TempThresholds {
+                warn_c: 70.0,
+                critical_c: 85.0,
+            },
+        }
+    }
+}
+
+impl ThermalLimits {
+    pub(crate) fn validate_at(&self, path: &str, report: &mut ValidationReport) {
+        for (name, thresholds) in [("air", &self.air), ("cpu", &self.cpu)] {
+            if !thresholds.warn_c.is_finite()
+                || !thresholds.critical_c.is_finite()
+                || thresholds.warn_c >= thresholds.critical_c
+            {
+                report.add(
+                    &field(&field(path, name), "warn_c"),
+                    ErrorCode::OutOfRange,
+                    format!(
+                        "warn_c must be less than critical_c ({}), got {}",
+                        thresholds.critical_c, thresholds.warn_c
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThermalLevel {
+    #[default]
+    Normal,
+    // Lights are dimmed and the fans are on.
+    Derating,
+    // Lights are off and the fans are on.
+    Critical,
+}
+
+// Reported in TerrariumState.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Copy, Debug)]
+pub struct ThermalStatus {
+    pub level: ThermalLevel,
+    // What the lights are multiplied by, from 0 to 1.
+    pub light_factor: f32,
+}
+
+impl Default for ThermalStatus {
+    fn default() -> Self {
+        Self {
+            level: ThermalLevel::Normal,
+            light_factor: 1.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ThermalProtection {
+    status: ThermalStatus,
+    // The air and cpu temperatures used by the last update().
+    last_temps: [Option<f32>; 2],
+}
+
+impl ThermalProtection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> ThermalStatus {
+        self.status
+    }
+
+    // Updates the status from the latest temperatures, which are None if they
+    // couldn't be read. Returns true if the level changed.
+    pub fn update(
+        &mut self,
+        limits: &ThermalLimits,
+        air_temp: Option<f32>,
+        cpu_temp: Option<f32>,
+    ) -> bool {
+        let old_level = self.status.level;
+        // While the protection is active, a temperature that can't be read is
+        // assumed not to have changed, so that losing the sensor that tripped
+        // it doesn't turn the lights back on.
+        let mut temps = [air_temp, cpu_temp];
+        if old_level != ThermalLevel::Normal {
+            for (temp, last) in temps.iter_mut().zip(self.last_temps) {
+                *temp = temp.or(last);
+            }
+        }
+        self.last_temps = temps;
+
+        let readings = [(temps[0], &limits.air), (temps[1], &limits.cpu)];
+        let readings: Vec<_> = readings
+            .iter()
+            .filter_map(|(temp, thresholds)| temp.map(|temp| (temp, *thresholds)))
+            .collect();
+
+        // How far each temperature is from its warning (0) to its critical
+        // (1) threshold. Only the hottest one matters.
+        let heat = readings
+            .iter()
+            .map(|(temp, thresholds)| {
+                (temp - thresholds.warn_c) / (thresholds.critical_c - thresholds.warn_c)
+            })
+            .fold(f32::NEG_INFINITY, f32::max);
+        let cooled = readings
+            .iter()
+            .all(|(temp, thresholds)| *temp <= thresholds.warn_c - HYSTERESIS_C);
+
+        self.status = if heat >= 1.0 || (old_level == ThermalLevel::Critical && heat > 0.0) {
+            ThermalStatus {
+                level: ThermalLevel::Critical,
+                light_factor: 0.0,
+            }
+        } else if heat > 0.0 {
+            ThermalStatus {
+                level: ThermalLevel::Derating,
+                light_factor: 1.0 - heat,
+            }
+        } else if old_level != ThermalLevel::Normal && !cooled {
+            // Below the warning threshold but not by enough yet. The lights
+            // are back to full but the fans keep running.
+            ThermalStatus {
+                level: ThermalLevel::Derating,
+                light_factor: 1.0,
+            }
+        } else {
+            ThermalStatus::default()
+        };
+        self.status.level != old_level
+    }
+
+    // Dims the lights and runs the fans as needed. This takes priority over
+    // the schedule and overrides.
+    pub fn apply(&self, values: &mut ActuatorValues) {
+        if self.status.level != ThermalLevel::Normal {
+            // Round like the schedule does, so that small changes in
+            // temperature don't re-fade the lights every time.
+            values.lights = (values.lights * self.status.light_factor * 1000.0).round() / 1000.0;
+            values.fans = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod thermal {
+    use super::*;
+
+    fn lights_at(protection: &ThermalProtection) -> f32 {
+        let mut values = ActuatorValues {
+            lights: 0.8,
+            fans: false,
+            mist: false,
+        };
+        protection.apply(&mut values);
+        values.lights
+    }
+
+    #[test]
+    fn derating() {
+        let limits = ThermalLimits::default();
+        let mut protection = ThermalProtection::new();
+        assert!(!protection.update(&limits, Some(25.0), Some(50.0)));
+        assert_eq!(lights_at(&protection), 0.8);
+
+        // Halfway from warn to critical.
+        assert!(protection.update(&limits, Some(35.0), Some(50.0)));
+        assert_eq!(protection.status().level, ThermalLevel::Derating);
+        assert_eq!(lights_at(&protection), 0.4);
+        let mut values = ActuatorValues::default();
+        protection.apply(&mut values);
+        assert!(values.fans);
+
+        // The hotter sensor, relative to its thresholds, wins.
+        assert!(!protection.update(&limits, Some(33.5), Some(81.25)));
+        assert_eq!(lights_at(&protection), 0.2);
+
+        assert!(protection.update(&limits, Some(25.0), Some(50.0)));
+        assert_eq!(protection.status(), ThermalStatus::default());
+    }
+
+    #[test]
+    fn hysteresis() {
+        let limits = ThermalLimits::default();
+        let mut protection = ThermalProtection::new();
+        assert!(protection.update(&limits, Some(32.5), None));
+        assert_eq!(protection.status().level, ThermalLevel::Derating);
+
+        // Just below the warning threshold, the lights are back to full but
+        // the fans keep running.
+        assert!(!protection.update(&limits, Some(31.5), None));
+        assert_eq!(protection.status().level, ThermalLevel::Derating);
+        assert_eq!(lights_at(&protection), 0.8);
+        let mut values = ActuatorValues::default();
+        protection.apply(&mut values);
+        assert!(values.fans);
+
+        assert!(!protection.update(&limits, Some(32.5), None));
+        assert!(protection.update(&limits, Some(31.0), None));
+        assert_eq!(protection.status(), ThermalStatus::default());
+    }
+
+    #[test]
+    fn missing_reading_holds_level() {
+        let limits = ThermalLimits::default();
+        let mut protection = ThermalProtection::new();
+        assert!(protection.update(&limits, Some(25.0), Some(90.0)));
+        assert_eq!(protection.status().level, ThermalLevel::Critical);
+
+        // The cpu temperature tripped it, so losing it doesn't turn the lights
+        // back on, whether or not the air temperature can be read.
+        assert!(!protection.update(&limits, Some(25.0), None));
+        assert_eq!(protection.status().level, ThermalLevel::Critical);
+        assert!(!protection.update(&limits, None, None));
+        assert_eq!(protection.status().level, ThermalLevel::Critical);
+
+        assert!(protection.update(&limits, None, Some(60.0)));
+        assert_eq!(protection.status(), ThermalStatus::default());
+    }
+
+    #[test]
+    fn critical() {
+        let limits = ThermalLimits::default();
+        let mut protection = ThermalProtection::new();
+        assert!(protection.update(&limits, Some(38.0), None));
+        assert_eq!(protection.status().level, ThermalLevel::Critical);
+        assert_eq!(lights_at(&protection), 0.0);
+
+        // The lights stay off until it's cooled down below the warning
+        // threshold.
+        assert!(!protection.update(&limits, Some(33.0), None));
+        assert_eq!(lights_at(&protection), 0.0);
+        assert!(protection.update(&limits, Some(31.0), None));
+        assert_eq!(protection.status().level, ThermalLevel::Normal);
+    }
+
+    #[test]
+    fn no_readings() {
+        let mut protection = ThermalProtection::new();
+        assert!(!protection.update(&ThermalLimits::default(), None, None));
+        assert_eq!(protection.status(), ThermalStatus::default());
+    }
+
+    #[test]
+    fn validate() {
+        let mut report = ValidationReport::default();
+        ThermalLimits::default().validate_at("thermal", &mut report);
+        assert!(report.is_empty());
+
+        let limits = ThermalLimits {
+            cpu: TempThresholds {
+                warn_c: 90.0,
+                critical_c: 85.0,
+            },
+            ..ThermalLimits::default()
+        };
+        limits.validate_at("thermal", &mut report);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, "thermal.cpu.warn_c");
+    }
+}
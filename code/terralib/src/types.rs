@@ -1,7 +1,14 @@
+use crate::mist_interlock::MistInterlockStatus;
+use crate::reasons::ActuatorReasons;
+use crate::thermal::ThermalStatus;
+use anyhow::anyhow;
+use schemars::JsonSchema;
 use serde;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Debug, Copy, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Actuator {
     Lights,
@@ -9,7 +16,7 @@ pub enum Actuator {
     Mist,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Clone, Copy, Serialize, Deserialize, JsonSchema, Default)]
 pub struct SensorValues {
     // Temperature in degrees Celsius
     pub temp: f32,
@@ -17,22 +24,47 @@ pub struct SensorValues {
     pub humid: f32,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Default)]
+#[derive(Clone, Copy, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Default)]
 pub struct ActuatorValues {
     pub lights: f32,
     pub mist: bool,
     pub fans: bool,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+impl ActuatorValues {
+    // Whether @actuator is on at all. The lights count as on when dimmed.
+    pub fn is_on(&self, actuator: Actuator) -> bool {
+        match actuator {
+            Actuator::Lights => self.lights > 0.0,
+            Actuator::Fans => self.fans,
+            Actuator::Mist => self.mist,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TerrariumState {
     pub actuators: ActuatorValues,
     // TODO: use Result for the below two?
     pub sensors: Option<SensorValues>,
     pub cpu_temp: Option<f32>,
+    // When the fans were last run to take a humidity sample for auto-mist, if
+    // sampling is configured.
+    #[serde(default)]
+    pub last_sensor_sample: Option<jiff::Timestamp>,
+    #[serde(default)]
+    pub thermal: ThermalStatus,
+    #[serde(default)]
+    pub mist_interlock: MistInterlockStatus,
+    // Overrides currently in effect, which take priority over the schedule.
+    #[serde(default)]
+    pub overrides: Vec<ActiveOverride>,
+    // Why each actuator has the value it does.
+    #[serde(default)]
+    pub reasons: ActuatorReasons,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, Copy, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug, Copy, Clone)]
 #[serde(untagged)]
 pub enum ActuatorValue {
     Bool(bool),
@@ -41,14 +73,364 @@ pub enum ActuatorValue {
 
 // Represents a temporary override of a single actuator. For example "set the
 // lights to 0.75 for 60 seconds".
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug, Clone)]
 pub struct ActuatorOverride {
     pub actuator: Actuator,
     pub value: ActuatorValue,
+    // Ignored if @hold is set.
     pub duration_secs: u32,
+    // Holds the override for longer than a fixed duration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<OverrideHold>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Eq, Debug, Copy, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideHold {
+    // Until the schedule next turns the actuator on or off, such as keeping
+    // the lights off until they're next scheduled to turn off anyway.
+    NextTransition,
+    // Until the override is cleared.
+    Manual,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
 pub struct ActuatorOverrideSet {
     pub updates: Vec<ActuatorOverride>,
 }
+
+// An override that's in effect, as reported in TerrariumState.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug, Clone)]
+pub struct ActiveOverride {
+    pub actuator: Actuator,
+    pub value: ActuatorValue,
+    // Time left until the override ends, or None if it's held.
+    pub remaining_secs: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hold: Option<OverrideHold>,
+    // Whether the override was applied by the running OverrideSequence.
+    #[serde(default)]
+    pub from_sequence: bool,
+}
+
+// Overrides applied one step after another, such as "mist for 30 seconds, then
+// run the fans for 2 minutes". Each step starts once the longest override of
+// the step before it is over, plus its own @delay_secs. The first step's delay
+// counts from when the sequence is received.
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug, Clone)]
+pub struct OverrideSequence {
+    pub steps: Vec<SequenceStep>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug, Clone)]
+pub struct SequenceStep {
+    #[serde(default)]
+    pub delay_secs: u32,
+    pub updates: Vec<ActuatorOverride>,
+}
+
+// Validated value types.
+//
+// These check their values when they're created, including when deserialized,
+// so a config containing an out of range value can't be loaded at all, rather
+// than relying on it being validated separately.
+
+// Declares a newtype wrapping an f32 that must be between 0.0 and 1.0.
+macro_rules! unit_interval_type {
+    ($name:ident, $what:literal) => {
+        #[derive(Serialize, Deserialize, PartialEq, PartialOrd, Debug, Copy, Clone, Default)]
+        #[serde(try_from = "f32", into = "f32")]
+        pub struct $name(f32);
+
+        impl $name {
+            pub fn new(value: f32) -> anyhow::Result<Self> {
+                if !(0.0..=1.0).contains(&value) {
+                    return Err(anyhow!(
+                        concat!($what, " must be between 0.0 and 1.0, got {}"),
+                        value
+                    ));
+                }
+                Ok(Self(value))
+            }
+
+            pub fn get(self) -> f32 {
+                self.0
+            }
+        }
+
+        impl TryFrom<f32> for $name {
+            type Error = anyhow::Error;
+
+            fn try_from(value: f32) -> anyhow::Result<Self> {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for f32 {
+            fn from(value: $name) -> f32 {
+                value.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl JsonSchema for $name {
+            fn schema_name() -> Cow<'static, str> {
+                stringify!($name).into()
+            }
+
+            fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+                schemars::json_schema!({
+                    "type": "number",
+                    "description": concat!($what, ", from 0.0 to 1.0."),
+                    "minimum": 0.0,
+                    "maximum": 1.0,
+                })
+            }
+        }
+    };
+}
+
+unit_interval_type!(Intensity, "light intensity");
+unit_interval_type!(RelativeHumidity, "relative humidity");
+
+// A number of seconds, at most MAX.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Default)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct BoundedSecs<const MAX: u32>(u32);
+
+impl<const MAX: u32> BoundedSecs<MAX> {
+    pub const MAX: u32 = MAX;
+
+    pub fn new(secs: u32) -> anyhow::Result<Self> {
+        if secs > MAX {
+            return Err(anyhow!("duration can be at most {MAX} seconds, got {secs}"));
+        }
+        Ok(Self(secs))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl<const MAX: u32> TryFrom<u32> for BoundedSecs<MAX> {
+    type Error = anyhow::Error;
+
+    fn try_from(secs: u32) -> anyhow::Result<Self> {
+        Self::new(secs)
+    }
+}
+
+impl<const MAX: u32> From<BoundedSecs<MAX>> for u32 {
+    fn from(secs: BoundedSecs<MAX>) -> u32 {
+        secs.0
+    }
+}
+
+impl<const MAX: u32> fmt::Display for BoundedSecs<MAX> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<const MAX: u32> JsonSchema for BoundedSecs<MAX> {
+    fn schema_name() -> Cow<'static, str> {
+        format!("BoundedSecs_{MAX}").into()
+    }
+
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "integer",
+            "description": "Duration in seconds.",
+            "minimum": 0,
+            "maximum": MAX,
+        })
+    }
+}
+
+// The number of hours between repeats of a scheduled event. It can't be 0,
+// which would repeat the event forever.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct RepeatHours(u32);
+
+impl RepeatHours {
+    pub fn new(hours: u32) -> anyhow::Result<Self> {
+        if hours == 0 {
+            return Err(anyhow!("repeat hours must be at least 1"));
+        }
+        Ok(Self(hours))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for RepeatHours {
+    type Error = anyhow::Error;
+
+    fn try_from(hours: u32) -> anyhow::Result<Self> {
+        Self::new(hours)
+    }
+}
+
+impl From<RepeatHours> for u32 {
+    fn from(hours: RepeatHours) -> u32 {
+        hours.0
+    }
+}
+
+impl fmt::Display for RepeatHours {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl JsonSchema for RepeatHours {
+    fn schema_name() -> Cow<'static, str> {
+        "RepeatHours".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "integer",
+            "description": "Hours between repeats.",
+            "minimum": 1,
+        })
+    }
+}
+
+// A name that can be used as the terrarium's hostname on the local network: 1
+// to 30 letters, digits, and hyphens, not starting or ending with a hyphen.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hostname(String);
+
+impl Hostname {
+    pub const MAX_LEN: usize = 30;
+
+    pub fn new(name: String) -> anyhow::Result<Self> {
+        if name.is_empty() {
+            return Err(anyhow!("name can not be empty"));
+        }
+        if name.len() > Self::MAX_LEN {
+            return Err(anyhow!(
+                "name can be at most {} characters, got '{name}'",
+                Self::MAX_LEN
+            ));
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            || name.starts_with('-')
+            || name.ends_with('-')
+        {
+            return Err(anyhow!(
+                "name can only contain letters, numbers, and '-', and can't start or end with '-', got '{name}'"
+            ));
+        }
+        Ok(Self(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for Hostname {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> anyhow::Result<Self> {
+        Self::new(name)
+    }
+}
+
+impl TryFrom<&str> for Hostname {
+    type Error = anyhow::Error;
+
+    fn try_from(name: &str) -> anyhow::Result<Self> {
+        Self::new(name.to_string())
+    }
+}
+
+impl From<Hostname> for String {
+    fn from(name: Hostname) -> String {
+        name.0
+    }
+}
+
+impl fmt::Display for Hostname {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl JsonSchema for Hostname {
+    fn schema_name() -> Cow<'static, str> {
+        "Hostname".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "type": "string",
+            "description": "Hostname on the local network.",
+            "pattern": "^[A-Za-z0-9]([A-Za-z0-9-]{0,28}[A-Za-z0-9])?$",
+        })
+    }
+}
+
+#[cfg(test)]
+mod types {
+    use super::*;
+
+    #[test]
+    fn unit_interval() {
+        assert_eq!(Intensity::new(0.5).unwrap().get(), 0.5);
+        assert!(Intensity::new(1.5).is_err());
+        assert!(RelativeHumidity::new(-0.1).is_err());
+        assert!(RelativeHumidity::new(f32::NAN).is_err());
+
+        let parsed: Intensity = serde_json::from_str("0.25").unwrap();
+        assert_eq!(parsed.get(), 0.25);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), "0.25");
+        let err = serde_json::from_str::<Intensity>("1.5").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("light intensity must be between")
+        );
+    }
+
+    #[test]
+    fn bounded_secs() {
+        type Secs = BoundedSecs<60>;
+        assert_eq!(Secs::new(60).unwrap().get(), 60);
+        assert!(Secs::new(61).is_err());
+        assert!(serde_json::from_str::<Secs>("30").is_ok());
+        assert!(serde_json::from_str::<Secs>("61").is_err());
+        assert!(serde_json::from_str::<Secs>("-1").is_err());
+    }
+
+    #[test]
+    fn hostname() {
+        assert!(Hostname::try_from("oasis").is_ok());
+        assert!(Hostname::try_from("oasis-2").is_ok());
+        assert!(Hostname::try_from("").is_err());
+        assert!(Hostname::try_from("-oasis").is_err());
+        assert!(Hostname::try_from("oasis-").is_err());
+        assert!(Hostname::try_from("my oasis").is_err());
+        assert!(Hostname::try_from("x".repeat(Hostname::MAX_LEN)).is_ok());
+        assert!(Hostname::try_from("x".repeat(Hostname::MAX_LEN + 1)).is_err());
+
+        let parsed: Hostname = serde_json::from_str(r#""oasis""#).unwrap();
+        assert_eq!(parsed.as_str(), "oasis");
+        assert!(serde_json::from_str::<Hostname>(r#""a.b""#).is_err());
+    }
+}